@@ -0,0 +1,64 @@
+use crate::atlas_vm::{debug::SourceLocation, instruction::Instruction};
+
+/// Instructions being generated, each one alongside the location of the code it comes from
+#[derive(Debug, Default)]
+pub(crate) struct Bytecode {
+    instructions: Vec<Instruction>,
+    locations: Vec<Option<SourceLocation>>,
+}
+
+impl Bytecode {
+    pub fn push(&mut self, instr: Instruction) {
+        self.instructions.push(instr);
+        self.locations.push(None);
+    }
+
+    pub fn append(&mut self, other: &mut Bytecode) {
+        self.instructions.append(&mut other.instructions);
+        self.locations.append(&mut other.locations);
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn last(&self) -> Option<&Instruction> {
+        self.instructions.last()
+    }
+
+    /// Whether running the code can go past its last instruction, either because it isn't a
+    /// `Return` or because a jump lands right after it, e.g. at the end of an `if` whose
+    /// branches both return
+    pub fn falls_through(&self) -> bool {
+        let end = Some(self.instructions.len());
+        self.last() != Some(&Instruction::Return)
+            || self
+                .instructions
+                .iter()
+                .enumerate()
+                .any(|(index, instr)| match instr {
+                    Instruction::Jmp { pos } => index.checked_add_signed(*pos) == end,
+                    Instruction::JmpZ { pos } => (index + 1).checked_add_signed(*pos) == end,
+                    Instruction::JmpTable { targets } => targets
+                        .iter()
+                        .any(|pos| index.checked_add_signed(*pos) == end),
+                    _ => false,
+                })
+    }
+
+    /// Gives `location` to the instructions from `start` on that don't have one yet.
+    ///
+    /// An expression is located after its operands, so each instruction ends up with the
+    /// innermost expression it was generated for.
+    pub fn locate(&mut self, start: usize, location: Option<SourceLocation>) {
+        for slot in &mut self.locations[start..] {
+            if slot.is_none() {
+                *slot = location;
+            }
+        }
+    }
+
+    pub fn into_parts(self) -> (Vec<Instruction>, Vec<Option<SourceLocation>>) {
+        (self.instructions, self.locations)
+    }
+}
@@ -1,20 +1,30 @@
 /// Contains the definition of the CodeGenArena
 pub mod arena;
+/// Instructions alongside their source location
+mod bytecode;
 
 use crate::{
     atlas_hir::{
         error::{HirResult, UnsupportedExpr, UnsupportedStatement},
-        expr::HirExpr,
-        signature::HirFunctionParameterSignature,
+        expr::{
+            HirDispatch, HirExpr, HirLambdaBody, HirLambdaExpr, HirMatchArmBody, HirMatchExpr,
+            HirPattern,
+        },
         stmt::{HirBlock, HirStatement},
         ty::HirTy,
         HirModule,
     },
-    atlas_vm::instruction::{ImportedLibrary, Instruction, Label, Program},
+    atlas_vm::{
+        debug::{DebugInfo, SourceFile, SourceLocation},
+        instruction::{ImportedLibrary, Instruction, Label, Program, VTable},
+    },
 };
 
+use std::cell::{Cell, RefCell};
+
 use arena::CodeGenArena;
-use atlas_core::prelude::Spanned;
+use atlas_core::prelude::{Span, Spanned};
+use bytecode::Bytecode;
 use miette::{SourceOffset, SourceSpan};
 
 /// Result of codegen
@@ -29,6 +39,20 @@ where
     program: Program<'gen>,
     arena: CodeGenArena<'gen>,
     current_pos: usize,
+    /// Bodies of the lambdas met so far, they get their own label once every function is generated
+    lambdas: RefCell<Vec<(String, usize, Bytecode)>>,
+    lambda_count: Cell<usize>,
+    /// `(class, trait)` pairs met in upcasts, the position of a pair is the id of its vtable
+    vtables: RefCell<Vec<(&'hir str, &'hir str)>>,
+    /// Locals in scope in the function being generated, the innermost last. The position of a
+    /// local is its slot in the frame, the slot is reused once its scope ends
+    locals: RefCell<Vec<&'hir str>>,
+    /// Most locals the function being generated had in scope at once, the size of its frame
+    frame_size: Cell<usize>,
+    /// Locals of the entry point, the ones given before compiling are already on the stack
+    entry_locals: Vec<&'hir str>,
+    /// Index in `DebugInfo::files` of the file of the function being generated
+    file: Cell<Option<usize>>,
     src: String,
 }
 
@@ -42,33 +66,92 @@ where
             hir,
             program: Program::new(),
             current_pos: 0,
+            lambdas: RefCell::new(Vec::new()),
+            lambda_count: Cell::new(0),
+            vtables: RefCell::new(Vec::new()),
+            locals: RefCell::new(Vec::new()),
+            frame_size: Cell::new(0),
+            entry_locals: Vec::new(),
+            file: Cell::new(None),
             arena,
             src,
         }
     }
+    /// Makes the entry point start with the locals left on the stack by a previous program
+    pub(crate) fn with_entry_locals(mut self, locals: Vec<&'hir str>) -> Self {
+        self.entry_locals = locals;
+        self
+    }
+    /// Locals of the entry point still in scope at its end, in slot order
+    pub(crate) fn entry_locals(&self) -> &[&'hir str] {
+        &self.entry_locals
+    }
+    /// Starts the vtables with the `(class, trait)` pairs of a previous program, so the trait
+    /// objects it made keep the id of their vtable
+    pub(crate) fn with_vtables(self, vtables: Vec<(&'hir str, &'hir str)>) -> Self {
+        self.vtables.replace(vtables);
+        self
+    }
     /// Take the HIR and convert it to a VM representation
-    pub(crate) fn compile(&mut self) -> CodegenResult<Program> {
+    pub(crate) fn compile(&mut self) -> CodegenResult<Program<'gen>> {
         let mut labels: Vec<Label> = Vec::new();
+        let mut files = Vec::new();
+        let mut locations = Vec::new();
         for func in self.hir.body.functions.clone() {
-            let mut bytecode = Vec::new();
-
-            let params = func.1.signature.params.clone();
-            self.generate_bytecode_args(params, &mut bytecode)?;
-            self.generate_bytecode_block(&func.1.body, &mut bytecode, self.src.clone())?;
+            let file = self.hir.body.file_of(func.0);
+            self.file
+                .set(file.map(|file| Self::file_index(&mut files, file.path, file.src)));
+            //The arguments are already on the stack, they are the first locals of the frame
+            let mut locals = if func.0 == "main" {
+                self.entry_locals.clone()
+            } else {
+                func.1.signature.params.iter().map(|p| p.name).collect()
+            };
+            let (mut bytecode, frame_size) = self
+                .generate_bytecode_frame(&mut locals, |bytecode| {
+                    self.generate_bytecode_block(&func.1.body, bytecode, self.src.clone())
+                })?;
+            if func.0 == "main" {
+                self.entry_locals = locals.clone();
+            }
 
             if func.0 == "main" {
+                //The result of the program is on top of the stack, above the locals of `main`
+                if bytecode.falls_through() {
+                    bytecode.push(Instruction::PushUnit);
+                }
                 bytecode.push(Instruction::Halt);
+            } else if bytecode.falls_through() {
+                //Functions returning `unit` may not end with a return statement
+                bytecode.push(Instruction::PushUnit);
+                bytecode.push(Instruction::Return);
             }
             let len = bytecode.len();
+            let (bytecode, mut located) = bytecode.into_parts();
+            locations.append(&mut located);
 
             labels.push(Label {
                 name: func.0.to_string(),
                 position: self.current_pos,
+                locals: frame_size,
                 body: self.arena.alloc_vec(bytecode),
             });
 
             self.current_pos += len;
         }
+        for (name, locals, bytecode) in self.lambdas.take() {
+            let len = bytecode.len();
+            let (bytecode, mut located) = bytecode.into_parts();
+            locations.append(&mut located);
+            labels.push(Label {
+                name,
+                position: self.current_pos,
+                locals,
+                body: self.arena.alloc_vec(bytecode),
+            });
+            self.current_pos += len;
+        }
+        self.program.debug = Some(self.arena.alloc(DebugInfo { files, locations }));
         self.program.entry_point = "main";
         self.program.labels = self.arena.alloc_vec(labels);
         let libraries = self
@@ -78,17 +161,35 @@ where
             .iter()
             .map(|l| ImportedLibrary {
                 name: l.path.to_string(),
-                is_std: true,
+                is_std: l.path.starts_with("std/"),
             })
             .collect::<Vec<_>>();
         self.program.libraries = self.arena.alloc_vec(libraries);
+        let vtables = self
+            .vtables
+            .take()
+            .into_iter()
+            .map(|(class, trait_name)| {
+                let class_sig = self.hir.signature.classes[class];
+                VTable {
+                    class: class.to_string(),
+                    trait_name: trait_name.to_string(),
+                    methods: self.hir.signature.traits[trait_name]
+                        .methods
+                        .iter()
+                        .map(|m| class_sig.method(m.name).unwrap().function.to_string())
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+        self.program.vtables = self.arena.alloc_vec(vtables);
         Ok(self.program)
     }
 
     fn generate_bytecode_block(
         &self,
         block: &HirBlock<'hir>,
-        bytecode: &mut Vec<Instruction>,
+        bytecode: &mut Bytecode,
         src: String,
     ) -> HirResult<()> {
         for stmt in &block.statements {
@@ -100,26 +201,31 @@ where
     fn generate_bytecode_stmt(
         &self,
         stmt: &HirStatement<'hir>,
-        bytecode: &mut Vec<Instruction>,
+        bytecode: &mut Bytecode,
         src: String,
     ) -> HirResult<()> {
+        let start = bytecode.len();
         match stmt {
             HirStatement::Return(e) => {
-                self.generate_bytecode_expr(e.value, bytecode, src)?;
+                self.generate_bytecode_expr(&e.value, bytecode, src)?;
                 bytecode.push(Instruction::Return);
             }
             HirStatement::IfElse(i) => {
-                self.generate_bytecode_expr(i.condition, bytecode, src.clone())?;
-                let mut then_body = Vec::new();
-                self.generate_bytecode_block(i.then_branch, &mut then_body, src.clone())?;
+                self.generate_bytecode_expr(&i.condition, bytecode, src.clone())?;
+                let mut then_body = Bytecode::default();
+                self.generate_bytecode_scope(|| {
+                    self.generate_bytecode_block(&i.then_branch, &mut then_body, src.clone())
+                })?;
 
                 bytecode.push(Instruction::JmpZ {
                     pos: (then_body.len() + if i.else_branch.is_some() { 1 } else { 0 }) as isize,
                 });
                 bytecode.append(&mut then_body);
-                if let Some(e) = i.else_branch {
-                    let mut else_body = Vec::new();
-                    self.generate_bytecode_block(e, &mut else_body, src)?;
+                if let Some(e) = &i.else_branch {
+                    let mut else_body = Bytecode::default();
+                    self.generate_bytecode_scope(|| {
+                        self.generate_bytecode_block(e, &mut else_body, src)
+                    })?;
 
                     bytecode.push(Instruction::Jmp {
                         pos: (else_body.len() + 1) as isize,
@@ -129,10 +235,12 @@ where
             }
             HirStatement::While(w) => {
                 let start = bytecode.len() as isize;
-                self.generate_bytecode_expr(w.condition, bytecode, src.clone())?;
-                let mut body = Vec::new();
+                self.generate_bytecode_expr(&w.condition, bytecode, src.clone())?;
+                let mut body = Bytecode::default();
 
-                self.generate_bytecode_block(w.body, &mut body, src)?;
+                self.generate_bytecode_scope(|| {
+                    self.generate_bytecode_block(&w.body, &mut body, src)
+                })?;
                 //If the condition is false jump to the end of the loop
                 bytecode.push(Instruction::JmpZ {
                     pos: (body.len() + 1) as isize,
@@ -143,33 +251,18 @@ where
                     pos: start - bytecode.len() as isize,
                 });
             }
-            HirStatement::Let(l) => {
-                let mut value = Vec::new();
-                self.generate_bytecode_expr(l.value, &mut value, src)?;
-                match l.ty {
-                    HirTy::Int64(_) => {
-                        value.push(Instruction::StoreI64 {
-                            var_name: l.name.to_string(),
-                        });
-                    }
-                    HirTy::Float64(_) => {
-                        value.push(Instruction::StoreF64 {
-                            var_name: l.name.to_string(),
-                        });
-                    }
-                    HirTy::UInt64(_) => {
-                        value.push(Instruction::StoreU64 {
-                            var_name: l.name.to_string(),
-                        });
-                    }
-                    HirTy::Boolean(_) => value.push(Instruction::StoreBool {
-                        var_name: l.name.to_string(),
-                    }),
-                    _ => unimplemented!("Unsupported type for now"),
+            HirStatement::Let(l) | HirStatement::Const(l) => {
+                self.generate_bytecode_expr(&l.value, bytecode, src)?;
+                //Declared once its value is computed, so `let x = x + 1;` reads the outer `x`
+                bytecode.push(Instruction::StoreLocal(self.declare_local(l.name)));
+            }
+            HirStatement::Expr(e) => {
+                self.generate_bytecode_expr(&e.expr, bytecode, src)?;
+                //Assignments don't leave anything on the stack, everything else does
+                if !matches!(e.expr, HirExpr::Assign(_)) {
+                    bytecode.push(Instruction::Pop);
                 }
-                bytecode.append(&mut value);
             }
-            HirStatement::Expr(e) => self.generate_bytecode_expr(e.expr, bytecode, src)?,
             _ => {
                 return Err(crate::atlas_hir::error::HirError::UnsupportedStatement(
                     UnsupportedStatement {
@@ -183,44 +276,37 @@ where
                 ))
             }
         }
+        bytecode.locate(start, self.location(stmt.span()));
         Ok(())
     }
 
     fn generate_bytecode_expr(
         &self,
         expr: &HirExpr<'hir>,
-        bytecode: &mut Vec<Instruction>,
+        bytecode: &mut Bytecode,
         src: String,
     ) -> HirResult<()> {
+        let start = bytecode.len();
         match expr {
             HirExpr::Assign(a) => {
                 let lhs = a.lhs.as_ref();
                 match lhs {
                     HirExpr::Ident(i) => {
                         self.generate_bytecode_expr(&a.rhs, bytecode, src)?;
-                        match i.ty {
-                            HirTy::Int64(_) => {
-                                bytecode.push(Instruction::StoreI64 {
-                                    var_name: i.name.to_string(),
-                                });
-                            }
-                            HirTy::Float64(_) => {
-                                bytecode.push(Instruction::StoreF64 {
-                                    var_name: i.name.to_string(),
-                                });
-                            }
-                            HirTy::UInt64(_) => {
-                                bytecode.push(Instruction::StoreU64 {
-                                    var_name: i.name.to_string(),
-                                });
-                            }
-                            HirTy::Uninitialized(_) => {
-                                bytecode.push(Instruction::StoreI64 {
-                                    var_name: i.name.to_string(),
-                                });
-                            }
-                            _ => unimplemented!("Unsupported type for now {:?}", i.ty),
-                        }
+                        bytecode.push(Instruction::StoreLocal(self.local_slot(i.name)));
+                    }
+                    HirExpr::Indexing(i) => {
+                        self.generate_bytecode_expr(&i.target, bytecode, src.clone())?;
+                        self.generate_bytecode_expr(&i.index, bytecode, src.clone())?;
+                        self.generate_bytecode_expr(&a.rhs, bytecode, src)?;
+                        bytecode.push(Instruction::ListStore);
+                    }
+                    HirExpr::FieldAccess(f) => {
+                        self.generate_bytecode_expr(&f.target, bytecode, src.clone())?;
+                        self.generate_bytecode_expr(&a.rhs, bytecode, src)?;
+                        bytecode.push(Instruction::SetField {
+                            field: self.field_index(&f.target, f.field),
+                        });
                     }
                     _ => {
                         return Err(crate::atlas_hir::error::HirError::UnsupportedExpr(
@@ -238,22 +324,51 @@ where
             }
             HirExpr::HirBinaryOp(b) => {
                 self.generate_bytecode_expr(&b.lhs, bytecode, src.clone())?;
-                self.generate_bytecode_expr(&b.rhs, bytecode, src)?;
+                self.generate_bytecode_expr(&b.rhs, bytecode, src.clone())?;
+                let ty = b.lhs.ty();
+                //Strings are compared to each other, then the ordering is compared to 0
+                if let (HirTy::String(_), false) =
+                    (ty, matches!(b.op, crate::atlas_hir::expr::HirBinaryOp::Add))
+                {
+                    bytecode.push(Instruction::StrCmp);
+                    bytecode.push(Instruction::PushInt(0));
+                }
                 match b.op {
                     crate::atlas_hir::expr::HirBinaryOp::Add => {
-                        bytecode.push(Instruction::AddI64);
+                        bytecode.push(match ty {
+                            HirTy::String(_) => Instruction::StrConcat,
+                            HirTy::Float64(_) => Instruction::AddF64,
+                            HirTy::UInt64(_) => Instruction::AddU64,
+                            _ => Instruction::AddI64,
+                        });
                     }
                     crate::atlas_hir::expr::HirBinaryOp::Sub => {
-                        bytecode.push(Instruction::SubI64);
+                        bytecode.push(match ty {
+                            HirTy::Float64(_) => Instruction::SubF64,
+                            HirTy::UInt64(_) => Instruction::SubU64,
+                            _ => Instruction::SubI64,
+                        });
                     }
                     crate::atlas_hir::expr::HirBinaryOp::Mul => {
-                        bytecode.push(Instruction::MulI64);
+                        bytecode.push(match ty {
+                            HirTy::Float64(_) => Instruction::MulF64,
+                            HirTy::UInt64(_) => Instruction::MulU64,
+                            _ => Instruction::MulI64,
+                        });
                     }
                     crate::atlas_hir::expr::HirBinaryOp::Div => {
-                        bytecode.push(Instruction::DivI64);
+                        bytecode.push(match ty {
+                            HirTy::Float64(_) => Instruction::DivF64,
+                            HirTy::UInt64(_) => Instruction::DivU64,
+                            _ => Instruction::DivI64,
+                        });
                     }
                     crate::atlas_hir::expr::HirBinaryOp::Mod => {
-                        bytecode.push(Instruction::ModI64);
+                        bytecode.push(match ty {
+                            HirTy::Float64(_) => Instruction::ModF64,
+                            HirTy::UInt64(_) => Instruction::ModU64,
+                            _ => Instruction::ModI64,
+                        });
                     }
                     crate::atlas_hir::expr::HirBinaryOp::Eq => {
                         bytecode.push(Instruction::Eq);
@@ -273,7 +388,19 @@ where
                     crate::atlas_hir::expr::HirBinaryOp::Lte => {
                         bytecode.push(Instruction::Lte);
                     }
-                    _ => unimplemented!("Unsupported binary operator for now"),
+                    crate::atlas_hir::expr::HirBinaryOp::And
+                    | crate::atlas_hir::expr::HirBinaryOp::Or => {
+                        return Err(crate::atlas_hir::error::HirError::UnsupportedExpr(
+                            UnsupportedExpr {
+                                span: SourceSpan::new(
+                                    SourceOffset::from(b.op_span.start()),
+                                    b.op_span.end() - b.op_span.start(),
+                                ),
+                                expr: "the `&&` & `||` operators".to_string(),
+                                src,
+                            },
+                        ));
+                    }
                 }
             }
             HirExpr::Unary(u) => {
@@ -287,11 +414,21 @@ where
                 }
                 let callee = f.callee.as_ref();
                 match callee {
+                    //The type checker only types the callee when it is a closure
+                    _ if matches!(callee.ty(), HirTy::Function(_)) => {
+                        self.generate_bytecode_expr(callee, bytecode, src)?;
+                        bytecode.push(Instruction::CallIndirect {
+                            args: f.args.len() as u8,
+                        });
+                    }
                     HirExpr::Ident(i) => {
                         let func = self.hir.signature.functions.get(i.name).unwrap();
-                        if func.is_external {
+                        if let Some(library) = func.library {
+                            //Native functions are registered under their unqualified name
+                            let name = i.name.rsplit("::").next().unwrap_or(i.name);
                             bytecode.push(Instruction::ExternCall {
-                                name: i.name.to_string(),
+                                library: library.to_string(),
+                                name: name.to_string(),
                                 args: f.args.len() as u8,
                             });
                         } else {
@@ -315,75 +452,659 @@ where
                     }
                 }
             }
-            HirExpr::Ident(i) => match i.ty {
-                HirTy::Int64(_) => {
-                    bytecode.push(Instruction::LoadI64 {
-                        var_name: i.name.to_string(),
-                    });
+            HirExpr::Ident(i) => match self.local(i.name) {
+                Some(slot) => bytecode.push(Instruction::LoadLocal(slot)),
+                //Otherwise the type checker made sure it names a function, it needs no capture
+                None => bytecode.push(Instruction::MakeClosure {
+                    function: i.name.to_string(),
+                    captures: 0,
+                }),
+            },
+            HirExpr::NewObj(o) => {
+                let struct_sig = self.hir.signature.structs.get(o.name).unwrap();
+                //Fields are pushed in declaration order so the VM can lay them out by index
+                for field_sig in &struct_sig.fields {
+                    let init = o.fields.iter().find(|f| f.name == field_sig.name).unwrap();
+                    self.generate_bytecode_expr(&init.value, bytecode, src.clone())?;
                 }
-                HirTy::Float64(_) => {
-                    bytecode.push(Instruction::LoadF64 {
-                        var_name: i.name.to_string(),
-                    });
+                bytecode.push(Instruction::NewObj {
+                    fields: struct_sig.fields.len(),
+                });
+            }
+            HirExpr::NewEnum(e) => {
+                let (discriminant, _) =
+                    self.hir.signature.enums[e.name].variant(e.variant).unwrap();
+                //An enum value is a structure whose first field is the discriminant
+                bytecode.push(Instruction::PushUnsignedInt(discriminant as u64));
+                for arg in &e.args {
+                    self.generate_bytecode_expr(arg, bytecode, src.clone())?;
                 }
-                HirTy::UInt64(_) => {
-                    bytecode.push(Instruction::LoadU64 {
-                        var_name: i.name.to_string(),
-                    });
+                bytecode.push(Instruction::NewEnum {
+                    fields: e.args.len() + 1,
+                });
+            }
+            //The receiver is passed as the first argument
+            HirExpr::MethodCall(m) => {
+                self.generate_bytecode_expr(&m.target, bytecode, src.clone())?;
+                for arg in &m.args {
+                    self.generate_bytecode_expr(arg, bytecode, src.clone())?;
                 }
-                //By default it will be an integer
-                _ => bytecode.push(Instruction::LoadI64 {
-                    var_name: i.name.to_string(),
-                }),
-            },
+                let args = m.args.len() as u8 + 1;
+                bytecode.push(match m.dispatch {
+                    HirDispatch::Static(name) => Instruction::CallFunction {
+                        name: name.to_string(),
+                        args,
+                    },
+                    HirDispatch::Dynamic(method) => Instruction::CallVirtual { method, args },
+                    HirDispatch::Unresolved => {
+                        unreachable!("the type checker resolves every method call")
+                    }
+                });
+            }
+            //A trait object is a structure holding the id of its vtable & the instance
+            HirExpr::Upcast(u) => {
+                let mut vtables = self.vtables.borrow_mut();
+                let id = match vtables
+                    .iter()
+                    .position(|&(c, t)| c == u.class && t == u.trait_name)
+                {
+                    Some(id) => id,
+                    None => {
+                        vtables.push((u.class, u.trait_name));
+                        vtables.len() - 1
+                    }
+                };
+                drop(vtables);
+                bytecode.push(Instruction::PushUnsignedInt(id as u64));
+                self.generate_bytecode_expr(&u.expr, bytecode, src)?;
+                bytecode.push(Instruction::NewObj { fields: 2 });
+            }
+            HirExpr::Match(m) => self.generate_bytecode_match(m, bytecode, src)?,
+            HirExpr::Lambda(l) => self.generate_bytecode_lambda(l, bytecode, src)?,
+            HirExpr::ListLiteral(l) => {
+                for item in &l.items {
+                    self.generate_bytecode_expr(item, bytecode, src.clone())?;
+                }
+                bytecode.push(Instruction::NewList {
+                    items: l.items.len(),
+                });
+            }
+            HirExpr::Indexing(i) => {
+                self.generate_bytecode_expr(&i.target, bytecode, src.clone())?;
+                self.generate_bytecode_expr(&i.index, bytecode, src)?;
+                bytecode.push(Instruction::ListLoad);
+            }
+            HirExpr::FieldAccess(f) => {
+                self.generate_bytecode_expr(&f.target, bytecode, src)?;
+                bytecode.push(Instruction::GetField {
+                    field: self.field_index(&f.target, f.field),
+                });
+            }
             HirExpr::IntegerLiteral(i) => bytecode.push(Instruction::PushInt(i.value)),
             HirExpr::FloatLiteral(f) => bytecode.push(Instruction::PushFloat(f.value)),
             HirExpr::BooleanLiteral(b) => bytecode.push(Instruction::PushBool(b.value)),
+            HirExpr::StringLiteral(s) => {
+                bytecode.push(Instruction::PushString(s.value.to_string()))
+            }
             HirExpr::UnsignedIntegererLiteral(u) => {
                 bytecode.push(Instruction::PushUnsignedInt(u.value))
             }
-            _ => {
-                return Err(crate::atlas_hir::error::HirError::UnsupportedExpr(
-                    UnsupportedExpr {
-                        span: SourceSpan::new(
-                            SourceOffset::from(expr.span().start()),
-                            expr.span().end() - expr.span().start(),
-                        ),
-                        expr: format!("{:?}", expr),
-                        src: src.clone(),
-                    },
-                ))
-            }
         }
+        bytecode.locate(start, self.location(expr.span()));
         Ok(())
     }
 
-    fn generate_bytecode_args(
+    /// The discriminant of the target selects an arm through a jump table.
+    /// Each arm pops the target once its bindings are stored and leaves its value on the stack.
+    fn generate_bytecode_match(
         &self,
-        args: Vec<&HirFunctionParameterSignature<'hir>>,
-        bytecode: &mut Vec<Instruction>,
+        m: &HirMatchExpr<'hir>,
+        bytecode: &mut Bytecode,
+        src: String,
     ) -> HirResult<()> {
-        let args = args.iter().rev().cloned().collect::<Vec<_>>();
-        for arg in args {
-            match arg.ty {
-                HirTy::Int64(_) => {
-                    bytecode.push(Instruction::StoreI64 {
-                        var_name: arg.name.to_string(),
-                    });
+        let enum_sig = match m.target.ty() {
+            HirTy::Named(n) => self.hir.signature.enums[n.name],
+            _ => unreachable!("the type checker only allows matching on enums"),
+        };
+        self.generate_bytecode_expr(&m.target, bytecode, src.clone())?;
+        bytecode.push(Instruction::Dup);
+        bytecode.push(Instruction::GetField { field: 0 });
+
+        let mut arms = Vec::with_capacity(m.arms.len());
+        for arm in &m.arms {
+            let mut body = Bytecode::default();
+            //The bindings are only in scope in their arm
+            self.generate_bytecode_scope(|| {
+                if let HirPattern::Variant(p) = &arm.pattern {
+                    for (i, binding) in p.bindings.iter().enumerate() {
+                        if binding.name == "_" {
+                            continue;
+                        }
+                        body.push(Instruction::Dup);
+                        body.push(Instruction::GetField { field: i + 1 });
+                        body.push(Instruction::StoreLocal(self.declare_local(binding.name)));
+                    }
                 }
-                HirTy::Float64(_) => {
-                    bytecode.push(Instruction::StoreF64 {
-                        var_name: arg.name.to_string(),
-                    });
+                body.push(Instruction::Pop);
+                match &arm.body {
+                    HirMatchArmBody::Block(b) => {
+                        self.generate_bytecode_block(b, &mut body, src.clone())?;
+                        body.push(Instruction::PushUnit);
+                    }
+                    HirMatchArmBody::Expr(e) => {
+                        self.generate_bytecode_expr(e, &mut body, src.clone())?
+                    }
                 }
-                HirTy::UInt64(_) => {
-                    bytecode.push(Instruction::StoreU64 {
-                        var_name: arg.name.to_string(),
-                    });
+                Ok(())
+            })?;
+            arms.push(body);
+        }
+
+        //Offsets are relative to the jump table, the first arm directly follows it
+        let mut starts = Vec::with_capacity(arms.len());
+        let mut offset = 1;
+        for arm in &arms {
+            starts.push(offset);
+            offset += arm.len() as isize + 1;
+        }
+        let end = offset;
+        let targets = enum_sig
+            .variants
+            .iter()
+            .map(|v| {
+                let arm = m
+                    .arms
+                    .iter()
+                    .position(|arm| match &arm.pattern {
+                        HirPattern::Wildcard(_) => true,
+                        HirPattern::Variant(p) => p.variant == v.name,
+                    })
+                    .unwrap();
+                starts[arm]
+            })
+            .collect();
+        bytecode.push(Instruction::JmpTable { targets });
+        for (arm, start) in arms.iter_mut().zip(starts) {
+            let jmp = start + arm.len() as isize;
+            bytecode.append(arm);
+            bytecode.push(Instruction::Jmp { pos: end - jmp });
+        }
+        Ok(())
+    }
+
+    /// The body goes to its own label, the closure is built from the current value of the captures
+    fn generate_bytecode_lambda(
+        &self,
+        l: &HirLambdaExpr<'hir>,
+        bytecode: &mut Bytecode,
+        src: String,
+    ) -> HirResult<()> {
+        let name = format!("lambda#{}", self.lambda_count.get());
+        self.lambda_count.set(self.lambda_count.get() + 1);
+
+        //Captures are pushed on top of the arguments by `CallIndirect`
+        let mut locals = l
+            .params
+            .iter()
+            .map(|p| p.name)
+            .chain(l.captures.iter().map(|c| c.name))
+            .collect();
+        let (body, frame_size) = self.generate_bytecode_frame(&mut locals, |body| {
+            match &l.body {
+                HirLambdaBody::Block(b) => {
+                    self.generate_bytecode_block(b, body, src.clone())?;
+                    if body.falls_through() {
+                        body.push(Instruction::PushUnit);
+                        body.push(Instruction::Return);
+                    }
+                }
+                HirLambdaBody::Expr(e) => {
+                    self.generate_bytecode_expr(e, body, src.clone())?;
+                    body.push(Instruction::Return);
                 }
-                _ => unimplemented!("Unsupported argument type for now"),
             }
+            Ok(())
+        })?;
+        self.lambdas
+            .borrow_mut()
+            .push((name.clone(), frame_size, body));
+
+        for capture in &l.captures {
+            bytecode.push(Instruction::LoadLocal(self.local_slot(capture.name)));
         }
+        bytecode.push(Instruction::MakeClosure {
+            function: name,
+            captures: l.captures.len(),
+        });
         Ok(())
     }
+
+    /// Generates a function body whose frame starts with `locals`, they are replaced by the
+    /// locals still in scope at the end of the body. Also gives the number of slots of the frame,
+    /// the VM reserves them when it enters the frame.
+    fn generate_bytecode_frame(
+        &self,
+        locals: &mut Vec<&'hir str>,
+        body: impl FnOnce(&mut Bytecode) -> HirResult<()>,
+    ) -> HirResult<(Bytecode, usize)> {
+        let outer_size = self.frame_size.replace(locals.len());
+        let outer = self.locals.replace(std::mem::take(locals));
+        let mut bytecode = Bytecode::default();
+        let res = body(&mut bytecode);
+        *locals = self.locals.replace(outer);
+        let frame_size = self.frame_size.replace(outer_size);
+        res?;
+        Ok((bytecode, frame_size))
+    }
+
+    /// The locals declared by `body` go out of scope once it is generated, their slots are free
+    /// for the next ones
+    fn generate_bytecode_scope(&self, body: impl FnOnce() -> HirResult<()>) -> HirResult<()> {
+        let len = self.locals.borrow().len();
+        let res = body();
+        self.locals.borrow_mut().truncate(len);
+        res
+    }
+
+    /// Index of the file at `path` in `files`, it's added the first time it's met
+    fn file_index(files: &mut Vec<SourceFile>, path: &str, src: &str) -> usize {
+        match files.iter().position(|f| f.path == path) {
+            Some(index) => index,
+            None => {
+                files.push(SourceFile {
+                    path: path.to_string(),
+                    src: src.into(),
+                });
+                files.len() - 1
+            }
+        }
+    }
+
+    /// Location of `span` in the file of the function being generated
+    fn location(&self, span: Span) -> Option<SourceLocation> {
+        self.file.get().map(|file| SourceLocation {
+            file,
+            start: span.start(),
+            end: span.end(),
+        })
+    }
+
+    /// Index of `field` in the declaration of the struct `target` evaluates to
+    fn field_index(&self, target: &HirExpr<'hir>, field: &str) -> usize {
+        let name = match target.ty() {
+            HirTy::Named(n) => n.name,
+            _ => unreachable!("the type checker only allows field access on structs"),
+        };
+        let (idx, _) = self.hir.signature.structs[name].field(field).unwrap();
+        idx
+    }
+
+    /// Slot of the innermost local `name` in scope, if there is one
+    fn local(&self, name: &str) -> Option<u16> {
+        let slot = self.locals.borrow().iter().rposition(|&l| l == name)?;
+        u16::try_from(slot).ok()
+    }
+
+    /// Slot of the innermost local `name` in scope
+    fn local_slot(&self, name: &str) -> u16 {
+        self.local(name)
+            .expect("the type checker only allows variables in scope")
+    }
+
+    /// Gives a new slot to the local `name`, it shadows the locals with the same name
+    fn declare_local(&self, name: &'hir str) -> u16 {
+        let mut locals = self.locals.borrow_mut();
+        locals.push(name);
+        self.frame_size.set(self.frame_size.get().max(locals.len()));
+        u16::try_from(locals.len() - 1).expect("a function can't have more than 65536 locals")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bumpalo::Bump;
+
+    use super::*;
+    use crate::{
+        atlas_frontend::{parse, parser::arena::AstArena},
+        atlas_hir::{
+            arena::HirArena, syntax_lowering_pass::AstSyntaxLoweringPass,
+            type_check_pass::TypeChecker,
+        },
+        atlas_memory::vm_data::VMData,
+        atlas_vm::Atlas77VM,
+    };
+
+    /// Compiles `source` & runs its `main`, which returns an `i64`
+    fn run(source: &str) -> CodegenResult<i64> {
+        let bump = Bump::new();
+        let ast_arena = AstArena::new(&bump);
+        let ast = parse("test.atlas", &ast_arena, source.to_string()).unwrap();
+        let hir_arena = HirArena::new();
+        let path = PathBuf::from("test.atlas");
+        let mut hir =
+            AstSyntaxLoweringPass::new(&hir_arena, &ast, &ast_arena, path, source.to_string())
+                .lower()?;
+        TypeChecker::new(&hir_arena, source.to_string()).check(&mut hir)?;
+        let bump = Bump::new();
+        let program =
+            CodeGenUnit::new(hir, CodeGenArena::new(&bump), source.to_string()).compile()?;
+        let result = Atlas77VM::new(program).run().unwrap();
+        assert_eq!(result.tag, VMData::TAG_I64);
+        Ok(result.as_i64())
+    }
+
+    #[test]
+    fn reads_and_writes_struct_fields() {
+        let source = r#"
+struct Point {
+    x: i64;
+    y: i64;
+}
+func main() -> i64 {
+    let p: Point = Point { y: 2, x: 1 };
+    p.y = p.x + 40;
+    return p.x * 100 + p.y;
+}
+"#;
+        assert_eq!(run(source).unwrap(), 141);
+    }
+
+    #[test]
+    fn indexes_lists() {
+        let source = r#"
+func main() -> i64 {
+    let l: [i64] = [1, 2, 3];
+    l[2] = l[0] + l[1];
+    let nested: [[i64]] = [[], l];
+    return nested[1][2] * 10 + l[1_u64];
+}
+"#;
+        assert_eq!(run(source).unwrap(), 32);
+        let empty = r#"
+struct Node {
+    v: i64;
+    next: [Node];
+}
+func main() -> i64 {
+    let n: Node = Node { v: 1, next: [] };
+    n.next = [Node { v: 2, next: [] }];
+    return n.v * 10 + n.next[0].v;
+}
+"#;
+        assert_eq!(run(empty).unwrap(), 12);
+    }
+
+    #[test]
+    fn orders_numbers_and_strings() {
+        let source = r#"
+func main() -> i64 {
+    if "abc" < "abd" {
+        if 2.5 >= 2.5 {
+            return 1;
+        }
+    }
+    return 0;
+}
+"#;
+        assert_eq!(run(source).unwrap(), 1);
+    }
+
+    #[test]
+    fn matches_enum_variants() {
+        let source = r#"
+enum Shape {
+    Circle(i64),
+    Rect(i64, i64),
+    Empty
+}
+func area(s: Shape) -> i64 {
+    return match s {
+        Shape::Circle(r) ~> 3 * r * r,
+        Shape::Rect(w, h) ~> w * h,
+        _ ~> 0
+    };
+}
+func main() -> i64 {
+    return area(Shape::Circle(2)) * 100 + area(Shape::Rect(2, 3)) + area(Shape::Empty);
+}
+"#;
+        assert_eq!(run(source).unwrap(), 1206);
+    }
+
+    #[test]
+    fn compares_enums_by_value() {
+        let source = r#"
+enum Color {
+    Red,
+    Rgb(i64, i64, i64),
+    Named(str)
+}
+func contains<T>(l: [T], value: T) -> bool {
+    let i: i64 = 0;
+    while i < 2 {
+        if l[i] == value {
+            return true;
+        }
+        i = i + 1;
+    }
+    return false;
+}
+func main() -> i64 {
+    let score: i64 = 0;
+    if Color::Red == Color::Red {
+        score = score + 1;
+    }
+    if Color::Rgb(1, 2, 3) == Color::Rgb(1, 2, 3) {
+        score = score + 10;
+    }
+    if Color::Rgb(1, 2, 3) != Color::Rgb(1, 2, 4) {
+        score = score + 100;
+    }
+    if Color::Red != Color::Named("red") {
+        score = score + 1000;
+    }
+    if contains([Color::Red, Color::Named("teal")], Color::Named("teal")) {
+        score = score + 10000;
+    }
+    return score;
+}
+"#;
+        assert_eq!(run(source).unwrap(), 11111);
+    }
+
+    #[test]
+    fn calls_generic_functions() {
+        let source = r#"
+func first<T>(l: [T]) -> T {
+    return l[0];
+}
+func pick<T>(a: T, b: T, take_a: bool) -> T {
+    if take_a {
+        return a;
+    }
+    return b;
+}
+func main() -> i64 {
+    let words: [str] = ["a", "bc"];
+    if pick(first(words), "def", false) == "def" {
+        return first([7, 8]) * 10 + pick(1, 2, true);
+    }
+    return 0;
+}
+"#;
+        assert_eq!(run(source).unwrap(), 71);
+    }
+
+    #[test]
+    fn gives_each_declaration_its_own_slot() {
+        let source = r#"
+enum Opt {
+    Some(i64),
+    None
+}
+func unwrap(o: Opt, n: i64) -> i64 {
+    let picked: i64 = match o {
+        Opt::Some(n) ~> n,
+        Opt::None ~> 0
+    };
+    return picked * 10 + n;
+}
+func main() -> i64 {
+    let x: i64 = 5;
+    let first: i64 = x;
+    let x: str = "five";
+    let i: i64 = 0;
+    let total: i64 = 0;
+    while i < 3 {
+        let x: i64 = i * 100;
+        total = total + x;
+        i = i + 1;
+    }
+    if x == "five" {
+        total = total + first;
+    }
+    let n: i64 = 7;
+    let got: i64 = match Opt::Some(40) {
+        Opt::Some(n) ~> n,
+        Opt::None ~> 0
+    };
+    return total + got + n + unwrap(Opt::Some(2), 3);
+}
+"#;
+        assert_eq!(run(source).unwrap(), 375);
+    }
+
+    #[test]
+    fn captures_values_in_closures() {
+        let source = r#"
+func make_adder(n: i64) -> (i64) -> i64 {
+    return \(x: i64) ~> x + n;
+}
+func apply(f: (i64) -> i64, x: i64) -> i64 {
+    return f(x);
+}
+func main() -> i64 {
+    let factor: i64 = 10;
+    let scale: (i64) -> i64 = \(x: i64) ~> x * factor;
+    factor = 1000;
+    let add_five: (i64) -> i64 = make_adder(5);
+    return apply(scale, 3) + add_five(2) + factor;
+}
+"#;
+        assert_eq!(run(source).unwrap(), 1037);
+    }
+
+    #[test]
+    fn uses_functions_as_values() {
+        let source = r#"
+func double(x: i64) -> i64 {
+    return x * 2;
+}
+func apply(f: (i64) -> i64, x: i64) -> i64 {
+    return f(x);
+}
+func main() -> i64 {
+    let f: (i64) -> i64 = double;
+    return apply(double, 5) * 100 + f(1);
+}
+"#;
+        assert_eq!(run(source).unwrap(), 1002);
+    }
+
+    #[test]
+    fn dispatches_trait_methods() {
+        let source = r#"
+trait Shape {
+    func area(&self) -> i64;
+}
+class Rect : Shape {
+    private:
+        w: i64;
+        h: i64;
+    public:
+        func new(w: i64, h: i64) -> Rect {
+            return Rect { w: w, h: h };
+        }
+        func widen(&self, by: i64) {
+            self.w = self.w + by;
+        }
+        #[override(Shape::area)]
+        func area(&self) -> i64 {
+            return self.w * self.h;
+        }
+}
+class Square : Shape {
+    private:
+        side: i64;
+    public:
+        func new(side: i64) -> Square {
+            return Square { side: side };
+        }
+        #[override(Shape::area)]
+        func area(&self) -> i64 {
+            return self.side * self.side;
+        }
+}
+func total(a: Shape, b: Shape) -> i64 {
+    return a.area() + b.area();
+}
+func main() -> i64 {
+    let r = Rect::new(2, 3);
+    r.widen(1);
+    let shape: Shape = Square::new(4);
+    let first: i64 = shape.area();
+    shape = r;
+    return total(r, Square::new(10)) * 1000 + first * 10 + shape.area() - 9;
+}
+"#;
+        assert_eq!(run(source).unwrap(), 109160);
+    }
+
+    #[test]
+    fn upcasts_list_items_to_traits() {
+        let source = r#"
+import "std/list"
+trait Shape {
+    func area(&self) -> i64;
+}
+class Square : Shape {
+    private:
+        side: i64;
+    public:
+        func new(side: i64) -> Square {
+            return Square { side: side };
+        }
+        #[override(Shape::area)]
+        func area(&self) -> i64 {
+            return self.side * self.side;
+        }
+}
+class Line : Shape {
+    private:
+        length: i64;
+    public:
+        func new(length: i64) -> Line {
+            return Line { length: length };
+        }
+        #[override(Shape::area)]
+        func area(&self) -> i64 {
+            return 0;
+        }
+}
+func main() -> i64 {
+    let s: [Shape] = [Square::new(2), Line::new(7), Square::new(3)];
+    let total = 0;
+    let i = 0_u64;
+    while i < len(s) {
+        total = total + s[i].area();
+        i = i + 1_u64;
+    }
+    return total;
+}
+"#;
+        assert_eq!(run(source).unwrap(), 13);
+    }
 }
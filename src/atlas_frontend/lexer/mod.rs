@@ -31,6 +31,7 @@ lexer_builder! {
             ';' => Semicolon,
             '\'' => Quote,
             '?' => Interrogation,
+            '#' => HashTag,
         },
         Either {
             '=' => '=' => OpEq, OpAssign,
@@ -99,6 +100,57 @@ lexer_builder! {
     },
 }
 
+/// Splits `source` into tokens, or gives the byte offset of the first character none of the
+/// systems accepts.
+///
+/// It's the loop of `AtlasLexer::tokenize` over the same systems, which doesn't say where it
+/// stopped. The spans of the tokens count characters as the systems do.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, usize> {
+    let systems = AtlasLexer::default().sys;
+    //The string system waits for its closing quote forever, there is none after the last quote
+    let last_quote = source.rfind('"');
+    let mut pos = BytePos::from(0);
+    let mut tokens = vec![Token::new(
+        Span {
+            start: pos,
+            end: pos,
+        },
+        TokenKind::SoI,
+    )];
+    let mut rest = source;
+    while let Some(c) = rest.chars().next() {
+        let offset = source.len() - rest.len();
+        if c == '"' && last_quote == Some(offset) {
+            return Err(offset);
+        }
+        let (token, end) = systems
+            .iter()
+            .find_map(|system| {
+                let mut state = LexerState::new(pos, rest, "<stdin>");
+                system(c, &mut state).map(|token| (token, state.current_pos))
+            })
+            .ok_or(offset)?;
+        match token.kind() {
+            TokenKind::WhiteSpace
+            | TokenKind::CarriageReturn
+            | TokenKind::NewLine
+            | TokenKind::Tabulation => {}
+            _ => tokens.push(token),
+        }
+        let read = usize::from(end) - usize::from(pos);
+        rest = &rest[rest.char_indices().nth(read).map_or(rest.len(), |(i, _)| i)..];
+        pos = end;
+    }
+    tokens.push(Token::new(
+        Span {
+            start: pos,
+            end: pos,
+        },
+        TokenKind::EoI,
+    ));
+    Ok(tokens)
+}
+
 impl std::fmt::Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.kind() {
@@ -1,18 +1,32 @@
 use std::path::PathBuf;
 
-use lexer::AtlasLexer;
-use parser::{arena::AstArena, ast::AstProgram, error::ParseResult};
+use lexer::Token;
+use miette::{SourceOffset, SourceSpan};
+use parser::{
+    arena::AstArena,
+    ast::AstProgram,
+    error::{ParseError, ParseResult, UnknownCharacterError},
+};
 
 pub mod lexer;
 pub mod parser;
 
+pub(crate) fn tokenize(source: String) -> ParseResult<Vec<Token>> {
+    lexer::tokenize(&source).map_err(|offset| {
+        let len = source[offset..].chars().next().map_or(0, char::len_utf8);
+        ParseError::UnknownCharacter(UnknownCharacterError {
+            span: SourceSpan::new(SourceOffset::from(offset), len),
+            src: source,
+        })
+    })
+}
+
 pub(crate) fn parse<'ast>(
     path: &'ast str,
     arena: &'ast AstArena<'ast>,
     source: String,
 ) -> ParseResult<AstProgram<'ast>> {
-    let mut lex: AtlasLexer = lexer::AtlasLexer::default();
-    let tokens = lex.set_source(source.clone()).tokenize().unwrap();
+    let tokens = tokenize(source.clone())?;
     let mut parser = parser::Parser::new(arena, tokens, PathBuf::from(path), source);
     parser.parse()
 }
@@ -9,15 +9,17 @@ pub(crate) struct AstProgram<'ast> {
 }
 
 /// An `Item` is anything that can be declared at the top-level scope of a program.
-/// This currently means variables & structs declarations
+/// This currently means functions, structs, enums, traits & classes declarations
 ///
-/// Enums & unions are also top-level items, but they are not yet supported
+/// Unions are also top-level items, but they are not yet supported
 #[derive(Debug, Clone, Serialize, Copy)]
 pub(crate) enum AstItem<'ast> {
     Struct(AstStruct<'ast>),
     ExternFunction(AstExternFunction<'ast>),
     Func(AstFunction<'ast>),
-    _Enum(AstEnum<'ast>),
+    Enum(AstEnum<'ast>),
+    Trait(AstTrait<'ast>),
+    Class(AstClass<'ast>),
     _Union(AstUnion<'ast>),
     Import(AstImport<'ast>),
 }
@@ -28,7 +30,9 @@ impl Spanned for AstItem<'_> {
             AstItem::Struct(v) => v.span,
             AstItem::ExternFunction(v) => v.span,
             AstItem::Func(v) => v.span,
-            AstItem::_Enum(v) => v.span,
+            AstItem::Enum(v) => v.span,
+            AstItem::Trait(v) => v.span,
+            AstItem::Class(v) => v.span,
             AstItem::_Union(v) => v.span,
             AstItem::Import(v) => v.span,
         }
@@ -39,6 +43,8 @@ impl Spanned for AstItem<'_> {
 pub(crate) struct AstFunction<'ast> {
     pub span: Span,
     pub name: &'ast AstIdentifier<'ast>,
+    /// `<T, U>`, empty for non generic functions
+    pub type_params: &'ast [&'ast AstIdentifier<'ast>],
     pub args: &'ast [&'ast AstObjField<'ast>],
     pub ret: &'ast AstType<'ast>,
     pub body: &'ast AstBlock<'ast>,
@@ -66,10 +72,11 @@ pub(crate) struct AstEnum<'ast> {
 }
 
 #[derive(Debug, Clone, Serialize, Copy)]
-/// Enums currently don't support associated values
+/// A variant can carry values, e.g. `Circle(f64)`
 pub(crate) struct AstEnumVariant<'ast> {
     pub span: Span,
     pub name: &'ast AstIdentifier<'ast>,
+    pub fields: &'ast [&'ast AstType<'ast>],
 }
 
 #[derive(Debug, Clone, Serialize, Copy)]
@@ -79,6 +86,64 @@ pub(crate) struct AstStruct<'ast> {
     pub fields: &'ast [&'ast AstObjField<'ast>],
 }
 
+/// A trait only declares the signatures of its methods, every one of them takes `&self`
+#[derive(Debug, Clone, Serialize, Copy)]
+pub(crate) struct AstTrait<'ast> {
+    pub span: Span,
+    pub name: &'ast AstIdentifier<'ast>,
+    pub methods: &'ast [&'ast AstTraitMethod<'ast>],
+}
+
+#[derive(Debug, Clone, Serialize, Copy)]
+pub(crate) struct AstTraitMethod<'ast> {
+    pub span: Span,
+    pub name: &'ast AstIdentifier<'ast>,
+    /// The parameters following `&self`
+    pub args: &'ast [&'ast AstObjField<'ast>],
+    pub ret: &'ast AstType<'ast>,
+}
+
+#[derive(Debug, Clone, Serialize, Copy)]
+pub(crate) struct AstClass<'ast> {
+    pub span: Span,
+    pub name: &'ast AstIdentifier<'ast>,
+    /// `class Rectangle : Shape, Drawable`
+    pub traits: &'ast [&'ast AstIdentifier<'ast>],
+    pub fields: &'ast [&'ast AstClassField<'ast>],
+    pub methods: &'ast [&'ast AstMethod<'ast>],
+}
+
+#[derive(Debug, Clone, Serialize, Copy, PartialEq, Eq)]
+pub(crate) enum AstVisibility {
+    Public,
+    Private,
+}
+
+#[derive(Debug, Clone, Serialize, Copy)]
+pub(crate) struct AstClassField<'ast> {
+    pub span: Span,
+    pub vis: AstVisibility,
+    pub field: &'ast AstObjField<'ast>,
+}
+
+#[derive(Debug, Clone, Serialize, Copy)]
+pub(crate) struct AstMethod<'ast> {
+    pub span: Span,
+    pub vis: AstVisibility,
+    /// Methods without `&self` are static and called with `Class::method()`
+    pub has_self: bool,
+    /// `#[override(Shape::getArea)]`
+    pub overrides: Option<AstOverride<'ast>>,
+    pub func: &'ast AstFunction<'ast>,
+}
+
+#[derive(Debug, Clone, Serialize, Copy)]
+pub(crate) struct AstOverride<'ast> {
+    pub span: Span,
+    pub trait_name: &'ast AstIdentifier<'ast>,
+    pub method: &'ast AstIdentifier<'ast>,
+}
+
 #[derive(Debug, Clone, Serialize, Copy)]
 pub(crate) struct AstObjField<'ast> {
     pub span: Span,
@@ -90,6 +155,7 @@ pub(crate) struct AstObjField<'ast> {
 pub(crate) struct AstExternFunction<'ast> {
     pub span: Span,
     pub name: &'ast AstIdentifier<'ast>,
+    pub type_params: &'ast [&'ast AstIdentifier<'ast>],
     pub args_name: &'ast [&'ast AstIdentifier<'ast>],
     pub args_ty: &'ast [&'ast AstType<'ast>],
     pub ret: &'ast AstType<'ast>,
@@ -170,7 +236,7 @@ pub(crate) struct AstAssignExpr<'ast> {
 #[derive(Debug, Clone, Serialize, Copy)]
 pub(crate) enum AstExpr<'ast> {
     _Let(AstLetExpr<'ast>),
-    _Lambda(AstLambdaExpr<'ast>),
+    Lambda(AstLambdaExpr<'ast>),
     _CompTime(AstCompTimeExpr<'ast>),
     IfElse(AstIfElseExpr<'ast>),
     BinaryOp(AstBinaryOpExpr<'ast>),
@@ -180,8 +246,10 @@ pub(crate) enum AstExpr<'ast> {
     Identifier(AstIdentifier<'ast>),
     Indexing(AstIndexingExpr<'ast>),
     FieldAccess(AstFieldAccessExpr<'ast>),
-    _NewObj(AstNewObjExpr<'ast>),
-    _Block(AstBlock<'ast>),
+    NewObj(AstNewObjExpr<'ast>),
+    StaticAccess(AstStaticAccessExpr<'ast>),
+    Match(AstMatchExpr<'ast>),
+    Block(AstBlock<'ast>),
     Assign(AstAssignExpr<'ast>),
     //Tuple(AstTupleExpr<'ast>),
 }
@@ -190,7 +258,7 @@ impl Spanned for AstExpr<'_> {
     fn span(&self) -> Span {
         match self {
             AstExpr::_Let(e) => e.span,
-            AstExpr::_Lambda(e) => e.span,
+            AstExpr::Lambda(e) => e.span,
             AstExpr::_CompTime(e) => e.span,
             AstExpr::IfElse(e) => e.span,
             AstExpr::BinaryOp(e) => e.span,
@@ -200,8 +268,10 @@ impl Spanned for AstExpr<'_> {
             AstExpr::Identifier(e) => e.span,
             AstExpr::Indexing(e) => e.span,
             AstExpr::FieldAccess(e) => e.span,
-            AstExpr::_NewObj(e) => e.span,
-            AstExpr::_Block(e) => e.span,
+            AstExpr::NewObj(e) => e.span,
+            AstExpr::StaticAccess(e) => e.span,
+            AstExpr::Match(e) => e.span,
+            AstExpr::Block(e) => e.span,
             AstExpr::Assign(e) => e.span,
         }
     }
@@ -240,6 +310,54 @@ pub(crate) struct AstFieldAccessExpr<'ast> {
     pub field: &'ast AstIdentifier<'ast>,
 }
 
+/// `Target::field`, e.g. `Shape::Circle`
+#[derive(Debug, Clone, Serialize, Copy)]
+pub(crate) struct AstStaticAccessExpr<'ast> {
+    pub span: Span,
+    pub target: &'ast AstIdentifier<'ast>,
+    pub field: &'ast AstIdentifier<'ast>,
+}
+
+#[derive(Debug, Clone, Serialize, Copy)]
+pub(crate) struct AstMatchExpr<'ast> {
+    pub span: Span,
+    pub target: &'ast AstExpr<'ast>,
+    pub arms: &'ast [&'ast AstMatchArm<'ast>],
+}
+
+#[derive(Debug, Clone, Serialize, Copy)]
+pub(crate) struct AstMatchArm<'ast> {
+    pub span: Span,
+    pub pattern: &'ast AstPattern<'ast>,
+    /// Either an `AstExpr::Block` or any other expression
+    pub body: &'ast AstExpr<'ast>,
+}
+
+#[derive(Debug, Clone, Serialize, Copy)]
+pub(crate) enum AstPattern<'ast> {
+    /// `_`
+    Wildcard(Span),
+    Variant(AstVariantPattern<'ast>),
+}
+
+impl Spanned for AstPattern<'_> {
+    fn span(&self) -> Span {
+        match self {
+            AstPattern::Wildcard(s) => *s,
+            AstPattern::Variant(p) => p.span,
+        }
+    }
+}
+
+/// `Shape::Rect(w, h)`, a binding named `_` is ignored
+#[derive(Debug, Clone, Serialize, Copy)]
+pub(crate) struct AstVariantPattern<'ast> {
+    pub span: Span,
+    pub enum_name: &'ast AstIdentifier<'ast>,
+    pub variant: &'ast AstIdentifier<'ast>,
+    pub bindings: &'ast [&'ast AstIdentifier<'ast>],
+}
+
 #[derive(Debug, Clone, Serialize, Copy)]
 pub(crate) struct AstIndexingExpr<'ast> {
     pub span: Span,
@@ -308,10 +426,14 @@ pub(crate) struct AstLetExpr<'ast> {
     pub value: &'ast AstExpr<'ast>,
 }
 
+/// `\(x: i64) -> i64 { return x * 2; }` or `\(x: i64) ~> x * 2`
 #[derive(Debug, Clone, Serialize, Copy)]
 pub(crate) struct AstLambdaExpr<'ast> {
     pub span: Span,
-    pub args: &'ast [&'ast AstIdentifier<'ast>],
+    pub args: &'ast [&'ast AstObjField<'ast>],
+    /// Inferred from the body for `~>` lambdas, `unit` for block lambdas when omitted
+    pub ret: Option<&'ast AstType<'ast>>,
+    /// Either an `AstExpr::Block` or any other expression
     pub body: &'ast AstExpr<'ast>,
 }
 
@@ -334,7 +456,7 @@ pub(crate) enum AstLiteral<'ast> {
     Float(AstFloatLiteral),
     String(AstStringLiteral<'ast>),
     Boolean(AstBooleanLiteral),
-    _List(AstListLiteral<'ast>),
+    List(AstListLiteral<'ast>),
 }
 
 impl Spanned for AstLiteral<'_> {
@@ -345,7 +467,7 @@ impl Spanned for AstLiteral<'_> {
             AstLiteral::Float(l) => l.span,
             AstLiteral::String(l) => l.span,
             AstLiteral::Boolean(l) => l.span,
-            AstLiteral::_List(l) => l.span,
+            AstLiteral::List(l) => l.span,
         }
     }
 }
@@ -397,7 +519,7 @@ pub(crate) enum AstType<'ast> {
     Named(AstNamedType<'ast>),
     Pointer(AstPointerType<'ast>),
     Function(AstFunctionType<'ast>),
-    _List(AstListType<'ast>),
+    List(AstListType<'ast>),
     _Map(AstMapType<'ast>),
     //Tuple(AstTupleType<'ast>),
 }
@@ -414,7 +536,7 @@ impl Spanned for AstType<'_> {
             AstType::Named(t) => t.span,
             AstType::Pointer(t) => t.span,
             AstType::Function(t) => t.span,
-            AstType::_List(t) => t.span,
+            AstType::List(t) => t.span,
             AstType::_Map(t) => t.span,
         }
     }
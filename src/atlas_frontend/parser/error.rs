@@ -5,9 +5,10 @@ use crate::{atlas_frontend::lexer::Token, declare_error_type};
 
 declare_error_type! {
     #[error("Parse error: {0}")]
-    pub(crate) enum ParseError {
+    pub enum ParseError {
         UnexpectedEndOfFile(UnexpectedEndOfFileError),
         UnexpectedToken(UnexpectedTokenError),
+        UnknownCharacter(UnknownCharacterError),
     }
 }
 
@@ -19,7 +20,7 @@ pub(crate) type ParseResult<T> = Result<T, ParseError>;
     help("Add more input to form a valid program")
 )]
 #[error("expected more characters after this")]
-pub(crate) struct UnexpectedEndOfFileError {
+pub struct UnexpectedEndOfFileError {
     #[label = "required more input to parse"]
     pub span: SourceSpan,
     #[source_code]
@@ -29,7 +30,7 @@ pub(crate) struct UnexpectedEndOfFileError {
 #[derive(Error, Diagnostic, Debug)]
 #[diagnostic(code(syntax::unexpected_token))]
 #[error("Found unexpected token during parsing")]
-pub(crate) struct UnexpectedTokenError {
+pub struct UnexpectedTokenError {
     pub token: Token,
     pub expected: crate::atlas_frontend::lexer::TokenVec,
     #[label("was not expecting to find '{token}' in this position, expected one of: {expected}")]
@@ -37,3 +38,16 @@ pub(crate) struct UnexpectedTokenError {
     #[source_code]
     pub src: String,
 }
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(
+    code(syntax::unknown_character),
+    help("remove the characters that aren't part of the language")
+)]
+#[error("found a character the lexer doesn't know")]
+pub struct UnknownCharacterError {
+    #[label = "the lexer can't go past this character"]
+    pub span: SourceSpan,
+    #[source_code]
+    pub src: String,
+}
@@ -7,9 +7,11 @@ use std::{
 
 use bumpalo::Bump;
 
+use atlas_core::prelude::Span;
+
 use super::ty::{
-    HirBooleanTy, HirFloatTy, HirIntegerTy, HirTy, HirTyId, HirUninitializedTy, HirUnitTy,
-    HirUnsignedIntTy,
+    HirBooleanTy, HirFloatTy, HirFunctionTy, HirGenericTy, HirIntegerTy, HirListTy, HirNamedTy,
+    HirStringTy, HirTy, HirTyId, HirUninitializedTy, HirUnitTy, HirUnsignedIntTy,
 };
 
 pub(crate) struct HirArena<'arena> {
@@ -123,6 +125,14 @@ impl<'arena> TypeArena<'arena> {
             .or_insert_with(|| self.allocator.alloc(HirTy::Unit(HirUnitTy {})))
     }
 
+    pub fn get_str_ty(&'arena self) -> &'arena HirTy<'arena> {
+        let id = HirTyId::compute_str_ty_id();
+        self.intern
+            .borrow_mut()
+            .entry(id)
+            .or_insert_with(|| self.allocator.alloc(HirTy::String(HirStringTy {})))
+    }
+
     pub fn get_uninitialized_ty(&'arena self) -> &'arena HirTy<'arena> {
         let id = HirTyId::compute_uninitialized_ty_id();
         self.intern.borrow_mut().entry(id).or_insert_with(|| {
@@ -130,4 +140,45 @@ impl<'arena> TypeArena<'arena> {
                 .alloc(HirTy::Uninitialized(HirUninitializedTy {}))
         })
     }
+
+    pub fn get_list_ty(&'arena self, inner: &'arena HirTy<'arena>) -> &'arena HirTy<'arena> {
+        let id = HirTyId::compute_list_ty_id(&HirTyId::from(inner));
+        self.intern
+            .borrow_mut()
+            .entry(id)
+            .or_insert_with(|| self.allocator.alloc(HirTy::List(HirListTy { inner })))
+    }
+
+    /// The span stored in the type is the one of the first occurrence of the name
+    pub fn get_named_ty(&'arena self, name: &'arena str, span: Span) -> &'arena HirTy<'arena> {
+        let id = HirTyId::compute_name_ty_id(name);
+        self.intern.borrow_mut().entry(id).or_insert_with(|| {
+            self.allocator
+                .alloc(HirTy::Named(HirNamedTy { name, span }))
+        })
+    }
+
+    pub fn get_function_ty(
+        &'arena self,
+        params: Vec<&'arena HirTy<'arena>>,
+        ret_ty: &'arena HirTy<'arena>,
+    ) -> &'arena HirTy<'arena> {
+        let id = HirTyId::compute_function_ty_id(
+            &HirTyId::from(ret_ty),
+            &params.iter().map(|p| HirTyId::from(*p)).collect::<Vec<_>>(),
+        );
+        self.intern.borrow_mut().entry(id).or_insert_with(|| {
+            self.allocator
+                .alloc(HirTy::Function(HirFunctionTy { ret_ty, params }))
+        })
+    }
+
+    /// The span stored in the type is the one of the first occurrence of the name
+    pub fn get_generic_ty(&'arena self, name: &'arena str, span: Span) -> &'arena HirTy<'arena> {
+        let id = HirTyId::compute_generic_ty_id(name);
+        self.intern.borrow_mut().entry(id).or_insert_with(|| {
+            self.allocator
+                .alloc(HirTy::Generic(HirGenericTy { name, span }))
+        })
+    }
 }
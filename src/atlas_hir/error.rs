@@ -12,8 +12,28 @@ declare_error_type! {
         FunctionTypeMismatch(FunctionTypeMismatchError),
         UnsupportedStatement(UnsupportedStatement),
         UnsupportedExpr(UnsupportedExpr),
+        UnsupportedType(UnsupportedTypeError),
         TryingToNegateUnsigned(TryingToNegateUnsignedError),
         TryingToMutateImmutableVariable(TryingToMutateImmutableVariableError),
+        UnknownField(UnknownFieldError),
+        MissingFields(MissingFieldsError),
+        DuplicateField(DuplicateFieldError),
+        CannotInferType(CannotInferTypeError),
+        UnknownVariant(UnknownVariantError),
+        VariantArity(VariantArityError),
+        NonExhaustiveMatch(NonExhaustiveMatchError),
+        UnknownMethod(UnknownMethodError),
+        PrivateMember(PrivateMemberError),
+        MissingTraitMethods(MissingTraitMethodsError),
+        InvalidOverride(InvalidOverrideError),
+        UnknownModule(UnknownModuleError),
+        CyclicImport(CyclicImportError),
+        ImportedModule(ImportedModuleError),
+        NativeSignatureMismatch(NativeSignatureMismatchError),
+        UnknownNative(UnknownNativeError),
+        NativeConflict(NativeConflictError),
+        DuplicateDefinition(DuplicateDefinitionError),
+        AmbiguousImport(AmbiguousImportError),
     }
 }
 
@@ -54,6 +74,17 @@ pub struct UnsupportedExpr {
     pub src: String,
 }
 
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(code(sema::unsupported_type))]
+#[error("{ty} isn't supported yet")]
+pub struct UnsupportedTypeError {
+    #[label = "unsupported type"]
+    pub span: Span,
+    pub ty: String,
+    #[source_code]
+    pub src: String,
+}
+
 #[derive(Error, Diagnostic, Debug)]
 #[diagnostic(code(sema::unsupported_stmt))]
 #[error("{stmt} isn't supported yet")]
@@ -65,6 +96,127 @@ pub struct UnsupportedStatement {
     pub src: String,
 }
 
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(
+    code(sema::unknown_module),
+    help("paths are relative to the importing file, without the `.atlas` extension")
+)]
+#[error("could not find module {path}")]
+pub struct UnknownModuleError {
+    pub path: String,
+    pub file: String,
+    #[label = "{file} doesn't exist or can't be read"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(
+    code(sema::native_signature_mismatch),
+    help("the host declares `{name}{signature}`")
+)]
+#[error("`{name}` doesn't match the signature of the native function")]
+pub struct NativeSignatureMismatchError {
+    pub name: String,
+    pub signature: String,
+    pub expected: String,
+    #[label = "expected {expected}"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(
+    code(sema::unknown_native),
+    help("import the module of the host providing it before declaring it")
+)]
+#[error("no imported module provides the native function `{name}`")]
+pub struct UnknownNativeError {
+    pub name: String,
+    #[label = "declared here"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+/// An `extern` declaration of a function several imported modules of the host provide
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(
+    code(sema::native_conflict),
+    help("the modules {modules} all provide it, import only one of them")
+)]
+#[error("`{name}` is provided by more than one native module")]
+pub struct NativeConflictError {
+    pub name: String,
+    pub modules: String,
+    #[label = "declared here"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+/// Two functions or two types with the same name, an imported one points at its `import`
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(code(sema::duplicate_definition), help("rename one of them"))]
+#[error("{name} is defined more than once")]
+pub struct DuplicateDefinitionError {
+    pub name: String,
+    #[label = "{name} is defined again here"]
+    pub span: Span,
+    #[label = "first defined here"]
+    pub previous: Span,
+    #[source_code]
+    pub src: String,
+}
+
+/// A function of a module imported without an alias has the name of another function
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(
+    code(sema::ambiguous_import),
+    help("import the module with an alias, e.g. `{import}`, its functions are then `alias::name`")
+)]
+#[error("{name} is ambiguous")]
+pub struct AmbiguousImportError {
+    pub name: String,
+    /// The import with an alias suggested
+    pub import: String,
+    #[label = "{name} is defined again here"]
+    pub span: Span,
+    #[label = "first defined here"]
+    pub previous: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(code(sema::cyclic_import), help("the import chain is {cycle}"))]
+#[error("cyclic import of {path}")]
+pub struct CyclicImportError {
+    pub path: String,
+    pub cycle: String,
+    #[label = "{path} is already being imported"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+/// Wraps an error found in an imported module, its source code is the one of the module
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(code(sema::imported_module))]
+#[error("error in module {path}")]
+pub struct ImportedModuleError {
+    pub path: String,
+    #[label = "imported here"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+    #[source]
+    #[diagnostic_source]
+    pub error: Box<dyn Diagnostic + Send + Sync>,
+}
+
 #[derive(Error, Diagnostic, Debug)]
 #[diagnostic(code(sema::unknown_type))]
 #[error("{name} does not name a known type")]
@@ -76,6 +228,157 @@ pub struct UnknownTypeError {
     pub src: String,
 }
 
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(code(sema::unknown_field))]
+#[error("{ty_name} has no field named {name}")]
+pub struct UnknownFieldError {
+    pub name: String,
+    pub ty_name: String,
+    #[label = "unknown field {name}"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(
+    code(sema::missing_fields),
+    help("every field of a struct must be initialized")
+)]
+#[error("missing fields in {ty_name} literal")]
+pub struct MissingFieldsError {
+    pub fields: String,
+    pub ty_name: String,
+    #[label = "missing {fields}"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(
+    code(sema::duplicate_field),
+    help("a field of a struct can only be initialized once")
+)]
+#[error("{name} is initialized more than once in {ty_name} literal")]
+pub struct DuplicateFieldError {
+    pub name: String,
+    pub ty_name: String,
+    #[label = "{name} is initialized again here"]
+    pub span: Span,
+    #[label = "first initialized here"]
+    pub previous: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(code(sema::unknown_variant))]
+#[error("{ty_name} has no variant named {name}")]
+pub struct UnknownVariantError {
+    pub name: String,
+    pub ty_name: String,
+    #[label = "unknown variant {name}"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(code(sema::variant_arity))]
+#[error("{ty_name}::{variant} carries {expected} value(s) but {found} were given")]
+pub struct VariantArityError {
+    pub ty_name: String,
+    pub variant: String,
+    pub expected: usize,
+    pub found: usize,
+    #[label = "expected {expected} value(s)"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(
+    code(sema::non_exhaustive_match),
+    help("add an arm for each missing variant or a wildcard `_` arm")
+)]
+#[error("non-exhaustive match on {ty_name}")]
+pub struct NonExhaustiveMatchError {
+    pub ty_name: String,
+    pub missing: String,
+    #[label = "missing {missing}"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(code(sema::unknown_method))]
+#[error("{ty_name} has no method named {name}")]
+pub struct UnknownMethodError {
+    pub name: String,
+    pub ty_name: String,
+    #[label = "unknown method {name}"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(
+    code(sema::private_member),
+    help("private members can only be used by the methods of {ty_name}")
+)]
+#[error("{name} is private")]
+pub struct PrivateMemberError {
+    pub name: String,
+    pub ty_name: String,
+    #[label = "{name} is a private member of {ty_name}"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(code(sema::missing_trait_methods))]
+#[error("{class} doesn't implement every method of {trait_name}")]
+pub struct MissingTraitMethodsError {
+    pub class: String,
+    pub trait_name: String,
+    pub methods: String,
+    #[label = "missing {methods}"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(
+    code(sema::invalid_override),
+    help("the class must implement {trait_name} and the method must share its name")
+)]
+#[error("{method} doesn't override {trait_name}::{overridden}")]
+pub struct InvalidOverrideError {
+    pub method: String,
+    pub trait_name: String,
+    pub overridden: String,
+    #[label = "invalid override"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(code(sema::cannot_infer_type), help("try adding a type annotation"))]
+#[error("type annotations needed")]
+pub struct CannotInferTypeError {
+    #[label = "cannot infer the type of this expression"]
+    pub span: Span,
+    #[source_code]
+    pub src: String,
+}
+
 #[derive(Error, Diagnostic, Debug)]
 #[diagnostic(code(sema::break_outside_loop))]
 #[error("break statement outside of loop")]
@@ -1,7 +1,7 @@
 use atlas_core::prelude::{Span, Spanned};
 use serde::Serialize;
 
-use super::ty::HirTy;
+use super::{signature::HirFunctionParameterSignature, stmt::HirBlock, ty::HirTy};
 
 #[derive(Debug, Clone, Serialize)]
 pub(crate) enum HirExpr<'hir> {
@@ -14,7 +14,16 @@ pub(crate) enum HirExpr<'hir> {
     IntegerLiteral(HirIntegerLiteralExpr<'hir>),
     BooleanLiteral(HirBooleanLiteralExpr<'hir>),
     UnsignedIntegererLiteral(HirUnsignedIntegerLiteralExpr<'hir>),
-    _StringLiteral(HirStringLiteralExpr<'hir>),
+    StringLiteral(HirStringLiteralExpr<'hir>),
+    NewObj(HirNewObjExpr<'hir>),
+    FieldAccess(HirFieldAccessExpr<'hir>),
+    ListLiteral(HirListLiteralExpr<'hir>),
+    Indexing(HirIndexingExpr<'hir>),
+    NewEnum(HirNewEnumExpr<'hir>),
+    Match(HirMatchExpr<'hir>),
+    Lambda(HirLambdaExpr<'hir>),
+    MethodCall(HirMethodCallExpr<'hir>),
+    Upcast(HirUpcastExpr<'hir>),
 }
 
 impl Spanned for HirExpr<'_> {
@@ -29,7 +38,16 @@ impl Spanned for HirExpr<'_> {
             HirExpr::HirBinaryOp(expr) => expr.span,
             HirExpr::Call(expr) => expr.span,
             HirExpr::Assign(expr) => expr.span,
-            HirExpr::_StringLiteral(expr) => expr.span,
+            HirExpr::StringLiteral(expr) => expr.span,
+            HirExpr::NewObj(expr) => expr.span,
+            HirExpr::FieldAccess(expr) => expr.span,
+            HirExpr::ListLiteral(expr) => expr.span,
+            HirExpr::Indexing(expr) => expr.span,
+            HirExpr::NewEnum(expr) => expr.span,
+            HirExpr::Match(expr) => expr.span,
+            HirExpr::Lambda(expr) => expr.span,
+            HirExpr::MethodCall(expr) => expr.span,
+            HirExpr::Upcast(expr) => expr.span,
         }
     }
 }
@@ -46,7 +64,16 @@ impl<'hir> HirExpr<'hir> {
             HirExpr::HirBinaryOp(expr) => expr.ty,
             HirExpr::Call(expr) => expr.ty,
             HirExpr::Assign(expr) => expr.ty,
-            HirExpr::_StringLiteral(expr) => expr.ty,
+            HirExpr::StringLiteral(expr) => expr.ty,
+            HirExpr::NewObj(expr) => expr.ty,
+            HirExpr::FieldAccess(expr) => expr.ty,
+            HirExpr::ListLiteral(expr) => expr.ty,
+            HirExpr::Indexing(expr) => expr.ty,
+            HirExpr::NewEnum(expr) => expr.ty,
+            HirExpr::Match(expr) => expr.ty,
+            HirExpr::Lambda(expr) => expr.ty,
+            HirExpr::MethodCall(expr) => expr.ty,
+            HirExpr::Upcast(expr) => expr.ty,
         }
     }
 }
@@ -65,6 +92,169 @@ pub(crate) struct HirStringLiteralExpr<'hir> {
     pub ty: &'hir HirTy<'hir>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirNewObjExpr<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    /// The fields are in the order they were written, not the order of the declaration
+    pub fields: Vec<HirFieldInit<'hir>>,
+    pub ty: &'hir HirTy<'hir>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirFieldInit<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    pub value: HirExpr<'hir>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirFieldAccessExpr<'hir> {
+    pub span: Span,
+    pub target: Box<HirExpr<'hir>>,
+    pub field: &'hir str,
+    pub field_span: Span,
+    /// The type of the field
+    pub ty: &'hir HirTy<'hir>,
+}
+
+/// `target.method(args)`, the receiver is passed as the first argument of the method
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirMethodCallExpr<'hir> {
+    pub span: Span,
+    pub target: Box<HirExpr<'hir>>,
+    pub method: &'hir str,
+    pub method_span: Span,
+    pub args: Vec<HirExpr<'hir>>,
+    pub dispatch: HirDispatch<'hir>,
+    /// The return type of the method
+    pub ty: &'hir HirTy<'hir>,
+}
+
+/// How a method call is resolved, filled in by the type checker
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) enum HirDispatch<'hir> {
+    Unresolved,
+    /// The receiver is a class, the method is called like a function by its mangled name
+    Static(&'hir str),
+    /// The receiver is a trait object, the method is looked up in its vtable at runtime
+    Dynamic(usize),
+}
+
+/// Turns a class instance into a trait object, inserted by the type checker wherever a class is
+/// used as one of the traits it implements
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirUpcastExpr<'hir> {
+    pub span: Span,
+    pub expr: Box<HirExpr<'hir>>,
+    pub class: &'hir str,
+    pub trait_name: &'hir str,
+    /// The trait type
+    pub ty: &'hir HirTy<'hir>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirListLiteralExpr<'hir> {
+    pub span: Span,
+    pub items: Vec<HirExpr<'hir>>,
+    pub ty: &'hir HirTy<'hir>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirIndexingExpr<'hir> {
+    pub span: Span,
+    pub target: Box<HirExpr<'hir>>,
+    pub index: Box<HirExpr<'hir>>,
+    /// The type of the element
+    pub ty: &'hir HirTy<'hir>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirLambdaExpr<'hir> {
+    pub span: Span,
+    pub params: Vec<&'hir HirFunctionParameterSignature<'hir>>,
+    /// Uninitialized until the type checker infers it from the body
+    pub ret_ty: &'hir HirTy<'hir>,
+    pub ret_ty_span: Option<Span>,
+    pub body: HirLambdaBody<'hir>,
+    /// Variables of the enclosing scopes used in the body, copied into the closure when it is created
+    pub captures: Vec<HirCapture<'hir>>,
+    pub ty: &'hir HirTy<'hir>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) enum HirLambdaBody<'hir> {
+    Block(HirBlock<'hir>),
+    Expr(Box<HirExpr<'hir>>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirCapture<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub ty: &'hir HirTy<'hir>,
+}
+
+/// `Shape::Circle(1.0)`, or `Shape::Empty` without arguments
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirNewEnumExpr<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    pub variant: &'hir str,
+    pub variant_span: Span,
+    pub args: Vec<HirExpr<'hir>>,
+    pub ty: &'hir HirTy<'hir>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirMatchExpr<'hir> {
+    pub span: Span,
+    pub target: Box<HirExpr<'hir>>,
+    pub arms: Vec<HirMatchArm<'hir>>,
+    /// The type shared by every arm, `unit` for block arms
+    pub ty: &'hir HirTy<'hir>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirMatchArm<'hir> {
+    pub span: Span,
+    pub pattern: HirPattern<'hir>,
+    pub body: HirMatchArmBody<'hir>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) enum HirMatchArmBody<'hir> {
+    Block(HirBlock<'hir>),
+    Expr(HirExpr<'hir>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) enum HirPattern<'hir> {
+    Wildcard(Span),
+    Variant(HirVariantPattern<'hir>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirVariantPattern<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    pub variant: &'hir str,
+    pub variant_span: Span,
+    pub bindings: Vec<HirPatternBinding<'hir>>,
+}
+
+/// A binding named `_` doesn't introduce a variable
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirPatternBinding<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub ty: &'hir HirTy<'hir>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub(crate) struct HirAssignExpr<'hir> {
     pub span: Span,
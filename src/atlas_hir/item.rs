@@ -19,7 +19,7 @@ pub(crate) struct HirImport<'hir> {
     pub path: &'hir str,
     pub path_span: Span,
 
-    /// As of now the alias is unsupported.
+    /// The functions of an aliased module are only reachable through `alias::name`.
     pub alias: Option<&'hir str>,
     pub alias_span: Option<Span>,
 }
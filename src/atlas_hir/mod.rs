@@ -19,6 +19,26 @@ pub mod ty;
 pub(crate) struct HirModuleBody<'hir> {
     pub functions: BTreeMap<&'hir str, HirFunction<'hir>>,
     pub imports: Vec<&'hir HirImport<'hir>>,
+    /// File the module was lowered from
+    #[serde(skip)]
+    pub file: Option<HirFile<'hir>>,
+    /// File of each function of an imported module, the others come from `file`
+    #[serde(skip)]
+    pub files: BTreeMap<&'hir str, HirFile<'hir>>,
+}
+
+/// A source file, the spans of the functions lowered from it point into `src`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HirFile<'hir> {
+    pub path: &'hir str,
+    pub src: &'hir str,
+}
+
+impl<'hir> HirModuleBody<'hir> {
+    /// File the function `name` was lowered from
+    pub fn file_of(&self, name: &str) -> Option<HirFile<'hir>> {
+        self.files.get(name).copied().or(self.file)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Default)]
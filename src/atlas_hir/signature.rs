@@ -7,10 +7,137 @@ use super::ty::{HirTy, HirUnitTy};
 
 /// An HirModuleSignature represents the API of a module.
 ///
-/// Currently only functions exist in the language.
+/// Currently functions, structs, enums, traits & classes exist in the language.
 #[derive(Debug, Clone, Serialize, Default)]
 pub(crate) struct HirModuleSignature<'hir> {
     pub functions: BTreeMap<&'hir str, &'hir HirFunctionSignature<'hir>>,
+    pub structs: BTreeMap<&'hir str, &'hir HirStructSignature<'hir>>,
+    pub enums: BTreeMap<&'hir str, &'hir HirEnumSignature<'hir>>,
+    pub traits: BTreeMap<&'hir str, &'hir HirTraitSignature<'hir>>,
+    pub classes: BTreeMap<&'hir str, &'hir HirClassSignature<'hir>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirTraitSignature<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    /// The position of a method is its slot in the vtables of the trait.
+    pub methods: Vec<&'hir HirTraitMethodSignature<'hir>>,
+}
+
+impl<'hir> HirTraitSignature<'hir> {
+    /// Returns the vtable slot of the method alongside its signature
+    pub fn method(&self, name: &str) -> Option<(usize, &'hir HirTraitMethodSignature<'hir>)> {
+        self.methods
+            .iter()
+            .enumerate()
+            .find(|(_, m)| m.name == name)
+            .map(|(i, m)| (i, *m))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirTraitMethodSignature<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    /// The signature without the `&self` parameter
+    pub signature: &'hir HirFunctionSignature<'hir>,
+}
+
+/// The fields of a class are registered as a struct of the same name, so both share their layout.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirClassSignature<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    pub traits: Vec<(&'hir str, Span)>,
+    pub methods: Vec<&'hir HirMethodSignature<'hir>>,
+}
+
+impl<'hir> HirClassSignature<'hir> {
+    pub fn method(&self, name: &str) -> Option<&'hir HirMethodSignature<'hir>> {
+        self.methods.iter().find(|m| m.name == name).copied()
+    }
+
+    pub fn implements(&self, trait_name: &str) -> bool {
+        self.traits.iter().any(|(t, _)| *t == trait_name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirMethodSignature<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    /// The name of the function the method is lowered to, e.g. `Rectangle::getArea`
+    pub function: &'hir str,
+    pub is_public: bool,
+    /// Static methods don't take `&self`
+    pub is_static: bool,
+    /// The trait & method given by `#[override(Trait::method)]`
+    pub overrides: Option<(&'hir str, &'hir str, Span)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirEnumSignature<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    /// The position of a variant is its discriminant in the VM.
+    pub variants: Vec<&'hir HirEnumVariantSignature<'hir>>,
+}
+
+impl<'hir> HirEnumSignature<'hir> {
+    /// Returns the discriminant of the variant alongside its signature
+    pub fn variant(&self, name: &str) -> Option<(usize, &'hir HirEnumVariantSignature<'hir>)> {
+        self.variants
+            .iter()
+            .enumerate()
+            .find(|(_, v)| v.name == name)
+            .map(|(i, v)| (i, *v))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirEnumVariantSignature<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    /// Types of the values carried by the variant
+    pub fields: Vec<&'hir HirTy<'hir>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirStructSignature<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    /// The fields are kept in declaration order, which is also their layout in the VM.
+    pub fields: Vec<&'hir HirStructFieldSignature<'hir>>,
+}
+
+impl<'hir> HirStructSignature<'hir> {
+    /// Returns the position of the field in the struct alongside its signature
+    pub fn field(&self, name: &str) -> Option<(usize, &'hir HirStructFieldSignature<'hir>)> {
+        self.fields
+            .iter()
+            .enumerate()
+            .find(|(_, f)| f.name == name)
+            .map(|(i, f)| (i, *f))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirStructFieldSignature<'hir> {
+    pub span: Span,
+    pub name: &'hir str,
+    pub name_span: Span,
+    pub ty: &'hir HirTy<'hir>,
+    pub ty_span: Span,
+    /// Struct fields are always public, class fields can be private
+    pub is_public: bool,
 }
 #[derive(Debug, Clone, Serialize)]
 pub(crate) struct HirFunctionSignature<'hir> {
@@ -21,7 +148,8 @@ pub(crate) struct HirFunctionSignature<'hir> {
     pub return_ty: &'hir HirTy<'hir>,
     /// The span of the return type, if it exists.
     pub return_ty_span: Option<Span>,
-    pub is_external: bool,
+    /// The library providing the function when it's declared with `extern`, e.g. `std/io`
+    pub library: Option<&'hir str>,
 }
 
 impl Default for HirFunctionSignature<'_> {
@@ -32,7 +160,7 @@ impl Default for HirFunctionSignature<'_> {
             type_params: Vec::new(),
             return_ty: &HirTy::Unit(HirUnitTy {}),
             return_ty_span: None,
-            is_external: false,
+            library: None,
         }
     }
 }
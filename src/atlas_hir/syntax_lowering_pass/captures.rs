@@ -0,0 +1,130 @@
+use crate::atlas_hir::{
+    expr::{HirCapture, HirExpr, HirLambdaBody, HirMatchArmBody, HirPattern},
+    stmt::{HirBlock, HirStatement},
+    ty::HirTy,
+};
+
+/// Collects the variables a lambda body uses without declaring them.
+///
+/// Functions called by name show up as well, the type checker drops every capture
+/// that doesn't resolve to a variable of the enclosing scopes.
+pub(super) struct CaptureCollector<'hir> {
+    bound: Vec<&'hir str>,
+    captures: Vec<HirCapture<'hir>>,
+    uninitialized: &'hir HirTy<'hir>,
+}
+
+impl<'hir> CaptureCollector<'hir> {
+    pub(super) fn new(params: Vec<&'hir str>, uninitialized: &'hir HirTy<'hir>) -> Self {
+        Self {
+            bound: params,
+            captures: Vec::new(),
+            uninitialized,
+        }
+    }
+
+    pub(super) fn collect(mut self, body: &HirLambdaBody<'hir>) -> Vec<HirCapture<'hir>> {
+        match body {
+            HirLambdaBody::Block(b) => self.visit_block(b),
+            HirLambdaBody::Expr(e) => self.visit_expr(e),
+        }
+        self.captures
+    }
+
+    fn use_name(&mut self, capture: &HirCapture<'hir>) {
+        if !self.bound.contains(&capture.name)
+            && !self.captures.iter().any(|c| c.name == capture.name)
+        {
+            self.captures.push(capture.clone());
+        }
+    }
+
+    fn visit_block(&mut self, block: &HirBlock<'hir>) {
+        let scope = self.bound.len();
+        for stmt in &block.statements {
+            self.visit_stmt(stmt);
+        }
+        self.bound.truncate(scope);
+    }
+
+    fn visit_stmt(&mut self, stmt: &HirStatement<'hir>) {
+        match stmt {
+            HirStatement::_Block(b) => self.visit_block(b),
+            HirStatement::Return(r) => self.visit_expr(&r.value),
+            HirStatement::Expr(e) => self.visit_expr(&e.expr),
+            HirStatement::Let(l) | HirStatement::Const(l) => {
+                self.visit_expr(&l.value);
+                self.bound.push(l.name);
+            }
+            HirStatement::IfElse(i) => {
+                self.visit_expr(&i.condition);
+                self.visit_block(&i.then_branch);
+                if let Some(else_branch) = &i.else_branch {
+                    self.visit_block(else_branch);
+                }
+            }
+            HirStatement::While(w) => {
+                self.visit_expr(&w.condition);
+                self.visit_block(&w.body);
+            }
+            HirStatement::Break(_) | HirStatement::Continue(_) => {}
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &HirExpr<'hir>) {
+        match expr {
+            HirExpr::Ident(i) => self.use_name(&HirCapture {
+                span: i.span,
+                name: i.name,
+                ty: self.uninitialized,
+            }),
+            HirExpr::Assign(a) => {
+                self.visit_expr(&a.lhs);
+                self.visit_expr(&a.rhs);
+            }
+            HirExpr::HirBinaryOp(b) => {
+                self.visit_expr(&b.lhs);
+                self.visit_expr(&b.rhs);
+            }
+            HirExpr::Unary(u) => self.visit_expr(&u.expr),
+            HirExpr::Call(c) => {
+                self.visit_expr(&c.callee);
+                c.args.iter().for_each(|arg| self.visit_expr(arg));
+            }
+            HirExpr::NewObj(o) => o.fields.iter().for_each(|f| self.visit_expr(&f.value)),
+            HirExpr::FieldAccess(f) => self.visit_expr(&f.target),
+            HirExpr::MethodCall(m) => {
+                self.visit_expr(&m.target);
+                m.args.iter().for_each(|arg| self.visit_expr(arg));
+            }
+            HirExpr::Upcast(u) => self.visit_expr(&u.expr),
+            HirExpr::ListLiteral(l) => l.items.iter().for_each(|item| self.visit_expr(item)),
+            HirExpr::Indexing(i) => {
+                self.visit_expr(&i.target);
+                self.visit_expr(&i.index);
+            }
+            HirExpr::NewEnum(e) => e.args.iter().for_each(|arg| self.visit_expr(arg)),
+            HirExpr::Match(m) => {
+                self.visit_expr(&m.target);
+                for arm in &m.arms {
+                    let scope = self.bound.len();
+                    if let HirPattern::Variant(p) = &arm.pattern {
+                        self.bound.extend(p.bindings.iter().map(|b| b.name));
+                    }
+                    match &arm.body {
+                        HirMatchArmBody::Block(b) => self.visit_block(b),
+                        HirMatchArmBody::Expr(e) => self.visit_expr(e),
+                    }
+                    self.bound.truncate(scope);
+                }
+            }
+            //The captures of a nested lambda have to be captured by this one first
+            HirExpr::Lambda(l) => l.captures.iter().for_each(|c| self.use_name(c)),
+            HirExpr::FloatLiteral(_)
+            | HirExpr::IntegerLiteral(_)
+            | HirExpr::BooleanLiteral(_)
+            | HirExpr::UnsignedIntegererLiteral(_)
+            | HirExpr::StringLiteral(_) => {}
+        }
+    }
+}
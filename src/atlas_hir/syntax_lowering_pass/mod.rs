@@ -1,5 +1,15 @@
+/// Capture analysis of lambdas
+mod captures;
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    rc::Rc,
+};
+
 use atlas_core::prelude::{Span, Spanned};
-use miette::{SourceOffset, SourceSpan};
+use miette::{Diagnostic, SourceOffset, SourceSpan};
 
 use crate::{
     atlas_frontend::{
@@ -7,34 +17,77 @@ use crate::{
         parser::{
             arena::AstArena,
             ast::{
-                AstBinaryOp, AstBlock, AstExpr, AstFunction, AstImport, AstItem, AstLiteral,
-                AstObjField, AstProgram, AstStatement, AstType, AstUnaryOp,
+                AstBinaryOp, AstBlock, AstClass, AstEnum, AstExpr, AstExternFunction, AstFunction,
+                AstIdentifier, AstImport, AstItem, AstLambdaExpr, AstLiteral, AstMatchExpr,
+                AstObjField, AstPattern, AstProgram, AstStatement, AstStaticAccessExpr, AstStruct,
+                AstTrait, AstType, AstUnaryOp, AstVisibility,
             },
         },
     },
     atlas_hir::{expr::HirAssignExpr, signature::HirFunctionSignature, stmt::HirLetStmt},
-    atlas_stdlib::{file::FILE_ATLAS, io::IO_ATLAS, math::MATH_ATLAS, string::STRING_ATLAS},
+    atlas_stdlib::{
+        file::FILE_ATLAS, io::IO_ATLAS, list::LIST_ATLAS, math::MATH_ATLAS, string::STRING_ATLAS,
+        time::TIME_ATLAS,
+    },
+    atlas_vm::native::{NativeFunction, NativeModule, NativeType},
 };
 
+use captures::CaptureCollector;
+
 use super::{
     arena::HirArena,
-    error::{HirError, HirResult, UnsupportedExpr, UnsupportedStatement},
+    error::{
+        AmbiguousImportError, CyclicImportError, DuplicateDefinitionError, HirError, HirResult,
+        ImportedModuleError, NativeConflictError, NativeSignatureMismatchError, UnknownModuleError,
+        UnknownNativeError, UnsupportedExpr, UnsupportedStatement, UnsupportedTypeError,
+    },
     expr::{
-        HirBinaryOp, HirBinaryOpExpr, HirBooleanLiteralExpr, HirExpr, HirFloatLiteralExpr,
-        HirFunctionCallExpr, HirIdentExpr, HirIntegerLiteralExpr, HirUnsignedIntegerLiteralExpr,
-        UnaryOp, UnaryOpExpr,
+        HirBinaryOp, HirBinaryOpExpr, HirBooleanLiteralExpr, HirDispatch, HirExpr,
+        HirFieldAccessExpr, HirFieldInit, HirFloatLiteralExpr, HirFunctionCallExpr, HirIdentExpr,
+        HirIndexingExpr, HirIntegerLiteralExpr, HirLambdaBody, HirLambdaExpr, HirListLiteralExpr,
+        HirMatchArm, HirMatchArmBody, HirMatchExpr, HirMethodCallExpr, HirNewEnumExpr,
+        HirNewObjExpr, HirPattern, HirPatternBinding, HirStringLiteralExpr,
+        HirUnsignedIntegerLiteralExpr, HirVariantPattern, UnaryOp, UnaryOpExpr,
     },
     item::HirFunction,
-    signature::{HirFunctionParameterSignature, HirModuleSignature, HirTypeParameterItemSignature},
+    signature::{
+        HirClassSignature, HirEnumSignature, HirEnumVariantSignature,
+        HirFunctionParameterSignature, HirMethodSignature, HirModuleSignature,
+        HirStructFieldSignature, HirStructSignature, HirTraitMethodSignature, HirTraitSignature,
+        HirTypeParameterItemSignature,
+    },
     stmt::{HirBlock, HirExprStmt, HirIfElseStmt, HirReturn, HirStatement, HirWhileStmt},
     ty::HirTy,
-    HirImport, HirModule, HirModuleBody,
+    type_check_pass::TypeChecker,
+    HirFile, HirImport, HirModule, HirModuleBody,
 };
 
 pub(crate) struct AstSyntaxLoweringPass<'ast, 'hir> {
     arena: &'hir HirArena<'hir>,
     ast: &'ast AstProgram<'ast>,
     ast_arena: &'ast AstArena<'ast>,
+    /// Type parameters of the function being lowered
+    generics: RefCell<Vec<&'hir str>>,
+    /// Files being lowered, from the root of the program down to the current one
+    import_stack: Vec<PathBuf>,
+    /// Modules of the host that can be imported
+    natives: &'ast [NativeModule],
+    /// Modules of the host imported so far, their functions can be declared with `extern`
+    imported_natives: RefCell<Vec<&'ast NativeModule>>,
+    /// The standard library being lowered, the runtime provides its `extern` functions
+    library: Option<&'ast str>,
+    /// The aliases the module is imported under, from the root of the program down: the types
+    /// of a module imported as `u` are named `u::Type`
+    namespace: Option<&'hir str>,
+    /// The structs, enums, traits & classes declared by the module itself
+    local_types: HashSet<&'ast str>,
+    /// Modules lowered so far by path & namespace, shared with the passes lowering the imports so
+    /// a module imported twice brings the same definitions
+    lowered: Rc<RefCell<HashMap<String, &'hir HirModule<'hir>>>>,
+    /// Where the functions of the module come from
+    defined_functions: RefCell<HashMap<&'hir str, Definition<'ast>>>,
+    /// Where the structs, enums & traits of the module come from, they share their names
+    defined_types: RefCell<HashMap<&'hir str, Definition<'ast>>>,
     //source code
     src: String,
 }
@@ -44,29 +97,89 @@ impl<'ast, 'hir> AstSyntaxLoweringPass<'ast, 'hir> {
         arena: &'hir HirArena<'hir>,
         ast: &'ast AstProgram,
         ast_arena: &'ast AstArena<'ast>,
+        path: PathBuf,
         src: String,
     ) -> Self {
         Self {
             arena,
             ast,
             ast_arena,
+            generics: RefCell::new(Vec::new()),
+            import_stack: vec![path.canonicalize().unwrap_or(path)],
+            natives: &[],
+            imported_natives: RefCell::new(Vec::new()),
+            library: None,
+            namespace: None,
+            local_types: ast
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    AstItem::Struct(s) => Some(s.name.name),
+                    AstItem::Enum(e) => Some(e.name.name),
+                    AstItem::Trait(t) => Some(t.name.name),
+                    AstItem::Class(c) => Some(c.name.name),
+                    _ => None,
+                })
+                .collect(),
+            lowered: Rc::new(RefCell::new(HashMap::new())),
+            defined_functions: RefCell::new(HashMap::new()),
+            defined_types: RefCell::new(HashMap::new()),
             src,
         }
     }
+    pub(crate) fn with_natives(mut self, natives: &'ast [NativeModule]) -> Self {
+        self.natives = natives;
+        self
+    }
+}
+
+/// Where a function or a type of the module comes from
+#[derive(Clone, Copy)]
+enum Definition<'ast> {
+    Local(Span),
+    Imported(&'ast AstImport<'ast>),
+}
+
+impl Definition<'_> {
+    fn span(&self) -> Span {
+        match self {
+            Definition::Local(span) => *span,
+            Definition::Imported(import) => import.span,
+        }
+    }
 }
 
 impl<'ast, 'hir> AstSyntaxLoweringPass<'ast, 'hir>
 where
     'ast: 'hir,
 {
-    pub(crate) fn lower(&self) -> HirResult<HirModule> {
-        let mut module_body = HirModuleBody::default();
+    pub(crate) fn lower(&self) -> HirResult<HirModule<'hir>> {
+        let mut module_body = HirModuleBody {
+            file: Some(HirFile {
+                path: self
+                    .arena
+                    .names()
+                    .get(&self.import_stack.last().unwrap().display().to_string()),
+                src: self.arena.intern(self.src.clone()),
+            }),
+            ..HirModuleBody::default()
+        };
         let mut module_signature = HirModuleSignature::default();
 
         let mut items = Vec::new();
         for item in self.ast.items {
             items.push(self.visit_item(&mut module_body, &mut module_signature, item)?);
         }
+        //The fields of the structs crossing to the host are only known once every item is lowered
+        if self.library.is_none() {
+            for item in self.ast.items {
+                if let AstItem::ExternFunction(e) = item {
+                    let (_, native) = self.native_provider(e)?;
+                    let signature = module_signature.functions[e.name.name];
+                    self.check_native_signature(native, e, &module_signature, signature)?;
+                }
+            }
+        }
         //println!("{:#?}", module_signature);
         Ok(HirModule {
             body: module_body,
@@ -83,14 +196,75 @@ where
             AstItem::Func(f) => {
                 let fun = self.visit_func(f)?;
                 let name = self.arena.names().get(f.name.name);
+                self.define_function(name, Definition::Local(f.name.span), false)?;
                 module_signature.functions.insert(name, fun.signature);
                 module_body.functions.insert(name, fun);
             }
             AstItem::Import(i) => {
-                let hir = self.visit_import(i)?;
-                let allocated_hir: &'hir HirModule<'hir> = self.arena.intern(hir);
+                let allocated_hir = self.visit_import(i)?;
+                //The functions of an aliased module live in their own namespace: `alias::name`.
+                //Its types & the methods of its classes are already named after it
+                let methods = allocated_hir
+                    .signature
+                    .classes
+                    .values()
+                    .flat_map(|c| c.methods.iter().map(|m| m.function))
+                    .collect::<HashSet<_>>();
+                let qualify = |name: &'hir str| match i.alias {
+                    Some(alias) if !methods.contains(name) => {
+                        self.arena.names().get(&format!("{}::{}", alias.name, name))
+                    }
+                    _ => name,
+                };
+                //A module imported twice, directly or not, brings the very same definitions
+                for (name, signature) in allocated_hir.signature.structs.iter() {
+                    let same = module_signature
+                        .structs
+                        .get(name)
+                        .is_some_and(|s| std::ptr::eq(*s, *signature));
+                    self.define_type(name, Definition::Imported(i), same)?;
+                    module_signature.structs.insert(name, *signature);
+                }
+                for (name, signature) in allocated_hir.signature.enums.iter() {
+                    let same = module_signature
+                        .enums
+                        .get(name)
+                        .is_some_and(|e| std::ptr::eq(*e, *signature));
+                    self.define_type(name, Definition::Imported(i), same)?;
+                    module_signature.enums.insert(name, *signature);
+                }
+                for (name, signature) in allocated_hir.signature.traits.iter() {
+                    let same = module_signature
+                        .traits
+                        .get(name)
+                        .is_some_and(|t| std::ptr::eq(*t, *signature));
+                    self.define_type(name, Definition::Imported(i), same)?;
+                    module_signature.traits.insert(name, *signature);
+                }
                 for (name, signature) in allocated_hir.signature.functions.iter() {
-                    module_signature.functions.insert(name, *signature);
+                    if *name != "main" {
+                        let name = qualify(name);
+                        let same = module_signature
+                            .functions
+                            .get(name)
+                            .is_some_and(|f| std::ptr::eq(*f, *signature));
+                        self.define_function(name, Definition::Imported(i), same)?;
+                        module_signature.functions.insert(name, *signature);
+                    }
+                }
+                for (name, signature) in allocated_hir.signature.classes.iter() {
+                    module_signature.classes.insert(name, *signature);
+                }
+                //Functions of user modules are compiled along the importing one
+                for (name, function) in allocated_hir.body.functions.iter() {
+                    if *name != "main" {
+                        let mut function = function.clone();
+                        function.name = qualify(name);
+                        if let Some(file) = allocated_hir.body.file_of(name) {
+                            module_body.files.insert(function.name, file);
+                        }
+                        module_body.functions.insert(function.name, function);
+                    }
                 }
                 allocated_hir.body.imports.iter().for_each(|i| {
                     module_body.imports.push(i);
@@ -98,10 +272,10 @@ where
             }
             AstItem::ExternFunction(e) => {
                 let name = self.arena.names().get(e.name.name);
+                let type_params = self.enter_generics(e.type_params);
                 let ty = self.visit_ty(e.ret)?;
 
                 let mut params: Vec<&HirFunctionParameterSignature<'hir>> = Vec::new();
-                let mut type_params: Vec<&HirTypeParameterItemSignature<'_>> = Vec::new();
 
                 for (arg_name, arg_ty) in e.args_name.iter().zip(e.args_ty.iter()) {
                     let hir_arg_ty = self.visit_ty(arg_ty)?;
@@ -114,149 +288,625 @@ where
                         ty: hir_arg_ty,
                         ty_span: arg_ty.span(),
                     }));
-
-                    type_params.push(self.arena.intern(HirTypeParameterItemSignature {
-                        span: arg_name.span,
-                        name: hir_arg_name,
-                        name_span: arg_name.span,
-                    }));
                 }
+                self.generics.borrow_mut().clear();
+                let library = match self.library {
+                    Some(library) => library,
+                    None => self.native_provider(e)?.0,
+                };
                 let hir = self.arena.intern(HirFunctionSignature {
                     span: e.span,
                     params,
                     type_params,
                     return_ty: ty,
                     return_ty_span: Some(e.ret.span()),
-                    is_external: true,
+                    library: Some(self.arena.names().get(library)),
                 });
+                self.define_function(name, Definition::Local(e.name.span), false)?;
                 module_signature.functions.insert(name, hir);
             }
+            AstItem::Struct(s) => {
+                let name = self.type_name(s.name.name);
+                let hir = self.visit_struct(s)?;
+                self.define_type(name, Definition::Local(s.name.span), false)?;
+                module_signature.structs.insert(name, hir);
+            }
+            AstItem::Enum(e) => {
+                let name = self.type_name(e.name.name);
+                let hir = self.visit_enum(e)?;
+                self.define_type(name, Definition::Local(e.name.span), false)?;
+                module_signature.enums.insert(name, hir);
+            }
+            AstItem::Trait(t) => {
+                let name = self.type_name(t.name.name);
+                let hir = self.visit_trait(t)?;
+                self.define_type(name, Definition::Local(t.name.span), false)?;
+                module_signature.traits.insert(name, hir);
+            }
+            AstItem::Class(c) => self.visit_class(module_body, module_signature, c)?,
             _ => {}
         }
         Ok(())
     }
 
-    fn visit_import(&self, node: &'ast AstImport<'ast>) -> HirResult<HirModule<'hir>> {
-        match node.path.split("/").last().unwrap() {
-            "io" => {
-                let ast: AstProgram<'ast> = parse(
-                    "atlas_stdlib/io.atlas",
-                    self.ast_arena,
-                    IO_ATLAS.to_string(),
-                )
-                .unwrap();
-                let allocated_ast = self.ast_arena.alloc(ast);
-                let hir = self.arena.intern(AstSyntaxLoweringPass::<'ast, 'hir>::new(
-                    self.arena,
-                    allocated_ast,
-                    self.ast_arena,
-                    IO_ATLAS.to_string(),
-                ));
-                let mut lower = hir.lower()?;
-
-                let hir_import: &'hir HirImport<'_> = self.arena.intern(HirImport {
-                    span: node.span,
-                    path: node.path,
-                    path_span: node.span,
-                    alias: None,
-                    alias_span: None,
+    /// The functions of a module imported without an alias mix with the others, a name clash
+    /// can only be told apart by aliasing it
+    fn define_function(
+        &self,
+        name: &'hir str,
+        definition: Definition<'ast>,
+        same: bool,
+    ) -> HirResult<()> {
+        let previous = self.defined_functions.borrow().get(name).copied();
+        let unaliased = [Some(definition), previous]
+            .into_iter()
+            .flatten()
+            .find_map(|d| match d {
+                Definition::Imported(import) if import.alias.is_none() => Some(import),
+                _ => None,
+            });
+        match (previous, unaliased) {
+            (Some(previous), Some(import)) if !same => {
+                let (span, previous) = (definition.span(), previous.span());
+                Err(HirError::AmbiguousImport(AmbiguousImportError {
+                    name: name.to_string(),
+                    import: format!(
+                        "import \"{}\" as {};",
+                        import.path,
+                        import
+                            .path
+                            .rsplit('/')
+                            .next()
+                            .unwrap_or(import.path)
+                            .trim_end_matches(".atlas")
+                    ),
+                    span: SourceSpan::new(
+                        SourceOffset::from(span.start()),
+                        span.end() - span.start(),
+                    ),
+                    previous: SourceSpan::new(
+                        SourceOffset::from(previous.start()),
+                        previous.end() - previous.start(),
+                    ),
+                    src: self.src.clone(),
+                }))
+            }
+            _ => self.define(&self.defined_functions, name, definition, same),
+        }
+    }
+
+    fn define_type(
+        &self,
+        name: &'hir str,
+        definition: Definition<'ast>,
+        same: bool,
+    ) -> HirResult<()> {
+        self.define(&self.defined_types, name, definition, same)
+    }
+
+    /// The name of a type of the module, `Point` is `u::Point` in a module imported as `u`.
+    ///
+    /// The types the module imports are named after its namespace as well, so `geo::Point`
+    /// names the same type from the module itself & from the one importing it.
+    fn type_name(&self, name: &str) -> &'hir str {
+        if let Some(namespace) = self.namespace {
+            let qualified = format!("{}::{}", namespace, name);
+            if self.local_types.contains(name)
+                || self.defined_types.borrow().contains_key(qualified.as_str())
+            {
+                return self.arena.names().get(&qualified);
+            }
+        }
+        self.arena.names().get(name)
+    }
+
+    /// The namespace of the module `node` imports, an alias nests in the current namespace
+    fn import_namespace(&self, node: &'ast AstImport<'ast>) -> Option<&'hir str> {
+        match (self.namespace, node.alias) {
+            (Some(namespace), Some(alias)) => Some(
+                self.arena
+                    .names()
+                    .get(&format!("{}::{}", namespace, alias.name)),
+            ),
+            (None, Some(alias)) => Some(self.arena.names().get(alias.name)),
+            (namespace, None) => namespace,
+        }
+    }
+
+    /// Modules are lowered once per namespace, their types are named after it
+    fn lowered_key(&self, node: &'ast AstImport<'ast>, path: String) -> String {
+        match self.import_namespace(node) {
+            Some(namespace) => format!("{} as {}", path, namespace),
+            None => path,
+        }
+    }
+
+    /// Records where `name` is defined, `same` tells it's the definition already known coming
+    /// through another import
+    fn define(
+        &self,
+        defined: &RefCell<HashMap<&'hir str, Definition<'ast>>>,
+        name: &'hir str,
+        definition: Definition<'ast>,
+        same: bool,
+    ) -> HirResult<()> {
+        let previous = defined.borrow_mut().insert(name, definition);
+        match previous {
+            Some(previous) if !same => {
+                let (span, previous) = (definition.span(), previous.span());
+                Err(HirError::DuplicateDefinition(DuplicateDefinitionError {
+                    name: name.to_string(),
+                    span: SourceSpan::new(
+                        SourceOffset::from(span.start()),
+                        span.end() - span.start(),
+                    ),
+                    previous: SourceSpan::new(
+                        SourceOffset::from(previous.start()),
+                        previous.end() - previous.start(),
+                    ),
+                    src: self.src.clone(),
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn visit_struct(
+        &self,
+        node: &'ast AstStruct<'ast>,
+    ) -> HirResult<&'hir HirStructSignature<'hir>> {
+        let fields = node
+            .fields
+            .iter()
+            .map(|field| {
+                let ty = self.visit_ty(field.ty)?;
+                let hir: &'hir HirStructFieldSignature<'hir> =
+                    self.arena.intern(HirStructFieldSignature {
+                        span: field.span,
+                        name: self.arena.names().get(field.name.name),
+                        name_span: field.name.span,
+                        ty,
+                        ty_span: field.ty.span(),
+                        is_public: true,
+                    });
+                Ok(hir)
+            })
+            .collect::<HirResult<Vec<_>>>()?;
+        let hir = self.arena.intern(HirStructSignature {
+            span: node.span,
+            name: self.type_name(node.name.name),
+            name_span: node.name.span,
+            fields,
+        });
+        Ok(hir)
+    }
+
+    fn visit_trait(&self, node: &'ast AstTrait<'ast>) -> HirResult<&'hir HirTraitSignature<'hir>> {
+        let methods = node
+            .methods
+            .iter()
+            .map(|method| {
+                let params = method
+                    .args
+                    .iter()
+                    .map(|arg| self.visit_func_param(arg))
+                    .collect::<HirResult<Vec<_>>>()?;
+                let signature = self.arena.intern(HirFunctionSignature {
+                    span: method.span,
+                    params,
+                    type_params: Vec::new(),
+                    return_ty: self.visit_ty(method.ret)?,
+                    return_ty_span: Some(method.ret.span()),
+                    library: None,
                 });
+                let hir: &'hir HirTraitMethodSignature<'hir> =
+                    self.arena.intern(HirTraitMethodSignature {
+                        span: method.span,
+                        name: self.arena.names().get(method.name.name),
+                        name_span: method.name.span,
+                        signature,
+                    });
+                Ok(hir)
+            })
+            .collect::<HirResult<Vec<_>>>()?;
+        let hir = self.arena.intern(HirTraitSignature {
+            span: node.span,
+            name: self.type_name(node.name.name),
+            name_span: node.name.span,
+            methods,
+        });
+        Ok(hir)
+    }
 
-                lower.body.imports.push(hir_import);
+    /// A class is lowered to a struct holding its fields & one function per method.
+    ///
+    /// The functions are named `Class::method` and the ones taking `&self` get it as their first
+    /// parameter.
+    fn visit_class(
+        &self,
+        module_body: &mut HirModuleBody<'hir>,
+        module_signature: &mut HirModuleSignature<'hir>,
+        node: &'ast AstClass<'ast>,
+    ) -> HirResult<()> {
+        let name = self.type_name(node.name.name);
+        let fields = node
+            .fields
+            .iter()
+            .map(|field| {
+                let ty = self.visit_ty(field.field.ty)?;
+                let hir: &'hir HirStructFieldSignature<'hir> =
+                    self.arena.intern(HirStructFieldSignature {
+                        span: field.span,
+                        name: self.arena.names().get(field.field.name.name),
+                        name_span: field.field.name.span,
+                        ty,
+                        ty_span: field.field.ty.span(),
+                        is_public: field.vis == AstVisibility::Public,
+                    });
+                Ok(hir)
+            })
+            .collect::<HirResult<Vec<_>>>()?;
+        self.define_type(name, Definition::Local(node.name.span), false)?;
+        module_signature.structs.insert(
+            name,
+            self.arena.intern(HirStructSignature {
+                span: node.span,
+                name,
+                name_span: node.name.span,
+                fields,
+            }),
+        );
 
-                Ok(lower)
+        let mut methods = Vec::new();
+        for method in node.methods {
+            let mut fun = self.visit_func(method.func)?;
+            let function = self.arena.names().get(&format!("{}::{}", name, fun.name));
+            if method.has_self {
+                let mut signature = fun.signature.clone();
+                signature.params.insert(
+                    0,
+                    self.arena.intern(HirFunctionParameterSignature {
+                        span: node.name.span,
+                        name: self.arena.names().get("self"),
+                        name_span: node.name.span,
+                        ty: self.arena.types().get_named_ty(name, node.name.span),
+                        ty_span: node.name.span,
+                    }),
+                );
+                fun.signature = self.arena.intern(signature);
             }
-            "math" => {
-                let ast: AstProgram<'ast> = parse(
-                    "atlas_stdlib/math.atlas",
-                    self.ast_arena,
-                    MATH_ATLAS.to_string(),
-                )
-                .unwrap();
-                let allocated_ast = self.ast_arena.alloc(ast);
-                let hir = self.arena.intern(AstSyntaxLoweringPass::<'ast, 'hir>::new(
-                    self.arena,
-                    allocated_ast,
-                    self.ast_arena,
-                    IO_ATLAS.to_string(),
-                ));
-                hir.lower()
-            }
-            "file" => {
-                let ast: AstProgram<'ast> = parse(
-                    "atlas_stdlib/file.atlas",
-                    self.ast_arena,
-                    FILE_ATLAS.to_string(),
-                )
-                .unwrap();
-                let allocated_ast = self.ast_arena.alloc(ast);
-                let hir = self.arena.intern(AstSyntaxLoweringPass::<'ast, 'hir>::new(
-                    self.arena,
-                    allocated_ast,
-                    self.ast_arena,
-                    IO_ATLAS.to_string(),
-                ));
-                hir.lower()
-            }
-            "list" => {
-                let ast: AstProgram<'ast> = parse(
-                    "atlas_stdlib/list.atlas",
-                    self.ast_arena,
-                    FILE_ATLAS.to_string(),
-                )
-                .unwrap();
-                let allocated_ast = self.ast_arena.alloc(ast);
-                let hir = self.arena.intern(AstSyntaxLoweringPass::<'ast, 'hir>::new(
-                    self.arena,
-                    allocated_ast,
-                    self.ast_arena,
-                    IO_ATLAS.to_string(),
-                ));
-                hir.lower()
-            }
-            "string" => {
-                let ast: AstProgram<'ast> = parse(
-                    "atlas_stdlib/string.atlas",
-                    self.ast_arena,
-                    STRING_ATLAS.to_string(),
-                )
-                .unwrap();
-                let allocated_ast = self.ast_arena.alloc(ast);
-                let hir = self.arena.intern(AstSyntaxLoweringPass::<'ast, 'hir>::new(
-                    self.arena,
-                    allocated_ast,
-                    self.ast_arena,
-                    IO_ATLAS.to_string(),
-                ));
-                hir.lower()
-            }
-            "time" => {
-                let ast: AstProgram<'ast> = parse(
-                    "atlas_stdlib/time.atlas",
-                    self.ast_arena,
-                    STRING_ATLAS.to_string(),
-                )
-                .unwrap();
-                let allocated_ast = self.ast_arena.alloc(ast);
-                let hir = self.arena.intern(AstSyntaxLoweringPass::<'ast, 'hir>::new(
-                    self.arena,
-                    allocated_ast,
-                    self.ast_arena,
-                    IO_ATLAS.to_string(),
-                ));
-                hir.lower()
-            }
-            _ => Err(HirError::UnsupportedStatement(UnsupportedStatement {
-                span: SourceSpan::new(
-                    SourceOffset::from(node.span.start()),
-                    node.span.end() - node.span.start(),
-                ),
-                stmt: format!("{:?}", node),
+            methods.push(&*self.arena.intern(HirMethodSignature {
+                span: method.span,
+                name: fun.name,
+                name_span: fun.name_span,
+                function,
+                is_public: method.vis == AstVisibility::Public,
+                is_static: !method.has_self,
+                overrides: method.overrides.map(|o| {
+                    (
+                        self.type_name(o.trait_name.name),
+                        self.arena.names().get(o.method.name),
+                        o.span,
+                    )
+                }),
+            }));
+            fun.name = function;
+            self.define_function(function, Definition::Local(method.func.name.span), false)?;
+            module_signature.functions.insert(function, fun.signature);
+            module_body.functions.insert(function, fun);
+        }
+
+        let traits = node
+            .traits
+            .iter()
+            .map(|t| (self.type_name(t.name), t.span))
+            .collect();
+        module_signature.classes.insert(
+            name,
+            self.arena.intern(HirClassSignature {
+                span: node.span,
+                name,
+                name_span: node.name.span,
+                traits,
+                methods,
+            }),
+        );
+        Ok(())
+    }
+
+    fn visit_enum(&self, node: &'ast AstEnum<'ast>) -> HirResult<&'hir HirEnumSignature<'hir>> {
+        let variants = node
+            .variants
+            .iter()
+            .map(|variant| {
+                let fields = variant
+                    .fields
+                    .iter()
+                    .map(|ty| self.visit_ty(ty))
+                    .collect::<HirResult<Vec<_>>>()?;
+                let hir: &'hir HirEnumVariantSignature<'hir> =
+                    self.arena.intern(HirEnumVariantSignature {
+                        span: variant.span,
+                        name: self.arena.names().get(variant.name.name),
+                        name_span: variant.name.span,
+                        fields,
+                    });
+                Ok(hir)
+            })
+            .collect::<HirResult<Vec<_>>>()?;
+        let hir = self.arena.intern(HirEnumSignature {
+            span: node.span,
+            name: self.type_name(node.name.name),
+            name_span: node.name.span,
+            variants,
+        });
+        Ok(hir)
+    }
+
+    fn visit_import(&self, node: &'ast AstImport<'ast>) -> HirResult<&'hir HirModule<'hir>> {
+        let modules = self.natives.iter().filter(|m| m.name == node.path);
+        if modules.clone().next().is_some() {
+            self.imported_natives.borrow_mut().extend(modules);
+            let mut hir = HirModule {
+                body: HirModuleBody::default(),
+                signature: HirModuleSignature::default(),
+            };
+            hir.body.imports.push(self.arena.intern(HirImport {
+                span: node.span,
+                path: node.path,
+                path_span: node.span,
+                alias: node.alias.map(|a| self.arena.names().get(a.name)),
+                alias_span: node.alias.map(|a| a.span),
+            }));
+            return Ok(self.arena.intern(hir));
+        }
+        let Some(std_module) = node.path.strip_prefix("std/") else {
+            return self.visit_user_import(node);
+        };
+        let key = self.lowered_key(node, node.path.to_string());
+        if let Some(module) = self.lowered.borrow().get(&key) {
+            return Ok(module);
+        }
+        let (file_name, source) = match std_module {
+            "io" => ("atlas_stdlib/io.atlas", IO_ATLAS),
+            "math" => ("atlas_stdlib/math.atlas", MATH_ATLAS),
+            "file" => ("atlas_stdlib/file.atlas", FILE_ATLAS),
+            "list" => ("atlas_stdlib/list.atlas", LIST_ATLAS),
+            "string" => ("atlas_stdlib/string.atlas", STRING_ATLAS),
+            "time" => ("atlas_stdlib/time.atlas", TIME_ATLAS),
+            _ => {
+                return Err(HirError::UnsupportedStatement(UnsupportedStatement {
+                    span: SourceSpan::new(
+                        SourceOffset::from(node.span.start()),
+                        node.span.end() - node.span.start(),
+                    ),
+                    stmt: format!("{:?}", node),
+                    src: self.src.clone(),
+                }))
+            }
+        };
+        let ast: AstProgram<'ast> =
+            parse(file_name, self.ast_arena, source.to_string()).map_err(|e| {
+                HirError::ImportedModule(ImportedModuleError {
+                    path: node.path.to_string(),
+                    span: SourceSpan::new(
+                        SourceOffset::from(node.span.start()),
+                        node.span.end() - node.span.start(),
+                    ),
+                    src: self.src.clone(),
+                    error: Box::new(e),
+                })
+            })?;
+        let allocated_ast = self.ast_arena.alloc(ast);
+        let mut lowering = AstSyntaxLoweringPass::<'ast, 'hir>::new(
+            self.arena,
+            allocated_ast,
+            self.ast_arena,
+            PathBuf::from(file_name),
+            source.to_string(),
+        );
+        lowering.library = Some(node.path);
+        lowering.namespace = self.import_namespace(node);
+        lowering.lowered = self.lowered.clone();
+        let mut lower = lowering.lower()?;
+
+        let hir_import: &'hir HirImport<'_> = self.arena.intern(HirImport {
+            span: node.span,
+            path: node.path,
+            path_span: node.span,
+            alias: node.alias.map(|a| self.arena.names().get(a.name)),
+            alias_span: node.alias.map(|a| a.span),
+        });
+
+        lower.body.imports.push(hir_import);
+
+        let lower = &*self.arena.intern(lower);
+        self.lowered.borrow_mut().insert(key, lower);
+        Ok(lower)
+    }
+
+    /// `import "utils/geometry"` loads `utils/geometry.atlas` relative to the importing file.
+    ///
+    /// The module is lowered & type checked on its own so its errors point to its own source.
+    fn visit_user_import(&self, node: &'ast AstImport<'ast>) -> HirResult<&'hir HirModule<'hir>> {
+        let span = SourceSpan::new(
+            SourceOffset::from(node.span.start()),
+            node.span.end() - node.span.start(),
+        );
+        let current = self.import_stack.last().unwrap();
+        let file = match node.path.ends_with(".atlas") {
+            true => current.with_file_name(node.path),
+            false => current.with_file_name(format!("{}.atlas", node.path)),
+        };
+        let unknown_module = || {
+            HirError::UnknownModule(UnknownModuleError {
+                path: node.path.to_string(),
+                file: file.display().to_string(),
+                span,
+                src: self.src.clone(),
+            })
+        };
+        let file = file.canonicalize().map_err(|_| unknown_module())?;
+        let key = self.lowered_key(node, file.display().to_string());
+        if let Some(module) = self.lowered.borrow().get(&key) {
+            return Ok(module);
+        }
+        let source = std::fs::read_to_string(&file).map_err(|_| unknown_module())?;
+
+        if let Some(pos) = self.import_stack.iter().position(|p| *p == file) {
+            let cycle = self.import_stack[pos..]
+                .iter()
+                .chain(std::iter::once(&file))
+                .map(|p| p.file_name().unwrap_or_default().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(HirError::CyclicImport(CyclicImportError {
+                path: node.path.to_string(),
+                cycle,
+                span,
+                src: self.src.clone(),
+            }));
+        }
+
+        let in_module = |error: Box<dyn Diagnostic + Send + Sync>| {
+            HirError::ImportedModule(ImportedModuleError {
+                path: node.path.to_string(),
+                span,
+                src: self.src.clone(),
+                error,
+            })
+        };
+        let file_name = self.ast_arena.alloc(file.display().to_string());
+        let ast =
+            parse(file_name, self.ast_arena, source.clone()).map_err(|e| in_module(Box::new(e)))?;
+        let allocated_ast = self.ast_arena.alloc(ast);
+        let mut lowering = AstSyntaxLoweringPass::<'ast, 'hir>::new(
+            self.arena,
+            allocated_ast,
+            self.ast_arena,
+            file.clone(),
+            source.clone(),
+        );
+        lowering.import_stack = self.import_stack.clone();
+        lowering.import_stack.push(file);
+        lowering.natives = self.natives;
+        lowering.namespace = self.import_namespace(node);
+        lowering.lowered = self.lowered.clone();
+        let mut hir = lowering.lower().map_err(|e| in_module(Box::new(e)))?;
+
+        TypeChecker::new(self.arena, source)
+            .check(&mut hir)
+            .map_err(|e| in_module(Box::new(e)))?;
+        let hir = &*self.arena.intern(hir);
+        self.lowered.borrow_mut().insert(key, hir);
+        Ok(hir)
+    }
+
+    /// The imported module of the host providing the function `node` declares, it has to be the
+    /// only one so the function called is the one whose signature is checked
+    fn native_provider(
+        &self,
+        node: &'ast AstExternFunction<'ast>,
+    ) -> HirResult<(&'ast str, &'ast NativeFunction)> {
+        let name = node.name.name;
+        let span = SourceSpan::new(
+            SourceOffset::from(node.span.start()),
+            node.span.end() - node.span.start(),
+        );
+        let mut providers: Vec<(&'ast str, &'ast NativeFunction)> = Vec::new();
+        for module in self.imported_natives.borrow().iter() {
+            if let Some(function) = module.get(name) {
+                //A module imported twice doesn't provide the function twice
+                if !providers.iter().any(|(_, f)| std::ptr::eq(*f, function)) {
+                    providers.push((module.name.as_str(), function));
+                }
+            }
+        }
+        match providers[..] {
+            [provider] => Ok(provider),
+            [] => Err(HirError::UnknownNative(UnknownNativeError {
+                name: name.to_string(),
+                span,
+                src: self.src.clone(),
+            })),
+            _ => Err(HirError::NativeConflict(NativeConflictError {
+                name: name.to_string(),
+                modules: providers
+                    .iter()
+                    .map(|(module, _)| format!("`{}`", module))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                span,
                 src: self.src.clone(),
             })),
         }
     }
 
+    /// An `extern` declaration of a native function of the host must match its Rust signature,
+    /// down to the fields of the structs it takes or returns
+    fn check_native_signature(
+        &self,
+        native: &NativeFunction,
+        node: &'ast AstExternFunction<'ast>,
+        module_signature: &HirModuleSignature<'hir>,
+        signature: &HirFunctionSignature<'hir>,
+    ) -> HirResult<()> {
+        let mismatch = |expected: String, span: Span| {
+            HirError::NativeSignatureMismatch(NativeSignatureMismatchError {
+                name: native.name.clone(),
+                signature: native.signature(),
+                expected,
+                span: SourceSpan::new(SourceOffset::from(span.start()), span.end() - span.start()),
+                src: self.src.clone(),
+            })
+        };
+        if signature.params.len() != native.params.len() {
+            return Err(mismatch(
+                format!("{} parameters", native.params.len()),
+                node.span,
+            ));
+        }
+        for (param, expected) in signature.params.iter().zip(native.params.iter()) {
+            if !Self::native_ty_matches(module_signature, expected, param.ty) {
+                return Err(mismatch(format!("{:#}", expected), param.ty_span));
+            }
+        }
+        if !Self::native_ty_matches(module_signature, &native.ret, signature.return_ty) {
+            return Err(mismatch(format!("{:#}", native.ret), node.ret.span()));
+        }
+        Ok(())
+    }
+
+    fn native_ty_matches(
+        module_signature: &HirModuleSignature<'hir>,
+        native: &NativeType,
+        ty: &HirTy,
+    ) -> bool {
+        match (native, ty) {
+            (NativeType::Int64, HirTy::Int64(_))
+            | (NativeType::Float64, HirTy::Float64(_))
+            | (NativeType::UInt64, HirTy::UInt64(_))
+            | (NativeType::Bool, HirTy::Boolean(_))
+            | (NativeType::Str, HirTy::String(_))
+            | (NativeType::Unit, HirTy::Unit(_)) => true,
+            (NativeType::List(inner), HirTy::List(l)) => {
+                Self::native_ty_matches(module_signature, inner, l.inner)
+            }
+            //The fields of a struct are mapped by position, so their names have to line up
+            (NativeType::Named(name, fields), HirTy::Named(n)) => {
+                name == n.name
+                    && module_signature.structs.get(n.name).is_none_or(|s| {
+                        s.fields
+                            .iter()
+                            .map(|f| f.name)
+                            .eq(fields.iter().map(String::as_str))
+                    })
+            }
+            (NativeType::Generic, HirTy::Generic(_)) => true,
+            _ => false,
+        }
+    }
+
     fn visit_block(&self, node: &'ast AstBlock<'ast>) -> HirResult<HirBlock<'hir>> {
         let statements = node
             .stmts
@@ -269,16 +919,16 @@ where
         })
     }
 
-    fn visit_stmt(&self, node: &'ast AstStatement<'ast>) -> HirResult<&'hir HirStatement<'hir>> {
+    fn visit_stmt(&self, node: &'ast AstStatement<'ast>) -> HirResult<HirStatement<'hir>> {
         match node {
             AstStatement::While(w) => {
                 let condition = self.visit_expr(w.condition)?;
                 let body = self.visit_block(w.body)?;
-                let hir = self.arena.intern(HirStatement::While(HirWhileStmt {
+                let hir = HirStatement::While(HirWhileStmt {
                     span: node.span(),
                     condition,
-                    body: self.arena.intern(body),
-                }));
+                    body,
+                });
                 Ok(hir)
             }
             AstStatement::Const(c) => {
@@ -288,14 +938,14 @@ where
                     None => self.arena.types().get_uninitialized_ty(),
                 };
                 let value = self.visit_expr(c.value)?;
-                let hir = self.arena.intern(HirStatement::Const(HirLetStmt {
+                let hir = HirStatement::Const(HirLetStmt {
                     span: node.span(),
                     name,
                     name_span: c.name.span,
                     ty,
-                    ty_span: c.ty.unwrap().span(),
+                    ty_span: c.ty.map_or(c.name.span, |ty| ty.span()),
                     value,
-                }));
+                });
                 Ok(hir)
             }
             AstStatement::Let(l) => {
@@ -305,57 +955,55 @@ where
                     None => self.arena.types().get_uninitialized_ty(),
                 };
                 let value = self.visit_expr(l.value)?;
-                let hir = self.arena.intern(HirStatement::Let(HirLetStmt {
+                let hir = HirStatement::Let(HirLetStmt {
                     span: node.span(),
                     name,
                     name_span: l.name.span,
                     ty,
-                    ty_span: l.ty.unwrap().span(),
+                    ty_span: l.ty.map_or(l.name.span, |ty| ty.span()),
                     value,
-                }));
+                });
                 Ok(hir)
             }
             AstStatement::IfElse(i) => {
                 let condition = self.visit_expr(i.condition)?;
-                let then_branch = self.arena.intern(self.visit_block(i.body)?);
-                //If you don't type, the compiler will use it as an "Option<&mut HirBlock<'hir>>"
-                //Which is dumb asf
-                let else_branch: Option<&HirBlock<'hir>> = match i.else_body {
-                    Some(else_body) => Some(self.arena.intern(self.visit_block(else_body)?)),
+                let then_branch = self.visit_block(i.body)?;
+                let else_branch = match i.else_body {
+                    Some(else_body) => Some(self.visit_block(else_body)?),
                     None => None,
                 };
-                let hir = self.arena.intern(HirStatement::IfElse(HirIfElseStmt {
+                let hir = HirStatement::IfElse(HirIfElseStmt {
                     span: node.span(),
                     condition,
                     then_branch,
                     else_branch,
-                }));
+                });
                 Ok(hir)
             }
             AstStatement::Break(b) => {
-                let hir = self.arena.intern(HirStatement::Break(b.span));
+                let hir = HirStatement::Break(b.span);
                 Ok(hir)
             }
             AstStatement::Continue(c) => {
-                let hir = self.arena.intern(HirStatement::Continue(c.span));
+                let hir = HirStatement::Continue(c.span);
                 Ok(hir)
             }
             //The parser really need a bit of work
             AstStatement::Return(r) => {
                 let expr = self.visit_expr(r.value)?;
-                let hir = self.arena.intern(HirStatement::Return(HirReturn {
+                let hir = HirStatement::Return(HirReturn {
                     span: node.span(),
-                    value: expr,
                     ty: expr.ty(),
-                }));
+                    value: expr,
+                });
                 Ok(hir)
             }
             AstStatement::Expr(e) => {
                 let expr = self.visit_expr(e)?;
-                let hir = self.arena.intern(HirStatement::Expr(HirExprStmt {
+                let hir = HirStatement::Expr(HirExprStmt {
                     span: node.span(),
                     expr,
-                }));
+                });
                 Ok(hir)
             }
             _ => Err(super::error::HirError::UnsupportedStatement(
@@ -371,45 +1019,83 @@ where
         }
     }
 
-    fn visit_expr(&self, node: &'ast AstExpr<'ast>) -> HirResult<&'hir HirExpr<'hir>> {
+    fn visit_expr(&self, node: &'ast AstExpr<'ast>) -> HirResult<HirExpr<'hir>> {
         match node {
             AstExpr::Assign(a) => {
                 let target = self.visit_expr(a.target)?;
                 let value = self.visit_expr(a.value)?;
-                let hir = self.arena.intern(HirExpr::Assign(HirAssignExpr {
+                let hir = HirExpr::Assign(HirAssignExpr {
                     span: node.span(),
-                    lhs: Box::new(target.clone()),
-                    rhs: Box::new(value.clone()),
+                    lhs: Box::new(target),
+                    rhs: Box::new(value),
                     ty: self.arena.types().get_uninitialized_ty(),
-                }));
+                });
                 Ok(hir)
             }
             AstExpr::BinaryOp(b) => {
                 let lhs = self.visit_expr(b.lhs)?;
                 let rhs = self.visit_expr(b.rhs)?;
                 let op = self.visit_bin_op(&b.op)?;
-                let hir = self.arena.intern(HirExpr::HirBinaryOp(HirBinaryOpExpr {
+                let hir = HirExpr::HirBinaryOp(HirBinaryOpExpr {
                     span: node.span(),
                     op,
                     op_span: Span::empty(),
-                    lhs: Box::new(lhs.clone()),
-                    rhs: Box::new(rhs.clone()),
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
                     ty: self.arena.types().get_uninitialized_ty(),
-                }));
+                });
                 Ok(hir)
             }
+            //The parser wraps every operand in a unary expression, even without operator
+            AstExpr::UnaryOp(u) if u.op.is_none() => self.visit_expr(u.expr),
             AstExpr::UnaryOp(u) => {
                 let expr = self.visit_expr(u.expr)?;
-                let hir = self.arena.intern(HirExpr::Unary(UnaryOpExpr {
+                let hir = HirExpr::Unary(UnaryOpExpr {
                     span: node.span(),
                     op: match u.op {
                         Some(AstUnaryOp::Neg) => Some(UnaryOp::Neg),
                         Some(AstUnaryOp::Not) => Some(UnaryOp::Not),
                         _ => None,
                     },
-                    expr: Box::new(expr.clone()),
                     ty: expr.ty(),
-                }));
+                    expr: Box::new(expr),
+                });
+                Ok(hir)
+            }
+            //`Enum::Variant(args)` builds an enum value rather than calling a function
+            AstExpr::Call(c) if matches!(c.callee, AstExpr::StaticAccess(_)) => {
+                let AstExpr::StaticAccess(s) = c.callee else {
+                    unreachable!()
+                };
+                let args = c
+                    .args
+                    .iter()
+                    .map(|arg| self.visit_expr(arg))
+                    .collect::<HirResult<Vec<_>>>()?;
+                Ok(self.visit_new_enum(node.span(), s, args))
+            }
+            AstExpr::StaticAccess(s) => Ok(self.visit_new_enum(node.span(), s, Vec::new())),
+            AstExpr::Match(m) => self.visit_match(m),
+            AstExpr::Lambda(l) => self.visit_lambda(l),
+            AstExpr::Call(c) if matches!(c.callee, AstExpr::FieldAccess(_)) => {
+                let AstExpr::FieldAccess(f) = c.callee else {
+                    unreachable!()
+                };
+                let target = self.visit_expr(f.target)?;
+                let args = c
+                    .args
+                    .iter()
+                    .map(|arg| self.visit_expr(arg))
+                    .collect::<HirResult<Vec<_>>>()?;
+                let hir = HirExpr::MethodCall(HirMethodCallExpr {
+                    span: node.span(),
+                    target: Box::new(target),
+                    method: self.arena.names().get(f.field.name),
+                    method_span: f.field.span,
+                    args,
+                    dispatch: HirDispatch::Unresolved,
+                    ty: self.arena.types().get_uninitialized_ty(),
+                });
                 Ok(hir)
             }
             AstExpr::Call(c) => {
@@ -417,70 +1103,113 @@ where
                 let args = c
                     .args
                     .iter()
-                    .map(|arg| self.visit_expr(arg).cloned())
+                    .map(|arg| self.visit_expr(arg))
                     .collect::<HirResult<Vec<_>>>()?;
-                let hir = self.arena.intern(HirExpr::Call(HirFunctionCallExpr {
+                let hir = HirExpr::Call(HirFunctionCallExpr {
                     span: node.span(),
-                    callee: Box::new(callee.clone()),
                     callee_span: callee.span(),
+                    callee: Box::new(callee),
                     args,
                     args_ty: Vec::new(),
                     ty: self.arena.types().get_uninitialized_ty(),
-                }));
+                });
                 Ok(hir)
             }
             AstExpr::Identifier(i) => {
-                let hir = self.arena.intern(HirExpr::Ident(HirIdentExpr {
+                let hir = HirExpr::Ident(HirIdentExpr {
                     name: self.arena.names().get(i.name),
                     span: i.span,
                     ty: self.arena.types().get_uninitialized_ty(),
-                }));
+                });
+                Ok(hir)
+            }
+            AstExpr::NewObj(o) => {
+                let (name, name_span) = match o.ty {
+                    AstType::Named(n) => (self.type_name(n.name.name), n.name.span),
+                    _ => unreachable!("the parser only builds object literals from a name"),
+                };
+                let fields = o
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        Ok(HirFieldInit {
+                            span: field.span,
+                            name: self.arena.names().get(field.name.name),
+                            name_span: field.name.span,
+                            value: self.visit_expr(field.value)?,
+                        })
+                    })
+                    .collect::<HirResult<Vec<_>>>()?;
+                let hir = HirExpr::NewObj(HirNewObjExpr {
+                    span: node.span(),
+                    name,
+                    name_span,
+                    fields,
+                    ty: self.arena.types().get_named_ty(name, name_span),
+                });
+                Ok(hir)
+            }
+            AstExpr::FieldAccess(f) => {
+                let target = self.visit_expr(f.target)?;
+                let hir = HirExpr::FieldAccess(HirFieldAccessExpr {
+                    span: node.span(),
+                    target: Box::new(target),
+                    field: self.arena.names().get(f.field.name),
+                    field_span: f.field.span,
+                    ty: self.arena.types().get_uninitialized_ty(),
+                });
+                Ok(hir)
+            }
+            AstExpr::Indexing(i) => {
+                let target = self.visit_expr(i.target)?;
+                let index = self.visit_expr(i.index)?;
+                let hir = HirExpr::Indexing(HirIndexingExpr {
+                    span: node.span(),
+                    target: Box::new(target),
+                    index: Box::new(index),
+                    ty: self.arena.types().get_uninitialized_ty(),
+                });
                 Ok(hir)
             }
 
             AstExpr::Literal(l) => {
                 let hir = match l {
-                    AstLiteral::Integer(i) => {
-                        self.arena
-                            .intern(HirExpr::IntegerLiteral(HirIntegerLiteralExpr {
-                                span: l.span(),
-                                value: i.value,
-                                ty: self.arena.types().get_integer64_ty(),
-                            }))
-                    }
-                    AstLiteral::Boolean(b) => {
-                        self.arena
-                            .intern(HirExpr::BooleanLiteral(HirBooleanLiteralExpr {
-                                span: l.span(),
-                                value: b.value,
-                                ty: self.arena.types().get_boolean_ty(),
-                            }))
-                    }
-                    AstLiteral::Float(f) => {
-                        self.arena
-                            .intern(HirExpr::FloatLiteral(HirFloatLiteralExpr {
-                                span: l.span(),
-                                value: f.value,
-                                ty: self.arena.types().get_float64_ty(),
-                            }))
-                    }
-                    AstLiteral::UnsignedIntegerer(u) => self.arena.intern(
+                    AstLiteral::Integer(i) => HirExpr::IntegerLiteral(HirIntegerLiteralExpr {
+                        span: l.span(),
+                        value: i.value,
+                        ty: self.arena.types().get_integer64_ty(),
+                    }),
+                    AstLiteral::Boolean(b) => HirExpr::BooleanLiteral(HirBooleanLiteralExpr {
+                        span: l.span(),
+                        value: b.value,
+                        ty: self.arena.types().get_boolean_ty(),
+                    }),
+                    AstLiteral::Float(f) => HirExpr::FloatLiteral(HirFloatLiteralExpr {
+                        span: l.span(),
+                        value: f.value,
+                        ty: self.arena.types().get_float64_ty(),
+                    }),
+                    AstLiteral::UnsignedIntegerer(u) => {
                         HirExpr::UnsignedIntegererLiteral(HirUnsignedIntegerLiteralExpr {
                             span: l.span(),
                             value: u.value,
                             ty: self.arena.types().get_uint64_ty(),
-                        }),
-                    ),
-                    _ => {
-                        return Err(super::error::HirError::UnsupportedExpr(UnsupportedExpr {
-                            span: SourceSpan::new(
-                                SourceOffset::from(node.span().start()),
-                                node.span().end() - node.span().start(),
-                            ),
-                            expr: format!("{:?}", node),
-                            src: self.src.clone(),
-                        }));
+                        })
                     }
+                    AstLiteral::String(s) => HirExpr::StringLiteral(HirStringLiteralExpr {
+                        span: l.span(),
+                        value: self.arena.names().get(s.value),
+                        ty: self.arena.types().get_str_ty(),
+                    }),
+                    AstLiteral::List(list) => HirExpr::ListLiteral(HirListLiteralExpr {
+                        span: l.span(),
+                        items: list
+                            .items
+                            .iter()
+                            .map(|item| self.visit_expr(item))
+                            .collect::<HirResult<Vec<_>>>()?,
+                        ty: self.arena.types().get_uninitialized_ty(),
+                    }),
                 };
                 Ok(hir)
             }
@@ -495,6 +1224,98 @@ where
         }
     }
 
+    fn visit_new_enum(
+        &self,
+        span: Span,
+        node: &'ast AstStaticAccessExpr<'ast>,
+        args: Vec<HirExpr<'hir>>,
+    ) -> HirExpr<'hir> {
+        let name = self.type_name(node.target.name);
+        HirExpr::NewEnum(HirNewEnumExpr {
+            span,
+            name,
+            name_span: node.target.span,
+            variant: self.arena.names().get(node.field.name),
+            variant_span: node.field.span,
+            args,
+            ty: self.arena.types().get_named_ty(name, node.target.span),
+        })
+    }
+
+    fn visit_lambda(&self, node: &'ast AstLambdaExpr<'ast>) -> HirResult<HirExpr<'hir>> {
+        let params = node
+            .args
+            .iter()
+            .map(|arg| self.visit_func_param(arg))
+            .collect::<HirResult<Vec<_>>>()?;
+        let (ret_ty, ret_ty_span) = match node.ret {
+            Some(ty) => (self.visit_ty(ty)?, Some(ty.span())),
+            None => (self.arena.types().get_uninitialized_ty(), None),
+        };
+        let body = match node.body {
+            AstExpr::Block(b) => HirLambdaBody::Block(self.visit_block(b)?),
+            e => HirLambdaBody::Expr(Box::new(self.visit_expr(e)?)),
+        };
+        let captures = CaptureCollector::new(
+            params.iter().map(|p| p.name).collect(),
+            self.arena.types().get_uninitialized_ty(),
+        )
+        .collect(&body);
+        Ok(HirExpr::Lambda(HirLambdaExpr {
+            span: node.span,
+            params,
+            ret_ty,
+            ret_ty_span,
+            body,
+            captures,
+            ty: self.arena.types().get_uninitialized_ty(),
+        }))
+    }
+
+    fn visit_match(&self, node: &'ast AstMatchExpr<'ast>) -> HirResult<HirExpr<'hir>> {
+        let target = self.visit_expr(node.target)?;
+        let arms = node
+            .arms
+            .iter()
+            .map(|arm| {
+                let pattern = match arm.pattern {
+                    AstPattern::Wildcard(span) => HirPattern::Wildcard(*span),
+                    AstPattern::Variant(v) => HirPattern::Variant(HirVariantPattern {
+                        span: v.span,
+                        name: self.type_name(v.enum_name.name),
+                        name_span: v.enum_name.span,
+                        variant: self.arena.names().get(v.variant.name),
+                        variant_span: v.variant.span,
+                        bindings: v
+                            .bindings
+                            .iter()
+                            .map(|b| HirPatternBinding {
+                                span: b.span,
+                                name: self.arena.names().get(b.name),
+                                ty: self.arena.types().get_uninitialized_ty(),
+                            })
+                            .collect(),
+                    }),
+                };
+                let body = match arm.body {
+                    AstExpr::Block(b) => HirMatchArmBody::Block(self.visit_block(b)?),
+                    e => HirMatchArmBody::Expr(self.visit_expr(e)?),
+                };
+                Ok(HirMatchArm {
+                    span: arm.span,
+                    pattern,
+                    body,
+                })
+            })
+            .collect::<HirResult<Vec<_>>>()?;
+        Ok(HirExpr::Match(HirMatchExpr {
+            span: node.span,
+            target: Box::new(target),
+            arms,
+            ty: self.arena.types().get_uninitialized_ty(),
+        }))
+    }
+
     fn visit_bin_op(&self, bin_op: &'ast AstBinaryOp) -> HirResult<HirBinaryOp> {
         let op = match bin_op {
             AstBinaryOp::Add => HirBinaryOp::Add,
@@ -513,12 +1334,21 @@ where
         Ok(op)
     }
 
-    fn visit_func(&self, node: &'ast AstFunction<'ast>) -> HirResult<HirFunction<'hir>> {
-        let type_parameters = node
-            .args
+    /// Makes the type parameters of a function visible to `visit_ty` until the function is lowered
+    fn enter_generics(
+        &self,
+        type_params: &'ast [&'ast AstIdentifier<'ast>],
+    ) -> Vec<&'hir HirTypeParameterItemSignature<'hir>> {
+        let type_params = type_params
             .iter()
-            .map(|arg| self.visit_type_param_item(arg))
-            .collect::<HirResult<Vec<_>>>();
+            .map(|param| self.visit_type_param_item(param))
+            .collect::<Vec<_>>();
+        *self.generics.borrow_mut() = type_params.iter().map(|param| param.name).collect();
+        type_params
+    }
+
+    fn visit_func(&self, node: &'ast AstFunction<'ast>) -> HirResult<HirFunction<'hir>> {
+        let type_parameters = self.enter_generics(node.type_params);
         let ret_type_span = node.ret.span();
         let ret_type = self.visit_ty(node.ret)?;
         let parameters = node
@@ -528,13 +1358,14 @@ where
             .collect::<HirResult<Vec<_>>>();
 
         let body = self.visit_block(node.body)?;
+        self.generics.borrow_mut().clear();
         let signature = self.arena.intern(HirFunctionSignature {
             span: node.span,
             params: parameters?,
-            type_params: type_parameters?,
+            type_params: type_parameters,
             return_ty: ret_type,
             return_ty_span: Some(ret_type_span),
-            is_external: false,
+            library: None,
         });
         let fun = HirFunction {
             span: node.span,
@@ -565,16 +1396,13 @@ where
 
     fn visit_type_param_item(
         &self,
-        node: &'ast AstObjField<'ast>,
-    ) -> HirResult<&'hir HirTypeParameterItemSignature<'hir>> {
-        let name = self.arena.names().get(node.name.name);
-
-        let hir = self.arena.intern(HirTypeParameterItemSignature {
+        node: &'ast AstIdentifier<'ast>,
+    ) -> &'hir HirTypeParameterItemSignature<'hir> {
+        self.arena.intern(HirTypeParameterItemSignature {
             span: node.span,
-            name,
-            name_span: node.name.span,
-        });
-        Ok(hir)
+            name: self.arena.names().get(node.name),
+            name_span: node.span,
+        })
     }
 
     fn visit_ty(&self, node: &'ast AstType<'ast>) -> HirResult<&'hir HirTy<'hir>> {
@@ -584,8 +1412,240 @@ where
             AstType::Float(_) => self.arena.types().get_float64_ty(),
             AstType::UnsignedIntegerer(_) => self.arena.types().get_uint64_ty(),
             AstType::Unit(_) => self.arena.types().get_unit_ty(),
-            _ => unimplemented!("visit_ty, {:?}", node),
+            AstType::String(_) => self.arena.types().get_str_ty(),
+            AstType::List(l) => {
+                let inner = self.visit_ty(l.inner)?;
+                self.arena.types().get_list_ty(inner)
+            }
+            AstType::Function(f) => {
+                let params = f
+                    .args
+                    .iter()
+                    .map(|arg| self.visit_ty(arg))
+                    .collect::<HirResult<Vec<_>>>()?;
+                let ret_ty = self.visit_ty(f.ret)?;
+                self.arena.types().get_function_ty(params, ret_ty)
+            }
+            AstType::Named(n) => {
+                let name = self.arena.names().get(n.name.name);
+                if self.generics.borrow().contains(&name) {
+                    self.arena.types().get_generic_ty(name, n.span)
+                } else {
+                    self.arena
+                        .types()
+                        .get_named_ty(self.type_name(n.name.name), n.span)
+                }
+            }
+            AstType::Pointer(_) | AstType::_Map(_) => {
+                return Err(HirError::UnsupportedType(UnsupportedTypeError {
+                    span: SourceSpan::new(
+                        SourceOffset::from(node.span().start()),
+                        node.span().end() - node.span().start(),
+                    ),
+                    ty: match node {
+                        AstType::Pointer(_) => "a reference type".to_string(),
+                        _ => "a map type".to_string(),
+                    },
+                    src: self.src.clone(),
+                }))
+            }
         };
         Ok(ty)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+
+    use super::*;
+    use crate::atlas_frontend::parse;
+
+    fn lower(source: &str) -> HirResult<()> {
+        let bump = Bump::new();
+        let ast_arena = AstArena::new(&bump);
+        let ast = parse("test.atlas", &ast_arena, source.to_string()).unwrap();
+        let hir_arena = HirArena::new();
+        let path = PathBuf::from("test.atlas");
+        AstSyntaxLoweringPass::new(&hir_arena, &ast, &ast_arena, path, source.to_string())
+            .lower()
+            .map(|_| ())
+    }
+
+    #[test]
+    fn reports_unsupported_types() {
+        let source = r#"
+func main() -> i64 {
+    let f: &i64 = 3;
+    return f;
+}
+"#;
+        match lower(source) {
+            Err(HirError::UnsupportedType(e)) => {
+                assert_eq!(
+                    &source[e.span.offset()..e.span.offset() + e.span.len()],
+                    "&i64"
+                )
+            }
+            _ => panic!("a reference type should be reported"),
+        }
+    }
+
+    /// Writes `files` to a directory of their own & lowers its `main.atlas`
+    fn lower_files(name: &str, files: &[(&str, &str)]) -> HirResult<()> {
+        let dir = std::env::temp_dir().join(format!("atlas_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, source) in files {
+            std::fs::write(dir.join(file), source).unwrap();
+        }
+        let path = dir.join("main.atlas");
+        let source = std::fs::read_to_string(&path).unwrap();
+        let bump = Bump::new();
+        let ast_arena = AstArena::new(&bump);
+        let file_name = path.display().to_string();
+        let ast = parse(&file_name, &ast_arena, source.clone()).unwrap();
+        let hir_arena = HirArena::new();
+        let result = AstSyntaxLoweringPass::new(&hir_arena, &ast, &ast_arena, path, source)
+            .lower()
+            .map(|_| ());
+        std::fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    #[test]
+    fn reports_duplicate_definitions() {
+        let twice = r#"
+func one() -> i64 {
+    return 1;
+}
+func one() -> i64 {
+    return 2;
+}
+func main() -> i64 {
+    return one();
+}
+"#;
+        assert!(matches!(
+            lower(twice),
+            Err(HirError::DuplicateDefinition(_))
+        ));
+        let types = r#"
+struct Shape {
+    x: i64;
+}
+enum Shape {
+    Dot,
+}
+func main() -> i64 {
+    return 0;
+}
+"#;
+        assert!(matches!(
+            lower(types),
+            Err(HirError::DuplicateDefinition(_))
+        ));
+        let util = r#"
+struct Point {
+    x: i64;
+}
+func value() -> i64 {
+    return 1;
+}
+"#;
+        let other = r#"
+func value() -> i64 {
+    return 3;
+}
+"#;
+        for main in [
+            r#"
+import "util"
+struct Point {
+    y: i64;
+}
+func main() -> i64 {
+    return value();
+}
+"#,
+            r#"
+import "util" as u;
+import "other" as u;
+func main() -> i64 {
+    return u::value();
+}
+"#,
+        ] {
+            let files = [
+                ("util.atlas", util),
+                ("other.atlas", other),
+                ("main.atlas", main),
+            ];
+            assert!(matches!(
+                lower_files("duplicate", &files),
+                Err(HirError::DuplicateDefinition(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn reports_ambiguous_imports() {
+        let word = r#"
+func len(w: str) -> i64 {
+    return 5;
+}
+"#;
+        let numbers = r#"
+func double(x: i64) -> i64 {
+    return x * 2;
+}
+func len(x: i64) -> i64 {
+    return double(x);
+}
+"#;
+        //Without an alias, the second `len` can't be told apart from the first one
+        for main in [
+            r#"
+import "word"
+import "numbers"
+func main() -> i64 {
+    return len(1);
+}
+"#,
+            r#"
+import "numbers"
+func len(x: i64) -> i64 {
+    return x;
+}
+func main() -> i64 {
+    return len(1);
+}
+"#,
+            r#"
+func len(x: i64) -> i64 {
+    return x;
+}
+import "numbers"
+func main() -> i64 {
+    return len(1);
+}
+"#,
+            r#"
+import "std/list"
+import "word"
+func main() -> i64 {
+    return len("a");
+}
+"#,
+        ] {
+            let files = [
+                ("word.atlas", word),
+                ("numbers.atlas", numbers),
+                ("main.atlas", main),
+            ];
+            assert!(matches!(
+                lower_files("ambiguous", &files),
+                Err(HirError::AmbiguousImport(_))
+            ));
+        }
+    }
+}
@@ -37,6 +37,12 @@ impl HirTyId {
         Self(hasher.finish())
     }
 
+    pub fn compute_str_ty_id() -> Self {
+        let mut hasher = DefaultHasher::new();
+        0x05.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+
     pub fn compute_function_ty_id(ret_ty: &HirTyId, params: &[HirTyId]) -> Self {
         let mut hasher = DefaultHasher::new();
 
@@ -50,11 +56,23 @@ impl HirTyId {
         Self(hasher.finish())
     }
 
+    pub fn compute_list_ty_id(inner: &HirTyId) -> Self {
+        let mut hasher = DefaultHasher::new();
+        (0x11, inner).hash(&mut hasher);
+        Self(hasher.finish())
+    }
+
     pub fn compute_name_ty_id(name: &str) -> Self {
         let mut hasher = DefaultHasher::new();
         (0x10, name).hash(&mut hasher);
         Self(hasher.finish())
     }
+
+    pub fn compute_generic_ty_id(name: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        (0x12, name).hash(&mut hasher);
+        Self(hasher.finish())
+    }
 }
 
 impl<'hir> From<&'hir HirTy<'hir>> for HirTyId {
@@ -65,10 +83,17 @@ impl<'hir> From<&'hir HirTy<'hir>> for HirTyId {
             HirTy::UInt64(_) => Self::compute_uint64_ty_id(),
             HirTy::Boolean(_) => Self::compute_boolean_ty_id(),
             HirTy::Unit(_) => Self::compute_unit_ty_id(),
-            HirTy::_Named(ty) => HirTyId::compute_name_ty_id(ty.name),
+            HirTy::String(_) => Self::compute_str_ty_id(),
+            HirTy::Named(ty) => HirTyId::compute_name_ty_id(ty.name),
+            HirTy::List(ty) => HirTyId::compute_list_ty_id(&HirTyId::from(ty.inner)),
+            HirTy::Generic(ty) => HirTyId::compute_generic_ty_id(ty.name),
             HirTy::Uninitialized(_) => Self::compute_uninitialized_ty_id(),
-            HirTy::_Function(f) => {
-                let parameters = f.params.iter().map(HirTyId::from).collect::<Vec<_>>();
+            HirTy::Function(f) => {
+                let parameters = f
+                    .params
+                    .iter()
+                    .map(|p| HirTyId::from(*p))
+                    .collect::<Vec<_>>();
                 let ret_ty = HirTyId::from(f.ret_ty);
                 HirTyId::compute_function_ty_id(&ret_ty, &parameters)
             }
@@ -83,10 +108,12 @@ pub(crate) enum HirTy<'hir> {
     UInt64(HirUnsignedIntTy),
     Unit(HirUnitTy),
     Boolean(HirBooleanTy),
-    _Named(HirNamedTy<'hir>),
+    String(HirStringTy),
+    Named(HirNamedTy<'hir>),
+    List(HirListTy<'hir>),
+    Generic(HirGenericTy<'hir>),
     Uninitialized(HirUninitializedTy),
-
-    _Function(HirFunctionTy<'hir>),
+    Function(HirFunctionTy<'hir>),
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -107,10 +134,25 @@ pub(crate) struct HirUnitTy {}
 #[derive(Debug, Clone, Serialize)]
 pub(crate) struct HirBooleanTy {}
 
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirStringTy {}
+
 #[derive(Debug, Clone, Serialize)]
 pub(crate) struct HirFunctionTy<'hir> {
     pub ret_ty: &'hir HirTy<'hir>,
-    pub params: Vec<HirTy<'hir>>,
+    pub params: Vec<&'hir HirTy<'hir>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirListTy<'hir> {
+    pub inner: &'hir HirTy<'hir>,
+}
+
+/// A type parameter of the enclosing function, e.g. the `T` in `func first<T>(l: [T]) -> T`
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HirGenericTy<'hir> {
+    pub name: &'hir str,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize)]
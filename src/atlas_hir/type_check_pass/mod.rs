@@ -12,11 +12,20 @@ use crate::atlas_hir::expr;
 use super::{
     arena::HirArena,
     error::{
-        FunctionTypeMismatchError, HirError, HirResult, TryingToMutateImmutableVariableError,
-        TryingToNegateUnsignedError, TypeMismatchError, UnknownTypeError,
+        BreakOutsideLoopError, CannotInferTypeError, ContinueOutsideLoopError, DuplicateFieldError,
+        FunctionTypeMismatchError, HirError, HirResult, InvalidOverrideError, MissingFieldsError,
+        MissingTraitMethodsError, NonExhaustiveMatchError, PrivateMemberError,
+        TryingToMutateImmutableVariableError, TryingToNegateUnsignedError, TypeMismatchError,
+        UnknownFieldError, UnknownMethodError, UnknownTypeError, UnknownVariantError,
+        UnsupportedExpr, UnsupportedStatement, VariantArityError,
     },
-    expr::{HirBinaryOp, HirExpr},
-    stmt::HirStatement,
+    expr::{
+        HirBinaryOp, HirBooleanLiteralExpr, HirDispatch, HirExpr, HirFunctionCallExpr,
+        HirIdentExpr, HirLambdaBody, HirLambdaExpr, HirMatchArmBody, HirMatchExpr,
+        HirMethodCallExpr, HirNewEnumExpr, HirPattern, HirUpcastExpr,
+    },
+    signature::{HirClassSignature, HirEnumSignature, HirFunctionSignature},
+    stmt::{HirLetStmt, HirStatement},
     ty::{HirTy, HirTyId},
     HirFunction, HirModule, HirModuleSignature,
 };
@@ -30,6 +39,10 @@ pub(crate) struct TypeChecker<'hir> {
     context: Vec<HashMap<String, ContextFunction<'hir>>>,
     signature: HirModuleSignature<'hir>,
     current_func_name: Option<&'hir str>,
+    /// Return type (and where it comes from) of the lambdas being checked, innermost last
+    lambda_returns: Vec<(&'hir HirTy<'hir>, Span)>,
+    /// Number of loops around the statement being checked, the body of a lambda starts over
+    loops: usize,
     // Source code
     src: String,
 }
@@ -118,19 +131,839 @@ impl<'hir> TypeChecker<'hir> {
             src,
             signature: HirModuleSignature::default(),
             current_func_name: None,
+            lambda_returns: Vec::new(),
+            loops: 0,
         }
     }
 
-    pub fn check(&mut self, hir: &HirModule<'hir>) -> HirResult<()> {
+    /// Checks the whole module and annotates every expression with its type.
+    pub fn check(&mut self, hir: &mut HirModule<'hir>) -> HirResult<()> {
         self.signature = hir.signature.clone();
-        for func in &hir.body.functions {
+        for s in self.signature.structs.values() {
+            for field in &s.fields {
+                self.check_ty_exists(field.ty, field.ty_span)?;
+            }
+        }
+        for e in self.signature.enums.values() {
+            for variant in &e.variants {
+                for field in &variant.fields {
+                    self.check_ty_exists(field, variant.span)?;
+                }
+            }
+        }
+        for t in self.signature.traits.values() {
+            for method in &t.methods {
+                for param in &method.signature.params {
+                    self.check_ty_exists(param.ty, param.ty_span)?;
+                }
+            }
+        }
+        for class in self.signature.classes.values() {
+            self.check_class(class)?;
+        }
+        for func in hir.body.functions.iter_mut() {
             self.current_func_name = Some(func.0);
             self.check_func(func.1)?;
         }
         Ok(())
     }
 
-    pub fn check_func(&mut self, func: &HirFunction<'hir>) -> HirResult<()> {
+    /// Every trait of a class has to be fully implemented by methods taking `&self`,
+    /// with the exact same signature.
+    fn check_class(&self, class: &'hir HirClassSignature<'hir>) -> HirResult<()> {
+        for (trait_name, span) in &class.traits {
+            let trait_sig = match self.signature.traits.get(trait_name) {
+                Some(t) => *t,
+                None => {
+                    return Err(HirError::UnknownType(UnknownTypeError {
+                        name: trait_name.to_string(),
+                        span: SourceSpan::new(
+                            SourceOffset::from(span.start()),
+                            span.end() - span.start(),
+                        ),
+                        src: self.src.clone(),
+                    }))
+                }
+            };
+            let mut missing = Vec::new();
+            for trait_method in &trait_sig.methods {
+                let method = match class.method(trait_method.name) {
+                    Some(m) if !m.is_static => m,
+                    _ => {
+                        missing.push(trait_method.name);
+                        continue;
+                    }
+                };
+                let expected = trait_method.signature;
+                let actual = self.signature.functions[method.function];
+                let same_params = actual.params.len() == expected.params.len() + 1
+                    && actual.params[1..]
+                        .iter()
+                        .zip(expected.params.iter())
+                        .all(|(a, e)| HirTyId::from(a.ty) == HirTyId::from(e.ty));
+                if !same_params
+                    || HirTyId::from(actual.return_ty) != HirTyId::from(expected.return_ty)
+                {
+                    return Err(HirError::TypeMismatch(TypeMismatchError {
+                        actual_type: format!("{:?}", actual),
+                        actual_loc: SourceSpan::new(
+                            SourceOffset::from(method.span.start()),
+                            method.span.end() - method.span.start(),
+                        ),
+                        expected_type: format!("{:?}", expected),
+                        expected_loc: SourceSpan::new(
+                            SourceOffset::from(trait_method.span.start()),
+                            trait_method.span.end() - trait_method.span.start(),
+                        ),
+                        src: self.src.clone(),
+                    }));
+                }
+            }
+            if !missing.is_empty() {
+                return Err(HirError::MissingTraitMethods(MissingTraitMethodsError {
+                    class: class.name.to_string(),
+                    trait_name: trait_name.to_string(),
+                    methods: missing.join(", "),
+                    span: SourceSpan::new(
+                        SourceOffset::from(class.name_span.start()),
+                        class.name_span.end() - class.name_span.start(),
+                    ),
+                    src: self.src.clone(),
+                }));
+            }
+        }
+        for method in &class.methods {
+            if let Some((trait_name, overridden, span)) = method.overrides {
+                let is_valid = class.implements(trait_name)
+                    && overridden == method.name
+                    && self
+                        .signature
+                        .traits
+                        .get(trait_name)
+                        .is_some_and(|t| t.method(overridden).is_some());
+                if !is_valid {
+                    return Err(HirError::InvalidOverride(InvalidOverrideError {
+                        method: method.name.to_string(),
+                        trait_name: trait_name.to_string(),
+                        overridden: overridden.to_string(),
+                        span: SourceSpan::new(
+                            SourceOffset::from(span.start()),
+                            span.end() - span.start(),
+                        ),
+                        src: self.src.clone(),
+                    }));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The class whose method is being checked, if any
+    fn current_class(&self) -> Option<&'hir str> {
+        //Methods are named `Class::method`, the class being named after its module: `alias::Class`
+        let (class, _) = self.current_func_name?.rsplit_once("::")?;
+        self.signature
+            .classes
+            .get_key_value(class)
+            .map(|(name, _)| *name)
+    }
+
+    /// Functions are looked up in the namespace of the function being checked, then in the
+    /// enclosing ones: `sq` called from `geo::area` is `geo::sq` if it exists, `sq` otherwise.
+    ///
+    /// Classes aren't namespaces, a method has to name the other methods of its class.
+    fn resolve_function(&self, name: &str) -> Option<&'hir str> {
+        let mut namespace = self
+            .current_func_name
+            .and_then(|f| f.rsplit_once("::"))
+            .map(|(namespace, _)| namespace);
+        while let Some(current) = namespace {
+            if !self.signature.classes.contains_key(current) {
+                let qualified = format!("{}::{}", current, name);
+                if let Some((function, _)) =
+                    self.signature.functions.get_key_value(qualified.as_str())
+                {
+                    return Some(function);
+                }
+            }
+            namespace = current.rsplit_once("::").map(|(parent, _)| parent);
+        }
+        self.signature
+            .functions
+            .get_key_value(name)
+            .map(|(function, _)| *function)
+    }
+
+    /// Private members can only be used from the methods of their own class
+    fn check_access(&self, class: &str, is_public: bool, name: &str, span: Span) -> HirResult<()> {
+        if is_public || self.current_class() == Some(class) {
+            return Ok(());
+        }
+        Err(HirError::PrivateMember(PrivateMemberError {
+            name: name.to_string(),
+            ty_name: class.to_string(),
+            span: SourceSpan::new(SourceOffset::from(span.start()), span.end() - span.start()),
+            src: self.src.clone(),
+        }))
+    }
+
+    /// A class is accepted wherever one of its traits is expected, it is then wrapped in a trait
+    /// object. Returns the type of the expression once wrapped.
+    fn upcast(
+        &self,
+        expected: &'hir HirTy<'hir>,
+        actual: &'hir HirTy<'hir>,
+        expr: &mut HirExpr<'hir>,
+    ) -> &'hir HirTy<'hir> {
+        let (HirTy::Named(trait_ty), HirTy::Named(class_ty)) = (expected, actual) else {
+            return actual;
+        };
+        match self.signature.classes.get(class_ty.name) {
+            Some(class)
+                if self.signature.traits.contains_key(trait_ty.name)
+                    && class.implements(trait_ty.name) =>
+            {
+                let span = expr.span();
+                let inner = std::mem::replace(
+                    expr,
+                    HirExpr::BooleanLiteral(HirBooleanLiteralExpr {
+                        value: false,
+                        span,
+                        ty: expected,
+                    }),
+                );
+                *expr = HirExpr::Upcast(HirUpcastExpr {
+                    span,
+                    expr: Box::new(inner),
+                    class: class.name,
+                    trait_name: trait_ty.name,
+                    ty: expected,
+                });
+                expected
+            }
+            _ => actual,
+        }
+    }
+
+    /// Named types have to refer to a struct, an enum, a trait or a class known by the module.
+    fn check_ty_exists(&self, ty: &'hir HirTy<'hir>, span: Span) -> HirResult<()> {
+        match ty {
+            HirTy::List(l) => self.check_ty_exists(l.inner, span),
+            HirTy::Function(f) => {
+                for param in &f.params {
+                    self.check_ty_exists(param, span)?;
+                }
+                self.check_ty_exists(f.ret_ty, span)
+            }
+            HirTy::Named(n)
+                if !self.signature.structs.contains_key(n.name)
+                    && !self.signature.enums.contains_key(n.name)
+                    && !self.signature.traits.contains_key(n.name) =>
+            {
+                Err(HirError::UnknownType(UnknownTypeError {
+                    name: n.name.to_string(),
+                    span: SourceSpan::new(
+                        SourceOffset::from(span.start()),
+                        span.end() - span.start(),
+                    ),
+                    src: self.src.clone(),
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// A list literal takes the type it is assigned to: an empty one has no item to infer it
+    /// from & the items of a list of traits are upcast to the trait. The items of a list literal
+    /// are hinted as well, e.g. `[[], l]`.
+    fn hint_list(expected: &'hir HirTy<'hir>, expr: &mut HirExpr<'hir>) {
+        if let (HirExpr::ListLiteral(l), HirTy::List(list)) = (expr, expected) {
+            l.ty = expected;
+            for item in l.items.iter_mut() {
+                Self::hint_list(list.inner, item);
+            }
+        }
+    }
+
+    /// Matches the type of a parameter against the type of an argument,
+    /// binding the type parameters of the callee on the way.
+    fn unify(
+        param: &'hir HirTy<'hir>,
+        arg: &'hir HirTy<'hir>,
+        type_args: &mut HashMap<&'hir str, &'hir HirTy<'hir>>,
+    ) -> bool {
+        match (param, arg) {
+            (HirTy::Generic(g), _) => match type_args.get(g.name) {
+                Some(bound) => HirTyId::from(*bound) == HirTyId::from(arg),
+                None => {
+                    type_args.insert(g.name, arg);
+                    true
+                }
+            },
+            (HirTy::List(p), HirTy::List(a)) => Self::unify(p.inner, a.inner, type_args),
+            (HirTy::Function(p), HirTy::Function(a)) => {
+                p.params.len() == a.params.len()
+                    && p.params
+                        .iter()
+                        .zip(a.params.iter())
+                        .all(|(p, a)| Self::unify(p, a, type_args))
+                    && Self::unify(p.ret_ty, a.ret_ty, type_args)
+            }
+            _ => HirTyId::from(param) == HirTyId::from(arg),
+        }
+    }
+
+    /// Replaces the type parameters by their inferred type, `None` if one of them isn't known yet
+    fn substitute(
+        &self,
+        ty: &'hir HirTy<'hir>,
+        type_args: &HashMap<&'hir str, &'hir HirTy<'hir>>,
+    ) -> Option<&'hir HirTy<'hir>> {
+        match ty {
+            HirTy::Generic(g) => type_args.get(g.name).copied(),
+            HirTy::List(l) => Some(
+                self.arena
+                    .types()
+                    .get_list_ty(self.substitute(l.inner, type_args)?),
+            ),
+            HirTy::Function(f) => {
+                let params = f
+                    .params
+                    .iter()
+                    .map(|p| self.substitute(p, type_args))
+                    .collect::<Option<Vec<_>>>()?;
+                let ret_ty = self.substitute(f.ret_ty, type_args)?;
+                Some(self.arena.types().get_function_ty(params, ret_ty))
+            }
+            _ => Some(ty),
+        }
+    }
+
+    fn check_lambda(&mut self, l: &mut HirLambdaExpr<'hir>) -> HirResult<&'hir HirTy<'hir>> {
+        //Only variables of the enclosing scopes are captured, the other names are functions
+        let func_ctx = self
+            .context
+            .last()
+            .unwrap()
+            .get(self.current_func_name.unwrap())
+            .unwrap();
+        l.captures
+            .retain_mut(|capture| match func_ctx.get(capture.name) {
+                Some(var) => {
+                    capture.ty = var.ty;
+                    true
+                }
+                None => false,
+            });
+        for param in &l.params {
+            self.check_ty_exists(param.ty, param.ty_span)?;
+        }
+
+        let func_ctx = self
+            .context
+            .last_mut()
+            .unwrap()
+            .get_mut(self.current_func_name.unwrap())
+            .unwrap();
+        func_ctx.new_scope();
+        //Captured variables are copies, assigning them wouldn't affect the enclosing scope
+        for capture in &l.captures {
+            func_ctx.insert(
+                capture.name,
+                ContextVariable {
+                    _name: capture.name,
+                    name_span: capture.span,
+                    ty: capture.ty,
+                    ty_span: capture.span,
+                    is_mut: false,
+                    span: capture.span,
+                },
+            );
+        }
+        for param in &l.params {
+            func_ctx.insert(
+                param.name,
+                ContextVariable {
+                    _name: param.name,
+                    name_span: param.name_span,
+                    ty: param.ty,
+                    ty_span: param.ty_span,
+                    is_mut: false,
+                    span: param.span,
+                },
+            );
+        }
+
+        let outer_loops = std::mem::take(&mut self.loops);
+        let ret_ty = match &mut l.body {
+            HirLambdaBody::Block(b) => {
+                let ret_ty = match l.ret_ty_span {
+                    Some(_) => l.ret_ty,
+                    None => self.arena.types().get_unit_ty(),
+                };
+                self.lambda_returns
+                    .push((ret_ty, l.ret_ty_span.unwrap_or(l.span)));
+                for stmt in b.statements.iter_mut() {
+                    self.check_stmt(stmt)?;
+                }
+                self.lambda_returns.pop();
+                ret_ty
+            }
+            HirLambdaBody::Expr(e) => {
+                self.lambda_returns
+                    .push((l.ret_ty, l.ret_ty_span.unwrap_or(l.span)));
+                let ty = self.check_expr(e)?;
+                self.lambda_returns.pop();
+                if let Some(span) = l.ret_ty_span {
+                    if HirTyId::from(ty) != HirTyId::from(l.ret_ty) {
+                        return Err(HirError::TypeMismatch(TypeMismatchError {
+                            actual_type: format!("{:?}", ty),
+                            actual_loc: SourceSpan::new(
+                                SourceOffset::from(e.start()),
+                                e.end() - e.start(),
+                            ),
+                            expected_type: format!("{:?}", l.ret_ty),
+                            expected_loc: SourceSpan::new(
+                                SourceOffset::from(span.start()),
+                                span.end() - span.start(),
+                            ),
+                            src: self.src.clone(),
+                        }));
+                    }
+                }
+                ty
+            }
+        };
+        self.loops = outer_loops;
+        self.context
+            .last_mut()
+            .unwrap()
+            .get_mut(self.current_func_name.unwrap())
+            .unwrap()
+            .end_scope();
+
+        l.ret_ty = ret_ty;
+        l.ty = self
+            .arena
+            .types()
+            .get_function_ty(l.params.iter().map(|p| p.ty).collect(), ret_ty);
+        Ok(l.ty)
+    }
+
+    /// A function named outside of a call is a closure without captures, e.g. `map(l, double)`.
+    ///
+    /// Its type arguments can't be inferred from a call & native functions have no body to
+    /// point a closure at, so neither can be used as a value.
+    fn function_value(
+        &self,
+        i: &mut HirIdentExpr<'hir>,
+        function: &'hir str,
+    ) -> HirResult<&'hir HirTy<'hir>> {
+        let signature = self.signature.functions[function];
+        let span = SourceSpan::new(
+            SourceOffset::from(i.span.start()),
+            i.span.end() - i.span.start(),
+        );
+        if !signature.type_params.is_empty() {
+            return Err(HirError::CannotInferType(CannotInferTypeError {
+                span,
+                src: self.src.clone(),
+            }));
+        }
+        if signature.library.is_some() {
+            return Err(HirError::UnsupportedExpr(UnsupportedExpr {
+                span,
+                expr: format!("using the native function `{}` as a value", i.name),
+                src: self.src.clone(),
+            }));
+        }
+        i.name = function;
+        i.ty = self.arena.types().get_function_ty(
+            signature.params.iter().map(|p| p.ty).collect(),
+            signature.return_ty,
+        );
+        Ok(i.ty)
+    }
+
+    /// Calls through a value of a function type, e.g. a lambda stored in a variable
+    fn check_closure_call(
+        &mut self,
+        f: &mut HirFunctionCallExpr<'hir>,
+    ) -> HirResult<&'hir HirTy<'hir>> {
+        let callee_ty = self.check_expr(&mut f.callee)?;
+        let fn_ty = match callee_ty {
+            HirTy::Function(fn_ty) => fn_ty,
+            _ => {
+                return Err(HirError::TypeMismatch(TypeMismatchError {
+                    actual_type: format!("{:?}", callee_ty),
+                    actual_loc: SourceSpan::new(
+                        SourceOffset::from(f.callee.start()),
+                        f.callee.end() - f.callee.start(),
+                    ),
+                    expected_type: "a function".to_string(),
+                    expected_loc: SourceSpan::new(
+                        SourceOffset::from(f.span.start()),
+                        f.span.end() - f.span.start(),
+                    ),
+                    src: self.src.clone(),
+                }))
+            }
+        };
+        if fn_ty.params.len() != f.args.len() {
+            return Err(HirError::FunctionTypeMismatch(FunctionTypeMismatchError {
+                expected_ty: format!("{:?}", callee_ty),
+                span: SourceSpan::new(
+                    SourceOffset::from(f.span.start()),
+                    f.span.end() - f.span.start(),
+                ),
+                src: self.src.clone(),
+            }));
+        }
+        f.args_ty.clear();
+        for (param, arg) in fn_ty.params.iter().zip(f.args.iter_mut()) {
+            Self::hint_list(param, arg);
+            let arg_ty = self.check_expr(arg)?;
+            let arg_ty = self.upcast(param, arg_ty, arg);
+            f.args_ty.push(arg_ty);
+            if HirTyId::from(arg_ty) != HirTyId::from(*param) {
+                return Err(HirError::TypeMismatch(TypeMismatchError {
+                    actual_type: format!("{:?}", arg_ty),
+                    actual_loc: SourceSpan::new(
+                        SourceOffset::from(arg.start()),
+                        arg.end() - arg.start(),
+                    ),
+                    expected_type: format!("{:?}", param),
+                    expected_loc: SourceSpan::new(
+                        SourceOffset::from(f.callee.start()),
+                        f.callee.end() - f.callee.start(),
+                    ),
+                    src: self.src.clone(),
+                }));
+            }
+        }
+        f.ty = fn_ty.ret_ty;
+        Ok(f.ty)
+    }
+
+    /// Checks the arguments of a call against the parameters of the callee, the first `skip`
+    /// parameters being already provided (i.e. `&self`).
+    ///
+    /// Returns the type of each argument and the return type of the call.
+    fn check_args(
+        &mut self,
+        func: &'hir HirFunctionSignature<'hir>,
+        skip: usize,
+        args: &mut [HirExpr<'hir>],
+        span: Span,
+    ) -> HirResult<(Vec<&'hir HirTy<'hir>>, &'hir HirTy<'hir>)> {
+        if func.params.len() != args.len() + skip {
+            return Err(HirError::FunctionTypeMismatch(FunctionTypeMismatchError {
+                expected_ty: format!("{:?}", func),
+                span: SourceSpan::new(SourceOffset::from(span.start()), span.end() - span.start()),
+                src: self.src.clone(),
+            }));
+        }
+
+        let mut args_ty = Vec::new();
+        //Type arguments are inferred from the arguments, left to right
+        let mut type_args = HashMap::new();
+        for (param, arg) in func.params[skip..].iter().zip(args.iter_mut()) {
+            let expected = self.substitute(param.ty, &type_args);
+            if let Some(expected) = expected {
+                Self::hint_list(expected, arg);
+            }
+            let mut arg_ty = self.check_expr(arg)?;
+            if let Some(expected) = expected {
+                arg_ty = self.upcast(expected, arg_ty, arg);
+            }
+            args_ty.push(arg_ty);
+            if !Self::unify(param.ty, arg_ty, &mut type_args) {
+                return Err(HirError::TypeMismatch(TypeMismatchError {
+                    actual_type: format!("{:?}", arg_ty),
+                    actual_loc: SourceSpan::new(
+                        SourceOffset::from(arg.start()),
+                        arg.end() - arg.start(),
+                    ),
+                    expected_type: format!(
+                        "{:?}",
+                        self.substitute(param.ty, &type_args).unwrap_or(param.ty)
+                    ),
+                    expected_loc: SourceSpan::new(
+                        SourceOffset::from(param.span.start()),
+                        param.span.end() - param.span.start(),
+                    ),
+                    src: self.src.clone(),
+                }));
+            }
+        }
+
+        match self.substitute(func.return_ty, &type_args) {
+            Some(ty) => Ok((args_ty, ty)),
+            None => Err(HirError::CannotInferType(CannotInferTypeError {
+                span: SourceSpan::new(SourceOffset::from(span.start()), span.end() - span.start()),
+                src: self.src.clone(),
+            })),
+        }
+    }
+
+    fn unknown_method(&self, ty_name: &str, method: &str, span: Span) -> HirError {
+        HirError::UnknownMethod(UnknownMethodError {
+            name: method.to_string(),
+            ty_name: ty_name.to_string(),
+            span: SourceSpan::new(SourceOffset::from(span.start()), span.end() - span.start()),
+            src: self.src.clone(),
+        })
+    }
+
+    /// Methods of a class are dispatched statically, methods of a trait through the vtable of
+    /// the trait object.
+    fn check_method_call(
+        &mut self,
+        m: &mut HirMethodCallExpr<'hir>,
+    ) -> HirResult<&'hir HirTy<'hir>> {
+        let target_ty = self.check_expr(&mut m.target)?;
+        let ty_name = match target_ty {
+            HirTy::Named(n) => n.name,
+            _ => {
+                return Err(self.unknown_method(
+                    &format!("{:?}", target_ty),
+                    m.method,
+                    m.method_span,
+                ))
+            }
+        };
+        let (func, skip) = if let Some(class) = self.signature.classes.get(ty_name).copied() {
+            let method = match class.method(m.method) {
+                Some(method) if !method.is_static => method,
+                _ => return Err(self.unknown_method(ty_name, m.method, m.method_span)),
+            };
+            self.check_access(class.name, method.is_public, m.method, m.method_span)?;
+            m.dispatch = HirDispatch::Static(method.function);
+            (self.signature.functions[method.function], 1)
+        } else if let Some(trait_sig) = self.signature.traits.get(ty_name).copied() {
+            let (slot, method) = trait_sig
+                .method(m.method)
+                .ok_or_else(|| self.unknown_method(ty_name, m.method, m.method_span))?;
+            m.dispatch = HirDispatch::Dynamic(slot);
+            (method.signature, 0)
+        } else {
+            return Err(self.unknown_method(ty_name, m.method, m.method_span));
+        };
+        let (_, ty) = self.check_args(func, skip, &mut m.args, m.span)?;
+        m.ty = ty;
+        Ok(m.ty)
+    }
+
+    /// `Class::method(args)` is parsed like an enum variant, it is a plain call of the function
+    /// the method has been lowered to.
+    fn static_call(
+        &self,
+        class: &'hir HirClassSignature<'hir>,
+        e: &mut HirNewEnumExpr<'hir>,
+    ) -> HirResult<HirExpr<'hir>> {
+        let method = class
+            .method(e.variant)
+            .ok_or_else(|| self.unknown_method(class.name, e.variant, e.variant_span))?;
+        self.check_access(class.name, method.is_public, e.variant, e.variant_span)?;
+        Ok(self.path_call(method.function, e))
+    }
+
+    /// `alias::function(args)` is parsed like an enum variant as well
+    fn path_call(&self, function: &'hir str, e: &mut HirNewEnumExpr<'hir>) -> HirExpr<'hir> {
+        let callee_span = Span::union_span(e.name_span, e.variant_span);
+        HirExpr::Call(HirFunctionCallExpr {
+            span: e.span,
+            callee: Box::new(HirExpr::Ident(HirIdentExpr {
+                name: function,
+                span: callee_span,
+                ty: self.arena.types().get_uninitialized_ty(),
+            })),
+            callee_span,
+            args: std::mem::take(&mut e.args),
+            args_ty: Vec::new(),
+            ty: self.arena.types().get_uninitialized_ty(),
+        })
+    }
+
+    fn get_enum(&self, name: &str, span: Span) -> HirResult<&'hir HirEnumSignature<'hir>> {
+        match self.signature.enums.get(name) {
+            Some(e) => Ok(*e),
+            None => Err(HirError::UnknownType(UnknownTypeError {
+                name: name.to_string(),
+                span: SourceSpan::new(SourceOffset::from(span.start()), span.end() - span.start()),
+                src: self.src.clone(),
+            })),
+        }
+    }
+
+    fn unknown_variant(&self, enum_name: &str, variant: &str, span: Span) -> HirError {
+        HirError::UnknownVariant(UnknownVariantError {
+            name: variant.to_string(),
+            ty_name: enum_name.to_string(),
+            span: SourceSpan::new(SourceOffset::from(span.start()), span.end() - span.start()),
+            src: self.src.clone(),
+        })
+    }
+
+    fn check_match(&mut self, m: &mut HirMatchExpr<'hir>) -> HirResult<&'hir HirTy<'hir>> {
+        let target_ty = self.check_expr(&mut m.target)?;
+        let enum_sig = match target_ty {
+            HirTy::Named(n) if self.signature.enums.contains_key(n.name) => {
+                self.signature.enums[n.name]
+            }
+            _ => {
+                return Err(HirError::TypeMismatch(TypeMismatchError {
+                    actual_type: format!("{:?}", target_ty),
+                    actual_loc: SourceSpan::new(
+                        SourceOffset::from(m.target.start()),
+                        m.target.end() - m.target.start(),
+                    ),
+                    expected_type: "an enum".to_string(),
+                    expected_loc: SourceSpan::new(
+                        SourceOffset::from(m.span.start()),
+                        m.span.end() - m.span.start(),
+                    ),
+                    src: self.src.clone(),
+                }))
+            }
+        };
+
+        let mut covered = vec![false; enum_sig.variants.len()];
+        let mut match_ty: Option<(&'hir HirTy<'hir>, Span)> = None;
+        for arm in m.arms.iter_mut() {
+            self.context
+                .last_mut()
+                .unwrap()
+                .get_mut(self.current_func_name.unwrap())
+                .unwrap()
+                .new_scope();
+            match &mut arm.pattern {
+                HirPattern::Wildcard(_) => covered.iter_mut().for_each(|c| *c = true),
+                HirPattern::Variant(p) => {
+                    if p.name != enum_sig.name {
+                        return Err(HirError::TypeMismatch(TypeMismatchError {
+                            actual_type: p.name.to_string(),
+                            actual_loc: SourceSpan::new(
+                                SourceOffset::from(p.name_span.start()),
+                                p.name_span.end() - p.name_span.start(),
+                            ),
+                            expected_type: enum_sig.name.to_string(),
+                            expected_loc: SourceSpan::new(
+                                SourceOffset::from(m.target.start()),
+                                m.target.end() - m.target.start(),
+                            ),
+                            src: self.src.clone(),
+                        }));
+                    }
+                    let (discriminant, variant) = enum_sig
+                        .variant(p.variant)
+                        .ok_or_else(|| self.unknown_variant(p.name, p.variant, p.variant_span))?;
+                    if variant.fields.len() != p.bindings.len() {
+                        return Err(HirError::VariantArity(VariantArityError {
+                            ty_name: p.name.to_string(),
+                            variant: p.variant.to_string(),
+                            expected: variant.fields.len(),
+                            found: p.bindings.len(),
+                            span: SourceSpan::new(
+                                SourceOffset::from(p.span.start()),
+                                p.span.end() - p.span.start(),
+                            ),
+                            src: self.src.clone(),
+                        }));
+                    }
+                    covered[discriminant] = true;
+                    for (binding, ty) in p.bindings.iter_mut().zip(variant.fields.iter()) {
+                        binding.ty = ty;
+                        if binding.name == "_" {
+                            continue;
+                        }
+                        self.context
+                            .last_mut()
+                            .unwrap()
+                            .get_mut(self.current_func_name.unwrap())
+                            .unwrap()
+                            .insert(
+                                binding.name,
+                                ContextVariable {
+                                    _name: binding.name,
+                                    name_span: binding.span,
+                                    ty,
+                                    ty_span: variant.span,
+                                    is_mut: false,
+                                    span: binding.span,
+                                },
+                            );
+                    }
+                }
+            }
+            let (arm_ty, arm_span) = match &mut arm.body {
+                HirMatchArmBody::Block(b) => {
+                    for stmt in b.statements.iter_mut() {
+                        self.check_stmt(stmt)?;
+                    }
+                    (self.arena.types().get_unit_ty(), b.span)
+                }
+                HirMatchArmBody::Expr(e) => (self.check_expr(e)?, e.span()),
+            };
+            self.context
+                .last_mut()
+                .unwrap()
+                .get_mut(self.current_func_name.unwrap())
+                .unwrap()
+                .end_scope();
+            match match_ty {
+                Some((ty, span)) if HirTyId::from(ty) != HirTyId::from(arm_ty) => {
+                    return Err(HirError::TypeMismatch(TypeMismatchError {
+                        actual_type: format!("{:?}", arm_ty),
+                        actual_loc: SourceSpan::new(
+                            SourceOffset::from(arm_span.start()),
+                            arm_span.end() - arm_span.start(),
+                        ),
+                        expected_type: format!("{:?}", ty),
+                        expected_loc: SourceSpan::new(
+                            SourceOffset::from(span.start()),
+                            span.end() - span.start(),
+                        ),
+                        src: self.src.clone(),
+                    }));
+                }
+                Some(_) => {}
+                None => match_ty = Some((arm_ty, arm_span)),
+            }
+        }
+
+        let missing = enum_sig
+            .variants
+            .iter()
+            .zip(covered.iter())
+            .filter(|(_, covered)| !**covered)
+            .map(|(v, _)| format!("{}::{}", enum_sig.name, v.name))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(HirError::NonExhaustiveMatch(NonExhaustiveMatchError {
+                ty_name: enum_sig.name.to_string(),
+                missing: missing.join(", "),
+                span: SourceSpan::new(
+                    SourceOffset::from(m.span.start()),
+                    m.span.end() - m.span.start(),
+                ),
+                src: self.src.clone(),
+            }));
+        }
+        m.ty = match match_ty {
+            Some((ty, _)) => ty,
+            None => self.arena.types().get_unit_ty(),
+        };
+        Ok(m.ty)
+    }
+
+    pub fn check_func(&mut self, func: &mut HirFunction<'hir>) -> HirResult<()> {
+        if let Some(span) = func.signature.return_ty_span {
+            self.check_ty_exists(func.signature.return_ty, span)?;
+        }
+        for param in &func.signature.params {
+            self.check_ty_exists(param.ty, param.ty_span)?;
+        }
         self.context.push(HashMap::new());
         self.context.last_mut().unwrap().insert(
             self.current_func_name.unwrap().to_string(),
@@ -154,25 +987,37 @@ impl<'hir> TypeChecker<'hir> {
                     },
                 );
         }
-        for stmt in &func.body.statements {
+        for stmt in func.body.statements.iter_mut() {
             self.check_stmt(stmt)?;
         }
         Ok(())
     }
-    pub fn check_stmt(&mut self, stmt: &HirStatement<'hir>) -> HirResult<()> {
+    pub fn check_stmt(&mut self, stmt: &mut HirStatement<'hir>) -> HirResult<()> {
         match stmt {
             HirStatement::Expr(e) => {
-                self.check_expr(e.expr)?;
+                self.check_expr(&mut e.expr)?;
                 Ok(())
             }
             HirStatement::Return(r) => {
-                let actual_ret_ty = self.check_expr(r.value)?;
-                let func_ret_from = self
-                    .signature
-                    .functions
-                    .get(self.current_func_name.unwrap())
-                    .unwrap();
-                let expected_ret_ty = func_ret_from.return_ty;
+                //Inside a lambda, `return` refers to the lambda
+                let (expected_ret_ty, expected_span) = match self.lambda_returns.last() {
+                    Some(&(ty, span)) => (ty, span),
+                    None => {
+                        let func_ret_from = self
+                            .signature
+                            .functions
+                            .get(self.current_func_name.unwrap())
+                            .unwrap();
+                        (
+                            func_ret_from.return_ty,
+                            func_ret_from.return_ty_span.unwrap_or(r.span),
+                        )
+                    }
+                };
+                Self::hint_list(expected_ret_ty, &mut r.value);
+                let actual_ret_ty = self.check_expr(&mut r.value)?;
+                let actual_ret_ty = self.upcast(expected_ret_ty, actual_ret_ty, &mut r.value);
+                r.ty = actual_ret_ty;
                 if HirTyId::from(actual_ret_ty) != HirTyId::from(expected_ret_ty) {
                     return Err(HirError::TypeMismatch(TypeMismatchError {
                         actual_type: format!("{:?}", actual_ret_ty),
@@ -182,11 +1027,8 @@ impl<'hir> TypeChecker<'hir> {
                         ),
                         expected_type: format!("{:?}", expected_ret_ty),
                         expected_loc: SourceSpan::new(
-                            SourceOffset::from(
-                                func_ret_from.return_ty_span.unwrap_or(r.span).start(),
-                            ),
-                            func_ret_from.return_ty_span.unwrap_or(r.span).end()
-                                - func_ret_from.return_ty_span.unwrap_or(r.span).start(),
+                            SourceOffset::from(expected_span.start()),
+                            expected_span.end() - expected_span.start(),
                         ),
                         src: self.src.clone(),
                     }));
@@ -195,7 +1037,7 @@ impl<'hir> TypeChecker<'hir> {
                 Ok(())
             }
             HirStatement::While(w) => {
-                let cond_ty = self.check_expr(w.condition)?;
+                let cond_ty = self.check_expr(&mut w.condition)?;
                 if HirTyId::from(cond_ty) != HirTyId::compute_boolean_ty_id() {
                     return Err(HirError::TypeMismatch(TypeMismatchError {
                         actual_type: format!("{:?}", cond_ty),
@@ -218,9 +1060,11 @@ impl<'hir> TypeChecker<'hir> {
                     .get_mut(self.current_func_name.unwrap())
                     .unwrap()
                     .new_scope();
-                for stmt in &w.body.statements {
+                self.loops += 1;
+                for stmt in w.body.statements.iter_mut() {
                     self.check_stmt(stmt)?;
                 }
+                self.loops -= 1;
                 self.context
                     .last_mut()
                     .unwrap()
@@ -231,7 +1075,7 @@ impl<'hir> TypeChecker<'hir> {
                 Ok(())
             }
             HirStatement::IfElse(i) => {
-                let cond_ty = self.check_expr(i.condition)?;
+                let cond_ty = self.check_expr(&mut i.condition)?;
                 if HirTyId::from(cond_ty) != HirTyId::compute_boolean_ty_id() {
                     return Err(HirError::TypeMismatch(TypeMismatchError {
                         actual_type: format!("{:?}", cond_ty),
@@ -254,7 +1098,7 @@ impl<'hir> TypeChecker<'hir> {
                     .get_mut(self.current_func_name.unwrap())
                     .unwrap()
                     .new_scope();
-                for stmt in &i.then_branch.statements {
+                for stmt in i.then_branch.statements.iter_mut() {
                     self.check_stmt(stmt)?;
                 }
                 self.context
@@ -263,14 +1107,14 @@ impl<'hir> TypeChecker<'hir> {
                     .get_mut(self.current_func_name.unwrap())
                     .unwrap()
                     .end_scope();
-                if let Some(else_branch) = &i.else_branch {
+                if let Some(else_branch) = &mut i.else_branch {
                     self.context
                         .last_mut()
                         .unwrap()
                         .get_mut(self.current_func_name.unwrap())
                         .unwrap()
                         .new_scope();
-                    for stmt in &else_branch.statements {
+                    for stmt in else_branch.statements.iter_mut() {
                         self.check_stmt(stmt)?;
                     }
                     self.context
@@ -282,91 +1126,117 @@ impl<'hir> TypeChecker<'hir> {
                 }
                 Ok(())
             }
-            HirStatement::Const(c) => {
-                let ty = HirTyId::from(c.ty);
-                self.context
-                    .last_mut()
-                    .unwrap()
-                    .get_mut(self.current_func_name.unwrap())
-                    .unwrap()
-                    .insert(
-                        c.name,
-                        ContextVariable {
-                            _name: c.name,
-                            name_span: c.name_span,
-                            ty: c.ty,
-                            ty_span: c.ty_span,
-                            is_mut: false,
-                            span: c.span,
-                        },
-                    );
-                let ty_value = self.check_expr(c.value)?;
-                if HirTyId::from(ty_value) != ty {
-                    return Err(HirError::TypeMismatch(TypeMismatchError {
-                        actual_type: format!("{:?}", ty_value),
-                        actual_loc: SourceSpan::new(
-                            SourceOffset::from(c.value.start()),
-                            c.value.end() - c.value.start(),
-                        ),
-                        expected_type: format!("{:?}", c.ty),
-                        expected_loc: SourceSpan::new(
-                            SourceOffset::from(c.span.start()),
-                            c.name_span.end() - c.span.start(),
-                        ),
-                        src: self.src.clone(),
-                    }));
-                }
-                Ok(())
-            }
-            HirStatement::Let(l) => {
-                let ty = HirTyId::from(l.ty);
-                self.context
-                    .last_mut()
-                    .unwrap()
-                    .get_mut(self.current_func_name.unwrap())
-                    .unwrap()
-                    .insert(
-                        l.name,
-                        ContextVariable {
-                            _name: l.name,
-                            name_span: l.name_span,
-                            ty: l.ty,
-                            ty_span: l.ty_span,
-                            is_mut: true,
-                            span: l.span,
-                        },
-                    );
-                let ty_value = self.check_expr(l.value)?;
-                if HirTyId::from(ty_value) != ty {
-                    return Err(HirError::TypeMismatch(TypeMismatchError {
-                        actual_type: format!("{:?}", ty_value),
-                        actual_loc: SourceSpan::new(
-                            SourceOffset::from(l.value.start()),
-                            l.value.end() - l.value.start(),
-                        ),
-                        expected_type: format!("{:?}", l.ty),
-                        expected_loc: SourceSpan::new(
-                            SourceOffset::from(l.name_span.start()),
-                            l.name_span.end() - l.name_span.start(),
-                        ),
-                        src: self.src.clone(),
-                    }));
-                }
-                Ok(())
+            HirStatement::Const(c) => self.check_let(c, false),
+            HirStatement::Let(l) => self.check_let(l, true),
+            HirStatement::Break(span) if self.loops == 0 => {
+                Err(HirError::BreakOutsideLoop(BreakOutsideLoopError {
+                    span: SourceSpan::new(
+                        SourceOffset::from(span.start()),
+                        span.end() - span.start(),
+                    ),
+                    src: self.src.clone(),
+                }))
             }
-            _ => {
-                todo!("TypeChecker::check_stmt")
+            HirStatement::Continue(span) if self.loops == 0 => {
+                Err(HirError::ContinueOutsideLoop(ContinueOutsideLoopError {
+                    span: SourceSpan::new(
+                        SourceOffset::from(span.start()),
+                        span.end() - span.start(),
+                    ),
+                    src: self.src.clone(),
+                }))
             }
+            //Loops can't be left early yet, the code generator has no jumps for it
+            HirStatement::Break(span) => Err(self.unsupported_statement("`break`", *span)),
+            HirStatement::Continue(span) => Err(self.unsupported_statement("`continue`", *span)),
+            HirStatement::_Block(b) => Err(self.unsupported_statement("a nested block", b.span)),
         }
     }
-    pub fn check_expr(&mut self, expr: &HirExpr<'hir>) -> HirResult<&'hir HirTy<'hir>> {
+    fn unsupported_statement(&self, stmt: &str, span: Span) -> HirError {
+        HirError::UnsupportedStatement(UnsupportedStatement {
+            span: SourceSpan::new(SourceOffset::from(span.start()), span.end() - span.start()),
+            stmt: stmt.to_string(),
+            src: self.src.clone(),
+        })
+    }
+    fn check_let(&mut self, l: &mut HirLetStmt<'hir>, is_mut: bool) -> HirResult<()> {
+        //Without annotation the variable takes the type of its value
+        let is_inferred = matches!(l.ty, HirTy::Uninitialized(_));
+        if is_inferred {
+            l.ty = self.check_expr(&mut l.value)?;
+        } else {
+            self.check_ty_exists(l.ty, l.ty_span)?;
+        }
+        self.context
+            .last_mut()
+            .unwrap()
+            .get_mut(self.current_func_name.unwrap())
+            .unwrap()
+            .insert(
+                l.name,
+                ContextVariable {
+                    _name: l.name,
+                    name_span: l.name_span,
+                    ty: l.ty,
+                    ty_span: l.ty_span,
+                    is_mut,
+                    span: l.span,
+                },
+            );
+        if is_inferred {
+            return Ok(());
+        }
+        Self::hint_list(l.ty, &mut l.value);
+        let ty_value = self.check_expr(&mut l.value)?;
+        let ty_value = self.upcast(l.ty, ty_value, &mut l.value);
+        if HirTyId::from(ty_value) != HirTyId::from(l.ty) {
+            return Err(HirError::TypeMismatch(TypeMismatchError {
+                actual_type: format!("{:?}", ty_value),
+                actual_loc: SourceSpan::new(
+                    SourceOffset::from(l.value.start()),
+                    l.value.end() - l.value.start(),
+                ),
+                expected_type: format!("{:?}", l.ty),
+                expected_loc: SourceSpan::new(
+                    SourceOffset::from(l.name_span.start()),
+                    l.name_span.end() - l.name_span.start(),
+                ),
+                src: self.src.clone(),
+            }));
+        }
+        Ok(())
+    }
+
+    pub fn check_expr(&mut self, expr: &mut HirExpr<'hir>) -> HirResult<&'hir HirTy<'hir>> {
+        if let HirExpr::NewEnum(e) = expr {
+            if let Some(class) = self.signature.classes.get(e.name).copied() {
+                *expr = self.static_call(class, e)?;
+            } else if !self.signature.enums.contains_key(e.name) {
+                let path = format!("{}::{}", e.name, e.variant);
+                match self.resolve_function(&path) {
+                    Some(function) => *expr = self.path_call(function, e),
+                    None => {
+                        return Err(HirError::UnknownType(UnknownTypeError {
+                            name: path,
+                            span: SourceSpan::new(
+                                SourceOffset::from(e.span.start()),
+                                e.span.end() - e.span.start(),
+                            ),
+                            src: self.src.clone(),
+                        }))
+                    }
+                }
+            }
+        }
         match expr {
             HirExpr::IntegerLiteral(_) => Ok(self.arena.types().get_integer64_ty()),
             HirExpr::FloatLiteral(_) => Ok(self.arena.types().get_float64_ty()),
             HirExpr::UnsignedIntegererLiteral(_) => Ok(self.arena.types().get_uint64_ty()),
             HirExpr::BooleanLiteral(_) => Ok(self.arena.types().get_boolean_ty()),
+            HirExpr::StringLiteral(_) => Ok(self.arena.types().get_str_ty()),
             HirExpr::Unary(u) => {
-                let ty = self.check_expr(&u.expr)?;
+                let ty = self.check_expr(&mut u.expr)?;
+                u.ty = ty;
                 match u.op {
                     Some(expr::UnaryOp::Neg) => {
                         if HirTyId::from(ty) != HirTyId::compute_integer64_ty_id() {
@@ -386,8 +1256,8 @@ impl<'hir> TypeChecker<'hir> {
                 }
             }
             HirExpr::HirBinaryOp(b) => {
-                let lhs = self.check_expr(&b.lhs)?;
-                let rhs = self.check_expr(&b.rhs)?;
+                let lhs = self.check_expr(&mut b.lhs)?;
+                let rhs = self.check_expr(&mut b.rhs)?;
                 if HirTyId::from(lhs) != HirTyId::from(rhs) {
                     return Err(HirError::TypeMismatch(TypeMismatchError {
                         actual_type: format!("{:?}", lhs),
@@ -403,9 +1273,45 @@ impl<'hir> TypeChecker<'hir> {
                         src: self.src.clone(),
                     }));
                 }
+                //Arithmetic is done on numbers & `+` concatenates strings, only numbers & strings
+                //are ordered. Any value can be checked for equality.
+                let is_number =
+                    matches!(lhs, HirTy::Int64(_) | HirTy::UInt64(_) | HirTy::Float64(_));
+                let expected = match b.op {
+                    HirBinaryOp::Sub | HirBinaryOp::Mul | HirBinaryOp::Div | HirBinaryOp::Mod
+                        if !is_number =>
+                    {
+                        Some("a numeric type")
+                    }
+                    HirBinaryOp::Add
+                    | HirBinaryOp::Gt
+                    | HirBinaryOp::Gte
+                    | HirBinaryOp::Lt
+                    | HirBinaryOp::Lte
+                        if !is_number && !matches!(lhs, HirTy::String(_)) =>
+                    {
+                        Some("a numeric type or str")
+                    }
+                    _ => None,
+                };
+                if let Some(expected) = expected {
+                    return Err(HirError::TypeMismatch(TypeMismatchError {
+                        actual_type: format!("{:?}", lhs),
+                        actual_loc: SourceSpan::new(
+                            SourceOffset::from(b.span.start()),
+                            b.span.end() - b.span.start(),
+                        ),
+                        expected_type: expected.to_string(),
+                        expected_loc: SourceSpan::new(
+                            SourceOffset::from(b.lhs.start()),
+                            b.lhs.end() - b.lhs.start(),
+                        ),
+                        src: self.src.clone(),
+                    }));
+                }
                 //Should handle conditions:
 
-                match b.op {
+                b.ty = match b.op {
                     HirBinaryOp::And
                     | HirBinaryOp::Eq
                     | HirBinaryOp::Neq
@@ -413,21 +1319,49 @@ impl<'hir> TypeChecker<'hir> {
                     | HirBinaryOp::Gt
                     | HirBinaryOp::Gte
                     | HirBinaryOp::Lt
-                    | HirBinaryOp::Lte => Ok(self.arena.types().get_boolean_ty()),
-                    _ => Ok(lhs),
-                }
+                    | HirBinaryOp::Lte => self.arena.types().get_boolean_ty(),
+                    _ => lhs,
+                };
+                Ok(b.ty)
             }
             //Todo, add support for extern func
             HirExpr::Call(f) => {
-                let callee = f.callee.as_ref();
+                //Variables shadow functions, anything else than a function name is a closure
+                let is_closure = match f.callee.as_ref() {
+                    HirExpr::Ident(i) => self
+                        .context
+                        .last()
+                        .unwrap()
+                        .get(self.current_func_name.unwrap())
+                        .unwrap()
+                        .get(i.name)
+                        .is_some(),
+                    _ => true,
+                };
+                if is_closure {
+                    return self.check_closure_call(f);
+                }
+                let callee = f.callee.as_mut();
                 let name = match callee {
                     HirExpr::Ident(i) => i.name,
                     _ => {
-                        todo!("TypeChecker::check_expr")
+                        return Err(HirError::UnsupportedExpr(UnsupportedExpr {
+                            span: SourceSpan::new(
+                                SourceOffset::from(callee.start()),
+                                callee.end() - callee.start(),
+                            ),
+                            expr: format!("calling {:?}", callee),
+                            src: self.src.clone(),
+                        }))
                     }
                 };
-                let func = match self.signature.functions.get(name) {
-                    Some(f) => *f,
+                let func = match self.resolve_function(name) {
+                    Some(resolved) => {
+                        if let HirExpr::Ident(i) = callee {
+                            i.name = resolved;
+                        }
+                        self.signature.functions[resolved]
+                    }
                     None => {
                         return Err(HirError::UnknownType(UnknownTypeError {
                             name: name.to_string(),
@@ -440,51 +1374,34 @@ impl<'hir> TypeChecker<'hir> {
                     }
                 };
 
-                if func.params.len() != f.args.len() {
-                    return Err(HirError::FunctionTypeMismatch(FunctionTypeMismatchError {
-                        expected_ty: format!("{:?}", func),
-                        span: SourceSpan::new(
-                            SourceOffset::from(f.span.start()),
-                            f.span.end() - f.span.start(),
-                        ),
-                        src: self.src.clone(),
-                    }));
-                }
-
-                for (param, arg) in func.params.iter().zip(f.args.iter()) {
-                    let arg_ty = self.check_expr(arg)?;
-                    if HirTyId::from(arg_ty) != HirTyId::from(param.ty) {
-                        return Err(HirError::TypeMismatch(TypeMismatchError {
-                            actual_type: format!("{:?}", arg_ty),
-                            actual_loc: SourceSpan::new(
-                                SourceOffset::from(arg.start()),
-                                arg.end() - arg.start(),
-                            ),
-                            expected_type: format!("{:?}", param.ty),
-                            expected_loc: SourceSpan::new(
-                                SourceOffset::from(param.span.start()),
-                                param.span.end() - param.span.start(),
-                            ),
-                            src: self.src.clone(),
-                        }));
-                    }
-                }
-
-                Ok(func.return_ty)
+                let (args_ty, ty) = self.check_args(func, 0, &mut f.args, f.span)?;
+                f.args_ty = args_ty;
+                f.ty = ty;
+                Ok(f.ty)
             }
             HirExpr::Assign(a) => {
-                //first because of the borrow checker
-                let rhs = self.check_expr(&a.rhs)?;
-                let lhs = match a.lhs.as_ref() {
+                //Only rebinding a variable needs it to be mutable, the items & fields of the
+                //object it holds are written through the reference, as `set` or a method would
+                let rebinds = matches!(a.lhs.as_ref(), HirExpr::Ident(_));
+                let mut root = a.lhs.as_ref();
+                loop {
+                    match root {
+                        HirExpr::FieldAccess(f) => root = f.target.as_ref(),
+                        HirExpr::Indexing(i) => root = i.target.as_ref(),
+                        _ => break,
+                    }
+                }
+                let (lhs_ty, lhs_loc) = match root {
                     HirExpr::Ident(i) => match self
                         .context
                         .last()
                         .unwrap()
                         .get(self.current_func_name.unwrap())
+                        .unwrap()
+                        .get(i.name)
                     {
-                        Some(ctx_func) => {
-                            let ctx_var = ctx_func.get(i.name).unwrap();
-                            if !ctx_var.is_mut {
+                        Some(ctx_var) => {
+                            if rebinds && !ctx_var.is_mut {
                                 return Err(HirError::TryingToMutateImmutableVariable(
                                     TryingToMutateImmutableVariableError {
                                         const_loc: SourceSpan::new(
@@ -500,7 +1417,10 @@ impl<'hir> TypeChecker<'hir> {
                                     },
                                 ));
                             }
-                            ctx_var
+                            (
+                                ctx_var.ty,
+                                Span::union_span(ctx_var.name_span, ctx_var.ty_span),
+                            )
                         }
                         None => {
                             return Err(HirError::UnknownType(UnknownTypeError {
@@ -514,26 +1434,48 @@ impl<'hir> TypeChecker<'hir> {
                         }
                     },
                     _ => {
-                        todo!("TypeChecker::check_expr")
+                        return Err(HirError::UnsupportedExpr(UnsupportedExpr {
+                            span: SourceSpan::new(
+                                SourceOffset::from(a.lhs.start()),
+                                a.lhs.end() - a.lhs.start(),
+                            ),
+                            expr: "assigning to a value that isn't held by a variable".to_string(),
+                            src: self.src.clone(),
+                        }))
+                    }
+                };
+                let lhs_ty = match a.lhs.as_mut() {
+                    HirExpr::Ident(i) => {
+                        i.ty = lhs_ty;
+                        lhs_ty
                     }
+                    lhs => self.check_expr(lhs)?,
+                };
+                let lhs_loc = match a.lhs.as_ref() {
+                    HirExpr::Ident(_) => lhs_loc,
+                    lhs => lhs.span(),
                 };
 
-                if HirTyId::from(lhs.ty) != HirTyId::from(rhs) {
+                Self::hint_list(lhs_ty, &mut a.rhs);
+                let rhs = self.check_expr(&mut a.rhs)?;
+                let rhs = self.upcast(lhs_ty, rhs, &mut a.rhs);
+                if HirTyId::from(lhs_ty) != HirTyId::from(rhs) {
                     Err(HirError::TypeMismatch(TypeMismatchError {
                         actual_type: format!("{:?}", rhs),
                         actual_loc: SourceSpan::new(
                             SourceOffset::from(a.lhs.start()),
                             a.rhs.end() - a.lhs.start(),
                         ),
-                        expected_type: format!("{:?}", lhs.ty),
+                        expected_type: format!("{:?}", lhs_ty),
                         expected_loc: SourceSpan::new(
-                            SourceOffset::from(lhs.name_span.start()),
-                            lhs.ty_span.end() - lhs.name_span.start(),
+                            SourceOffset::from(lhs_loc.start()),
+                            lhs_loc.end() - lhs_loc.start(),
                         ),
                         src: self.src.clone(),
                     }))
                 } else {
-                    Ok(lhs.ty)
+                    a.ty = lhs_ty;
+                    Ok(lhs_ty)
                 }
             }
             HirExpr::Ident(i) => {
@@ -545,7 +1487,10 @@ impl<'hir> TypeChecker<'hir> {
                     .unwrap()
                     .get(i.name)
                 {
+                    i.ty = ctx_var.ty;
                     Ok(ctx_var.ty)
+                } else if let Some(function) = self.resolve_function(i.name) {
+                    self.function_value(i, function)
                 } else {
                     Err(HirError::UnknownType(UnknownTypeError {
                         name: i.name.to_string(),
@@ -557,9 +1502,565 @@ impl<'hir> TypeChecker<'hir> {
                     }))
                 }
             }
-            _ => {
-                todo!("TypeChecker::check_expr")
+            HirExpr::NewObj(o) => {
+                let struct_sig = match self.signature.structs.get(o.name) {
+                    Some(s) => *s,
+                    None => {
+                        return Err(HirError::UnknownType(UnknownTypeError {
+                            name: o.name.to_string(),
+                            span: SourceSpan::new(
+                                SourceOffset::from(o.name_span.start()),
+                                o.name_span.end() - o.name_span.start(),
+                            ),
+                            src: self.src.clone(),
+                        }))
+                    }
+                };
+                for (i, field) in o.fields.iter().enumerate() {
+                    if let Some(previous) = o.fields[..i].iter().find(|f| f.name == field.name) {
+                        return Err(HirError::DuplicateField(DuplicateFieldError {
+                            name: field.name.to_string(),
+                            ty_name: o.name.to_string(),
+                            span: SourceSpan::new(
+                                SourceOffset::from(field.name_span.start()),
+                                field.name_span.end() - field.name_span.start(),
+                            ),
+                            previous: SourceSpan::new(
+                                SourceOffset::from(previous.name_span.start()),
+                                previous.name_span.end() - previous.name_span.start(),
+                            ),
+                            src: self.src.clone(),
+                        }));
+                    }
+                }
+                for field in o.fields.iter_mut() {
+                    let field_sig = match struct_sig.field(field.name) {
+                        Some((_, f)) => f,
+                        None => {
+                            return Err(HirError::UnknownField(UnknownFieldError {
+                                name: field.name.to_string(),
+                                ty_name: o.name.to_string(),
+                                span: SourceSpan::new(
+                                    SourceOffset::from(field.name_span.start()),
+                                    field.name_span.end() - field.name_span.start(),
+                                ),
+                                src: self.src.clone(),
+                            }))
+                        }
+                    };
+                    if !field_sig.is_public {
+                        self.check_access(o.name, false, field.name, field.name_span)?;
+                    }
+                    Self::hint_list(field_sig.ty, &mut field.value);
+                    let value_ty = self.check_expr(&mut field.value)?;
+                    let value_ty = self.upcast(field_sig.ty, value_ty, &mut field.value);
+                    if HirTyId::from(value_ty) != HirTyId::from(field_sig.ty) {
+                        return Err(HirError::TypeMismatch(TypeMismatchError {
+                            actual_type: format!("{:?}", value_ty),
+                            actual_loc: SourceSpan::new(
+                                SourceOffset::from(field.value.start()),
+                                field.value.end() - field.value.start(),
+                            ),
+                            expected_type: format!("{:?}", field_sig.ty),
+                            expected_loc: SourceSpan::new(
+                                SourceOffset::from(field_sig.span.start()),
+                                field_sig.span.end() - field_sig.span.start(),
+                            ),
+                            src: self.src.clone(),
+                        }));
+                    }
+                }
+                let missing = struct_sig
+                    .fields
+                    .iter()
+                    .filter(|f| !o.fields.iter().any(|init| init.name == f.name))
+                    .map(|f| f.name)
+                    .collect::<Vec<_>>();
+                if !missing.is_empty() {
+                    return Err(HirError::MissingFields(MissingFieldsError {
+                        fields: missing.join(", "),
+                        ty_name: o.name.to_string(),
+                        span: SourceSpan::new(
+                            SourceOffset::from(o.span.start()),
+                            o.span.end() - o.span.start(),
+                        ),
+                        src: self.src.clone(),
+                    }));
+                }
+                Ok(o.ty)
+            }
+            HirExpr::FieldAccess(f) => {
+                let target_ty = self.check_expr(&mut f.target)?;
+                let struct_sig = match target_ty {
+                    HirTy::Named(n) => self.signature.structs.get(n.name).copied(),
+                    _ => None,
+                };
+                match struct_sig.and_then(|s| s.field(f.field)) {
+                    Some((_, field_sig)) => {
+                        if !field_sig.is_public {
+                            let class = struct_sig.unwrap().name;
+                            self.check_access(class, false, f.field, f.field_span)?;
+                        }
+                        f.ty = field_sig.ty;
+                        Ok(field_sig.ty)
+                    }
+                    None => Err(HirError::UnknownField(UnknownFieldError {
+                        name: f.field.to_string(),
+                        ty_name: match target_ty {
+                            HirTy::Named(n) => n.name.to_string(),
+                            _ => format!("{:?}", target_ty),
+                        },
+                        span: SourceSpan::new(
+                            SourceOffset::from(f.field_span.start()),
+                            f.field_span.end() - f.field_span.start(),
+                        ),
+                        src: self.src.clone(),
+                    })),
+                }
             }
+            HirExpr::ListLiteral(l) => {
+                let mut items = l.items.iter_mut();
+                let first = match items.next() {
+                    Some(first) => first,
+                    //Only typed if it has been hinted by the context
+                    None if matches!(l.ty, HirTy::List(_)) => return Ok(l.ty),
+                    None => {
+                        return Err(HirError::CannotInferType(CannotInferTypeError {
+                            span: SourceSpan::new(
+                                SourceOffset::from(l.span.start()),
+                                l.span.end() - l.span.start(),
+                            ),
+                            src: self.src.clone(),
+                        }))
+                    }
+                };
+                let hint = match l.ty {
+                    HirTy::List(list) => Some(list.inner),
+                    _ => None,
+                };
+                let first_span = first.span();
+                let mut inner = self.check_expr(first)?;
+                if let Some(hint) = hint {
+                    inner = self.upcast(hint, inner, first);
+                }
+                for item in items {
+                    let mut item_ty = self.check_expr(item)?;
+                    if let Some(hint) = hint {
+                        item_ty = self.upcast(hint, item_ty, item);
+                    }
+                    if HirTyId::from(item_ty) != HirTyId::from(inner) {
+                        return Err(HirError::TypeMismatch(TypeMismatchError {
+                            actual_type: format!("{:?}", item_ty),
+                            actual_loc: SourceSpan::new(
+                                SourceOffset::from(item.start()),
+                                item.end() - item.start(),
+                            ),
+                            expected_type: format!("{:?}", inner),
+                            expected_loc: SourceSpan::new(
+                                SourceOffset::from(first_span.start()),
+                                first_span.end() - first_span.start(),
+                            ),
+                            src: self.src.clone(),
+                        }));
+                    }
+                }
+                l.ty = self.arena.types().get_list_ty(inner);
+                Ok(l.ty)
+            }
+            HirExpr::Indexing(i) => {
+                let target_ty = self.check_expr(&mut i.target)?;
+                let inner = match target_ty {
+                    HirTy::List(l) => l.inner,
+                    _ => {
+                        return Err(HirError::TypeMismatch(TypeMismatchError {
+                            actual_type: format!("{:?}", target_ty),
+                            actual_loc: SourceSpan::new(
+                                SourceOffset::from(i.target.start()),
+                                i.target.end() - i.target.start(),
+                            ),
+                            expected_type: "List".to_string(),
+                            expected_loc: SourceSpan::new(
+                                SourceOffset::from(i.span.start()),
+                                i.span.end() - i.span.start(),
+                            ),
+                            src: self.src.clone(),
+                        }))
+                    }
+                };
+                let index_ty = self.check_expr(&mut i.index)?;
+                if !matches!(index_ty, HirTy::Int64(_) | HirTy::UInt64(_)) {
+                    return Err(HirError::TypeMismatch(TypeMismatchError {
+                        actual_type: format!("{:?}", index_ty),
+                        actual_loc: SourceSpan::new(
+                            SourceOffset::from(i.index.start()),
+                            i.index.end() - i.index.start(),
+                        ),
+                        expected_type: format!("{:?}", self.arena.types().get_uint64_ty()),
+                        expected_loc: SourceSpan::new(
+                            SourceOffset::from(i.span.start()),
+                            i.span.end() - i.span.start(),
+                        ),
+                        src: self.src.clone(),
+                    }));
+                }
+                i.ty = inner;
+                Ok(inner)
+            }
+            HirExpr::NewEnum(e) => {
+                let enum_sig = self.get_enum(e.name, e.name_span)?;
+                let (_, variant) = enum_sig
+                    .variant(e.variant)
+                    .ok_or_else(|| self.unknown_variant(e.name, e.variant, e.variant_span))?;
+                if variant.fields.len() != e.args.len() {
+                    return Err(HirError::VariantArity(VariantArityError {
+                        ty_name: e.name.to_string(),
+                        variant: e.variant.to_string(),
+                        expected: variant.fields.len(),
+                        found: e.args.len(),
+                        span: SourceSpan::new(
+                            SourceOffset::from(e.span.start()),
+                            e.span.end() - e.span.start(),
+                        ),
+                        src: self.src.clone(),
+                    }));
+                }
+                for (field, arg) in variant.fields.iter().zip(e.args.iter_mut()) {
+                    Self::hint_list(field, arg);
+                    let arg_ty = self.check_expr(arg)?;
+                    let arg_ty = self.upcast(field, arg_ty, arg);
+                    if HirTyId::from(arg_ty) != HirTyId::from(*field) {
+                        return Err(HirError::TypeMismatch(TypeMismatchError {
+                            actual_type: format!("{:?}", arg_ty),
+                            actual_loc: SourceSpan::new(
+                                SourceOffset::from(arg.start()),
+                                arg.end() - arg.start(),
+                            ),
+                            expected_type: format!("{:?}", field),
+                            expected_loc: SourceSpan::new(
+                                SourceOffset::from(variant.span.start()),
+                                variant.span.end() - variant.span.start(),
+                            ),
+                            src: self.src.clone(),
+                        }));
+                    }
+                }
+                Ok(e.ty)
+            }
+            HirExpr::Match(m) => self.check_match(m),
+            HirExpr::Lambda(l) => self.check_lambda(l),
+            HirExpr::MethodCall(m) => self.check_method_call(m),
+            HirExpr::Upcast(u) => Ok(u.ty),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bumpalo::Bump;
+
+    use super::*;
+    use crate::atlas_frontend::{parse, parser::arena::AstArena};
+    use crate::atlas_hir::syntax_lowering_pass::AstSyntaxLoweringPass;
+
+    fn check(source: &str) -> HirResult<()> {
+        let bump = Bump::new();
+        let ast_arena = AstArena::new(&bump);
+        let ast = parse("test.atlas", &ast_arena, source.to_string()).unwrap();
+        let hir_arena = HirArena::new();
+        let path = PathBuf::from("test.atlas");
+        let mut hir =
+            AstSyntaxLoweringPass::new(&hir_arena, &ast, &ast_arena, path, source.to_string())
+                .lower()?;
+        TypeChecker::new(&hir_arena, source.to_string()).check(&mut hir)
+    }
+
+    #[test]
+    fn writes_through_immutable_variables() {
+        let source = r#"
+struct Point {
+    x: i64;
+}
+func reset(l: [i64], p: Point) {
+    l[0] = 0;
+    p.x = 0;
+    const nested: [[i64]] = [l];
+    nested[0][0] = 1;
+}
+"#;
+        check(source).unwrap();
+        let parameter = r#"
+func reset(l: [i64]) {
+    l = [];
+}
+"#;
+        let constant = r#"
+func reset() {
+    const l: [i64] = [1];
+    l = [2];
+}
+"#;
+        for rebinding in [parameter, constant] {
+            assert!(matches!(
+                check(rebinding),
+                Err(HirError::TryingToMutateImmutableVariable(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn reports_struct_literal_errors() {
+        let missing = r#"
+struct Point {
+    x: i64;
+    y: i64;
+}
+func main() -> i64 {
+    let p: Point = Point { x: 1 };
+    return p.x;
+}
+"#;
+        assert!(matches!(check(missing), Err(HirError::MissingFields(_))));
+        let unknown = r#"
+struct Point {
+    x: i64;
+}
+func main() -> i64 {
+    let p: Point = Point { x: 1 };
+    return p.z;
+}
+"#;
+        assert!(matches!(check(unknown), Err(HirError::UnknownField(_))));
+        let repeated = r#"
+struct P {
+    x: i64;
+}
+func main() -> i64 {
+    let p: P = P { x: 1, x: 2 };
+    return p.x;
+}
+"#;
+        assert!(matches!(check(repeated), Err(HirError::DuplicateField(_))));
+    }
+
+    #[test]
+    fn checks_list_items() {
+        let mixed = r#"
+func main() -> i64 {
+    let l: [i64] = [1, true];
+    return l[0];
+}
+"#;
+        assert!(matches!(check(mixed), Err(HirError::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn types_empty_lists_from_context() {
+        let returned = r#"
+func empty() -> [i64] {
+    return [];
+}
+func main() -> i64 {
+    let l: [i64] = empty();
+    return 0;
+}
+"#;
+        let argument = r#"
+func count(l: [i64]) -> i64 {
+    return 7;
+}
+func main() -> i64 {
+    return count([]);
+}
+"#;
+        let assigned = r#"
+func main() -> i64 {
+    let l: [i64] = [1, 2];
+    l = [];
+    let m: [[i64]] = [l];
+    m[0] = [];
+    return 3;
+}
+"#;
+        let field = r#"
+struct Node {
+    v: i64;
+    next: [Node];
+}
+func main() -> i64 {
+    let n: Node = Node { v: 1, next: [] };
+    n.next = [Node { v: 2, next: [] }];
+    return n.v * 10 + n.next[0].v;
+}
+"#;
+        for source in [returned, argument, assigned, field] {
+            check(source).unwrap();
+        }
+        let unhinted = r#"
+func main() -> i64 {
+    let x: i64 = 0;
+    x = [];
+    return x;
+}
+"#;
+        assert!(check(unhinted).is_err());
+    }
+
+    #[test]
+    fn orders_numbers_and_strings_only() {
+        let generic = r#"
+func max<T>(a: T, b: T) -> T {
+    if a < b {
+        return b;
+    }
+    return a;
+}
+func main() -> i64 {
+    return max(1, 2);
+}
+"#;
+        assert!(matches!(check(generic), Err(HirError::TypeMismatch(_))));
+        let lists = r#"
+func main() -> i64 {
+    let l: [i64] = [1];
+    if l > l {
+        return 1;
+    }
+    return 0;
+}
+"#;
+        assert!(matches!(check(lists), Err(HirError::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn reports_non_exhaustive_matches() {
+        let source = r#"
+enum Shape {
+    Circle(i64),
+    Empty
+}
+func main() -> i64 {
+    return match Shape::Empty {
+        Shape::Circle(r) ~> r
+    };
+}
+"#;
+        assert!(matches!(
+            check(source),
+            Err(HirError::NonExhaustiveMatch(_))
+        ));
+    }
+
+    #[test]
+    fn infers_type_arguments() {
+        let mismatch = r#"
+func pick<T>(a: T, b: T) -> T {
+    return a;
+}
+func main() -> i64 {
+    return pick(1, "one");
+}
+"#;
+        assert!(matches!(check(mismatch), Err(HirError::TypeMismatch(_))));
+    }
+
+    #[test]
+    fn takes_atlas_functions_as_values_only() {
+        let generic = r#"
+func id<T>(x: T) -> T {
+    return x;
+}
+func main() -> i64 {
+    let f: (i64) -> i64 = id;
+    return f(1);
+}
+"#;
+        assert!(matches!(check(generic), Err(HirError::CannotInferType(_))));
+        let native = r#"
+import "std/math"
+func main() -> i64 {
+    let f: (i64) -> i64 = abs;
+    return f(1);
+}
+"#;
+        assert!(matches!(check(native), Err(HirError::UnsupportedExpr(_))));
+    }
+
+    #[test]
+    fn checks_class_members() {
+        let private = r#"
+class Rect {
+    private:
+        w: i64;
+    public:
+        func new(w: i64) -> Rect {
+            return Rect { w: w };
+        }
+}
+func main() -> i64 {
+    let r = Rect::new(2);
+    return r.w;
+}
+"#;
+        assert!(matches!(check(private), Err(HirError::PrivateMember(_))));
+        let missing = r#"
+trait Shape {
+    func area(&self) -> i64;
+}
+class Dot : Shape {
+    public:
+        func new() -> Dot {
+            return Dot {};
+        }
+}
+func main() -> i64 {
+    return 0;
+}
+"#;
+        assert!(matches!(
+            check(missing),
+            Err(HirError::MissingTraitMethods(_))
+        ));
+    }
+
+    #[test]
+    fn reports_unsupported_statements() {
+        let outside = r#"
+func main() -> i64 {
+    break;
+    return 0;
+}
+"#;
+        assert!(matches!(check(outside), Err(HirError::BreakOutsideLoop(_))));
+        let in_lambda = r#"
+func main() -> i64 {
+    let i: i64 = 0;
+    while i < 3 {
+        let f: () -> unit = \() {
+            continue;
+        };
+        i = i + 1;
+    }
+    return i;
+}
+"#;
+        assert!(matches!(
+            check(in_lambda),
+            Err(HirError::ContinueOutsideLoop(_))
+        ));
+        let in_loop = r#"
+func main() -> i64 {
+    let i: i64 = 0;
+    while i < 3 {
+        break;
+    }
+    return i;
+}
+"#;
+        assert!(matches!(
+            check(in_loop),
+            Err(HirError::UnsupportedStatement(_))
+        ));
+    }
+}
@@ -17,3 +17,55 @@ macro_rules! declare_error_type {
         }
     }
 }
+
+/// Implements the conversion traits of [`atlas_vm::convert`](crate::atlas_vm::convert) for a Rust
+/// struct mirroring an Atlas struct of the same name.
+///
+/// The fields must be the ones of the Atlas struct, listed in the order it declares them. An
+/// `extern` declaration using a struct whose fields don't match is rejected:
+/// ```ignore
+/// struct Point { x: i64, y: i64 }
+/// atlas_struct!(Point { x, y });
+/// ```
+#[macro_export]
+macro_rules! atlas_struct {
+    ($name:ident { $($field:ident),* $(,)? }) => {
+        impl $crate::atlas_vm::convert::AtlasType for $name {
+            fn atlas_type() -> $crate::atlas_vm::native::NativeType {
+                $crate::atlas_vm::native::NativeType::Named(
+                    stringify!($name).to_string(),
+                    vec![$(stringify!($field).to_string()),*],
+                )
+            }
+        }
+
+        impl $crate::atlas_vm::convert::FromAtlas for $name {
+            fn from_atlas(
+                value: $crate::atlas_memory::vm_data::VMData,
+                memory: &$crate::atlas_memory::object_map::Memory,
+            ) -> $crate::atlas_vm::RuntimeResult<Self> {
+                let len = [$(stringify!($field)),*].len();
+                let fields = $crate::atlas_vm::convert::structure_fields(value, memory, len)?;
+                let mut fields = fields.iter();
+                Ok(Self {
+                    $($field: $crate::atlas_vm::convert::FromAtlas::from_atlas(
+                        *fields.next().unwrap(),
+                        memory,
+                    )?,)*
+                })
+            }
+        }
+
+        impl $crate::atlas_vm::convert::IntoAtlas for $name {
+            fn into_atlas(
+                self,
+                memory: &mut $crate::atlas_memory::object_map::Memory,
+            ) -> $crate::atlas_vm::RuntimeResult<$crate::atlas_memory::vm_data::VMData> {
+                let fields = vec![$(
+                    $crate::atlas_vm::convert::IntoAtlas::into_atlas(self.$field, memory)?
+                ),*];
+                $crate::atlas_vm::convert::new_structure(fields, memory)
+            }
+        }
+    };
+}
@@ -1,15 +1,56 @@
-use crate::atlas_memory::vm_data::VMData;
+use std::collections::HashMap;
+
+use crate::{
+    atlas_memory::vm_data::VMData,
+    atlas_vm::{errors::RuntimeError, RuntimeResult},
+};
+
+/// Number of objects a heap may hold unless the embedder sets another limit
+pub const DEFAULT_HEAP_LIMIT: usize = 1 << 20;
 
 /// Probably should be renamed lmao
 ///
-/// Need to find a way to make the memory shrink, grows, and garbage collect unused memory (by scanning the stack & VarMap)
+/// The heap starts with `min` slots & doubles when it's full, up to `limit` slots. Unreachable
+/// objects are reclaimed by [`Memory::collect`], a mark & sweep collector the VM runs before
+/// allocating once enough objects are alive, which also gives unused slots back.
 pub struct Memory {
     mem: Vec<Object>,
     pub free: ObjectIndex,
+    /// Number of slots the heap never shrinks below
+    min: usize,
+    /// Number of slots the heap never grows above
+    limit: usize,
+    /// Number of slots holding an object
+    live: usize,
+    /// Highest number of slots that held an object at once
+    peak: usize,
+    /// Number of collections run so far
+    collections: usize,
+    /// Number of live objects above which the next allocation collects first
+    threshold: usize,
+    /// Objects kept alive for native code, with the number of times each one was pinned
+    pinned: HashMap<ObjectIndex, usize>,
+}
+
+/// Usage of a [`Memory`] at some point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Objects allocated, reachable or not
+    pub live: usize,
+    /// Highest number of objects allocated at once
+    pub peak: usize,
+    /// Collections run since the heap was created
+    pub collections: usize,
+    /// Slots currently reserved for objects
+    pub capacity: usize,
+    /// Slots the heap may grow to
+    pub limit: usize,
+    /// Approximate number of bytes used by the slots & by what the objects own
+    pub bytes: usize,
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
 pub struct ObjectIndex {
     pub idx: u64,
 }
@@ -26,36 +67,183 @@ impl std::fmt::Display for ObjectIndex {
 }
 
 impl Memory {
+    /// A heap of `space` slots that never grows
     pub fn new(space: usize) -> Self {
-        Self {
+        Self::with_limit(space, space)
+    }
+
+    /// A heap of `space` slots that grows up to `limit` slots
+    pub fn with_limit(space: usize, limit: usize) -> Self {
+        let space = space.max(1);
+        let mut memory = Self {
             free: ObjectIndex::new(0),
-            mem: (0..space)
-                .map(|x| Object::Free {
-                    next: ObjectIndex::new(((x + 1) % space) as u64),
-                })
-                .collect(),
+            mem: Vec::with_capacity(space),
+            min: space,
+            limit: limit.max(space),
+            live: 0,
+            peak: 0,
+            collections: 0,
+            threshold: space / 2,
+            pinned: HashMap::new(),
+        };
+        memory.grow_to(space);
+        memory
+    }
+
+    /// Changes the number of slots the heap may grow to, it can't go below the last object
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.max(self.used()).max(1);
+        self.min = self.min.min(self.limit);
+        if self.mem.len() > self.limit {
+            self.mem.truncate(self.limit);
+            self.relink();
         }
+        self.threshold = self.threshold.min(self.limit);
     }
 
-    /// Need to add a way to increase `mem` size if we out of memory
-    /// And a way to clean it when there's too much memory (basically shrink and grow)
-    ///
-    /// Result<ObjectIndex, Object> should become Result<ObjectIndex, RuntimeError> with RuntimeError::OutOfMemory(Object)
-    pub fn put(&mut self, object: Object) -> Result<ObjectIndex, Object> {
-        let idx = self.free;
-        let v = self.get_mut(self.free);
-        let repl = std::mem::replace(v, object);
+    /// Adds free slots up to `len`, they're chained in order & the last one points to itself
+    fn grow_to(&mut self, len: usize) {
+        let start = self.mem.len();
+        self.mem.extend((start..len).map(|x| Object::Free {
+            next: ObjectIndex::new((x + 1).min(len - 1) as u64),
+        }));
+        self.free = ObjectIndex::new(start as u64);
+    }
 
-        match repl {
+    pub fn put(&mut self, object: Object) -> RuntimeResult<ObjectIndex> {
+        if !matches!(self.get(self.free), Object::Free { .. }) {
+            if self.mem.len() >= self.limit {
+                return Err(RuntimeError::OutOfMemory {
+                    object: object.kind(),
+                    limit: self.limit,
+                });
+            }
+            self.grow_to((self.mem.len() * 2).min(self.limit));
+        }
+        let idx = self.free;
+        match std::mem::replace(self.get_mut(idx), object) {
             Object::Free { next } => {
                 self.free = next;
+                self.live += 1;
+                self.peak = self.peak.max(self.live);
                 Ok(idx)
             }
-            _ => {
-                let obj = std::mem::replace(v, repl);
-                Err(obj)
+            _ => unreachable!(),
+        }
+    }
+
+    /// Number of objects currently allocated, reachable or not
+    pub fn live(&self) -> usize {
+        self.live
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        let bytes = self.mem.len() * std::mem::size_of::<Object>()
+            + self.mem.iter().map(Object::owned_bytes).sum::<usize>();
+        MemoryStats {
+            live: self.live,
+            peak: self.peak,
+            collections: self.collections,
+            capacity: self.mem.len(),
+            limit: self.limit,
+            bytes,
+        }
+    }
+
+    #[inline(always)]
+    pub fn should_collect(&self) -> bool {
+        self.live >= self.threshold
+    }
+
+    /// Keeps the object at `index` alive until it's unpinned as many times as it was pinned
+    pub fn pin(&mut self, index: ObjectIndex) {
+        *self.pinned.entry(index).or_insert(0) += 1;
+    }
+
+    pub fn unpin(&mut self, index: ObjectIndex) {
+        if let Some(count) = self.pinned.get_mut(&index) {
+            *count -= 1;
+            if *count == 0 {
+                self.pinned.remove(&index);
+            }
+        }
+    }
+
+    /// Frees every object that can't be reached from `roots` or from a pinned object, returns
+    /// the number of objects freed.
+    ///
+    /// Lists, structures & closures are traced through their items, fields & captures. When
+    /// most of the heap is free afterwards, the free slots at its end are released.
+    pub fn collect(&mut self, roots: impl IntoIterator<Item = VMData>) -> usize {
+        self.collections += 1;
+        let mut marked = vec![false; self.mem.len()];
+        let mut pending = roots
+            .into_iter()
+            .filter(|value| value.is_object())
+            .map(|value| value.as_object())
+            .chain(self.pinned.keys().copied())
+            .collect::<Vec<_>>();
+        while let Some(index) = pending.pop() {
+            let slot = index.idx as usize;
+            if std::mem::replace(&mut marked[slot], true) {
+                continue;
+            }
+            let children: &[VMData] = match &self.mem[slot] {
+                Object::List(items) => items,
+                Object::Structure(s) => &s.fields,
+                Object::Closure(c) => &c.captures,
+                _ => &[],
+            };
+            pending.extend(
+                children
+                    .iter()
+                    .filter(|value| value.is_object())
+                    .map(|value| value.as_object()),
+            );
+        }
+
+        let mut freed = 0;
+        for (object, marked) in self.mem.iter_mut().zip(marked) {
+            if !marked && !matches!(object, Object::Free { .. }) {
+                *object = Object::Free {
+                    next: ObjectIndex::new(0),
+                };
+                freed += 1;
+            }
+        }
+        self.live -= freed;
+
+        //Objects don't move, so only the free slots after the last object can be released
+        let len = (self.live * 2).max(self.used()).max(self.min);
+        if len < self.mem.len() / 2 {
+            self.mem.truncate(len);
+            self.mem.shrink_to_fit();
+        }
+        self.relink();
+        self.threshold = (self.live * 2).max(self.min / 2).min(self.limit);
+        freed
+    }
+
+    /// Number of slots up to the last object
+    fn used(&self) -> usize {
+        self.mem
+            .iter()
+            .rposition(|object| !matches!(object, Object::Free { .. }))
+            .map_or(0, |slot| slot + 1)
+    }
+
+    /// Rebuilds the free list in order, the last free slot points to itself
+    fn relink(&mut self) {
+        let mut next = None;
+        for (slot, object) in self.mem.iter_mut().enumerate().rev() {
+            if let Object::Free { next: link } = object {
+                let index = ObjectIndex::new(slot as u64);
+                *link = next.unwrap_or(index);
+                next = Some(index);
             }
         }
+        //Without any free slot, `free` points to a used one & the next allocation grows the heap
+        self.free = next.unwrap_or(ObjectIndex::new(0));
     }
 
     #[inline(always)]
@@ -86,6 +274,7 @@ pub enum Object {
     String(String),
     Structure(Structure),
     List(Vec<VMData>),
+    Closure(Closure),
     Free { next: ObjectIndex },
 }
 
@@ -95,6 +284,7 @@ impl std::fmt::Display for Object {
             Object::String(s) => write!(f, "{}", s),
             Object::Structure(s) => write!(f, "{:?}", s),
             Object::List(l) => write!(f, "{:?}", l),
+            Object::Closure(c) => write!(f, "{:?}", c),
             Object::Free { next } => write!(f, "Free: next -> {}", next),
         }
     }
@@ -105,6 +295,29 @@ impl Object {
         data.into()
     }
 
+    /// What the object is, as shown in errors
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Object::String(_) => "string",
+            Object::Structure(_) => "structure",
+            Object::List(_) => "list",
+            Object::Closure(_) => "closure",
+            Object::Free { .. } => "free slot",
+        }
+    }
+
+    /// Bytes the object owns outside of its slot
+    fn owned_bytes(&self) -> usize {
+        let value = std::mem::size_of::<VMData>();
+        match self {
+            Object::String(s) => s.capacity(),
+            Object::Structure(s) => s.fields.capacity() * value,
+            Object::List(l) => l.capacity() * value,
+            Object::Closure(c) => c.captures.capacity() * value,
+            Object::Free { .. } => 0,
+        }
+    }
+
     pub fn string(&self) -> &String {
         match &self {
             Object::String(s) => s,
@@ -146,6 +359,13 @@ impl Object {
             _ => unreachable!(),
         }
     }
+
+    pub fn closure(&self) -> &Closure {
+        match &self {
+            Object::Closure(c) => c,
+            _ => unreachable!(),
+        }
+    }
 }
 
 impl From<Structure> for Object {
@@ -170,3 +390,62 @@ impl From<Vec<VMData>> for Object {
 pub struct Structure {
     pub fields: Vec<VMData>,
 }
+
+/// A function alongside the values it captured when it was created
+#[derive(Clone, Debug)]
+pub struct Closure {
+    /// Position of the first instruction of the function
+    pub function: usize,
+    pub captures: Vec<VMData>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(memory: &mut Memory, s: &str) -> VMData {
+        VMData::new_string(memory.put(Object::String(s.to_string())).unwrap())
+    }
+
+    #[test]
+    fn collects_unreachable_objects() {
+        let mut memory = Memory::new(8);
+        let item = string(&mut memory, "item");
+        let list = memory.put(Object::List(vec![item])).unwrap();
+        let list = VMData::new_list(VMData::TAG_LIST, list);
+        let pinned = string(&mut memory, "pinned");
+        memory.pin(pinned.as_object());
+        string(&mut memory, "garbage");
+
+        assert_eq!(memory.collect([list]), 1);
+        assert_eq!(memory.live(), 3);
+        assert_eq!(memory.get(item.as_object()).string(), "item");
+
+        memory.unpin(pinned.as_object());
+        assert_eq!(memory.collect([]), 3);
+        //Every slot can be allocated again
+        for _ in 0..8 {
+            string(&mut memory, "new");
+        }
+        assert!(memory.put(Object::String(String::new())).is_err());
+    }
+
+    #[test]
+    fn grows_and_shrinks() {
+        let mut memory = Memory::with_limit(4, 16);
+        let values = (0..16)
+            .map(|_| string(&mut memory, "value"))
+            .collect::<Vec<_>>();
+        assert_eq!(memory.stats().capacity, 16);
+        assert!(matches!(
+            memory.put(Object::String(String::new())),
+            Err(RuntimeError::OutOfMemory { limit: 16, .. })
+        ));
+
+        assert_eq!(memory.collect(values[..2].iter().copied()), 14);
+        let stats = memory.stats();
+        assert_eq!((stats.live, stats.peak, stats.capacity), (2, 16, 4));
+        assert_eq!(stats.collections, 1);
+        assert_eq!(memory.get(values[1].as_object()).string(), "value");
+    }
+}
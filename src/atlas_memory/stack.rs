@@ -1,10 +1,8 @@
 use std::fmt::Display;
 
 use crate::{atlas_memory::vm_data::VMData, atlas_vm::errors::RuntimeError};
-/// The size of the stack in bytes, 16384 is the maximum before it overflows "thread main"
-///
-/// I'll try allocating the stack into the heap later on so
-const STACK_SIZE: usize = 16 * 16384 / size_of::<VMData>();
+/// Number of values the stack may hold unless the embedder sets another limit
+pub const DEFAULT_STACK_LIMIT: usize = 1 << 20;
 /// The stack should be more used overall.
 ///
 /// And allow features such as holding objects themselves e.g.
@@ -15,9 +13,12 @@ const STACK_SIZE: usize = 16 * 16384 / size_of::<VMData>();
 ///
 /// The stack should also be able to create new stack frames with special rules
 /// to access data in other stack frames
+///
+/// The values live on the heap & grow with the stack, up to `limit` values.
 #[derive(Debug)]
 pub struct Stack {
-    values: [VMData; STACK_SIZE],
+    values: Vec<VMData>,
+    limit: usize,
     pub top: usize,
 }
 impl Default for Stack {
@@ -29,20 +30,44 @@ impl Default for Stack {
 /// TODO: this implementation should be overhauled a bit cuz it's kinda clunky
 impl Stack {
     pub fn new() -> Self {
+        Self::with_limit(DEFAULT_STACK_LIMIT)
+    }
+
+    /// A stack holding at most `limit` values
+    pub fn with_limit(limit: usize) -> Self {
+        let mut values = Vec::with_capacity(limit.min(1024));
+        values.push(VMData::new_unit());
         Self {
-            values: [VMData::new_unit(); STACK_SIZE],
+            values,
+            limit: limit.max(1),
             top: 1,
         }
     }
 
+    /// Changes the number of values the stack may hold, it can't go below the values it holds
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.max(self.top);
+    }
+
     pub fn push(&mut self, val: VMData) -> Result<(), RuntimeError> {
-        if self.top < STACK_SIZE {
+        if self.top < self.values.len() {
             self.values[self.top] = val;
-            self.top += 1;
-            Ok(())
+        } else if self.top < self.limit {
+            self.values.push(val);
         } else {
-            Err(RuntimeError::StackOverflow)
+            //The VM knows where it was when the stack overflowed, it fills in the rest
+            return Err(RuntimeError::StackOverflow {
+                depth: 0,
+                function: None,
+            });
         }
+        self.top += 1;
+        Ok(())
+    }
+
+    /// The values currently on the stack, from the bottom
+    pub fn values(&self) -> &[VMData] {
+        &self.values[..self.top]
     }
 
     pub fn truncate(&mut self, new_top: usize) {
@@ -68,17 +93,26 @@ impl Stack {
         }
     }
 
-    pub fn push_object(&mut self, _obj: &[VMData]) -> Result<(), RuntimeError> {
-        unimplemented!("push_object(&mut self, obj: &[VMData])")
-    }
-
     pub fn new_stack_frame(&mut self) {}
 
-    pub fn get(&mut self, _offset: usize) -> Result<VMData, RuntimeError> {
-        unimplemented!("get(&mut self, offset: usize)")
+    /// Reads the value at the absolute position `offset`, which has to be below the top
+    pub fn get(&self, offset: usize) -> Result<VMData, RuntimeError> {
+        if offset < self.top {
+            Ok(self.values[offset])
+        } else {
+            Err(RuntimeError::IndexOutOfBounds)
+        }
     }
 
-    pub fn set(&mut self, _offset: usize) {}
+    /// Overwrites the value at the absolute position `offset`, which has to be below the top
+    pub fn set(&mut self, offset: usize, val: VMData) -> Result<(), RuntimeError> {
+        if offset < self.top {
+            self.values[offset] = val;
+            Ok(())
+        } else {
+            Err(RuntimeError::IndexOutOfBounds)
+        }
+    }
 }
 
 impl Display for Stack {
@@ -88,8 +122,8 @@ impl Display for Stack {
             "Stack: {{ values: {}, top: {}}}",
             {
                 let mut s = "[".to_string();
-                for i in 0..self.top - 1 {
-                    s.push_str(&format!("{}, ", self.values[i]))
+                for value in &self.values[..self.top.saturating_sub(1)] {
+                    s.push_str(&format!("{}, ", value))
                 }
                 s.push(']');
                 s
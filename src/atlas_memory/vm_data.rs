@@ -40,6 +40,14 @@ impl VMData {
     pub const TAG_BOOL: u64 = 10;
     pub const TAG_STR: u64 = 11;
     pub const TAG_CHAR: u64 = 12;
+    /// Tag of every list, whatever the type of its elements
+    pub const TAG_LIST: u64 = 257;
+    /// Tag of every user defined structure, the type is only known at compile time
+    pub const TAG_OBJECT: u64 = 258;
+    /// Tag of every closure, whatever its signature
+    pub const TAG_CLOSURE: u64 = 259;
+    /// Tag of every enum value, unlike structures they are compared by value
+    pub const TAG_ENUM: u64 = 260;
 
     pub fn new(tag: u64, data: RawVMData) -> Self {
         Self { tag, data }
@@ -229,6 +237,7 @@ macro_rules! enum_variant_function {
     ($getter: ident, $is: ident, $variant: ident, $ty: ty) => {
         #[inline(always)]
         #[must_use]
+        #[allow(clippy::must_use_unit)]
         pub fn $getter(self) -> $ty {
             unsafe { self.data.$getter }
         }
@@ -0,0 +1,270 @@
+//! The interactive prompt of `atlas_77 repl`.
+//!
+//! Imports & items are kept as source, while statements are executed as soon as they are entered.
+//! Each entry is compiled as the body of `main` along with every item declared so far, the `let`
+//! statements of the previous entries come first so the entry is type-checked against them, but
+//! only the new statements are generated. The VM is reused from one entry to the next, and
+//! `main` keeps its frame so the bindings of the previous entries are still in their slots.
+//!
+//! The code of an entry is linked after the code of the previous ones, which stays in place: a
+//! closure stored in a binding still points to the function it was made from. The vtables keep
+//! their ids from one entry to the next for the trait objects stored in bindings.
+
+use std::path::PathBuf;
+
+use atlas_core::prelude::Spanned;
+use bumpalo::Bump;
+
+use crate::{
+    atlas_codegen::{arena::CodeGenArena, CodeGenUnit},
+    atlas_frontend::{lexer::TokenKind, parse, parser::arena::AstArena, tokenize},
+    atlas_hir::{
+        arena::HirArena,
+        expr::HirExpr,
+        stmt::{HirReturn, HirStatement},
+        syntax_lowering_pass::AstSyntaxLoweringPass,
+        ty::HirTy,
+        type_check_pass::TypeChecker,
+    },
+    atlas_memory::vm_data::VMData,
+    atlas_vm::{instruction::Program, Atlas77VM},
+};
+
+pub struct Repl<'run> {
+    vm: Atlas77VM<'run>,
+    /// Holds the bytecode of every entry, the VM may refer to it
+    bump: &'run Bump,
+    /// Imports & items declared so far
+    items: String,
+    /// `let` & `const` statements of the previous entries, in order
+    bindings: Vec<String>,
+    /// Locals of `main` left on the stack by the previous entries, in slot order
+    locals: Vec<String>,
+    /// `(class, trait)` pair of each vtable of the previous entries, in id order
+    vtables: Vec<(String, String)>,
+}
+
+/// What an entry compiles to, applied to the session once it ran
+struct CompiledEntry<'run> {
+    program: Program<'run>,
+    bindings: Vec<String>,
+    locals: Vec<String>,
+    vtables: Vec<(String, String)>,
+}
+
+impl<'run> Repl<'run> {
+    pub fn new(bump: &'run Bump) -> Self {
+        Self {
+            vm: Atlas77VM::new(Program::new()),
+            bump,
+            items: String::new(),
+            bindings: Vec::new(),
+            locals: Vec::new(),
+            vtables: Vec::new(),
+        }
+    }
+
+    /// Compiles & runs `input`, returns the value of its last expression unless it is `unit`
+    pub fn eval(&mut self, input: &str) -> miette::Result<Option<String>> {
+        let input = input.trim();
+        let is_item = tokenize(input.to_string())?
+            .iter()
+            .map(|token| token.kind())
+            .find(|kind| *kind != TokenKind::SoI)
+            .is_some_and(|kind| {
+                matches!(
+                    kind,
+                    TokenKind::KwImport
+                        | TokenKind::KwFunc
+                        | TokenKind::KwExtern
+                        | TokenKind::KwStruct
+                        | TokenKind::KwEnum
+                        | TokenKind::KwClass
+                        | TokenKind::KwTrait
+                        | TokenKind::KwPublic
+                        | TokenKind::KwPrivate
+                )
+            });
+        let (items, statements) = if is_item {
+            (format!("{}{}\n", self.items, input), String::new())
+        } else if input.ends_with(';') || input.ends_with('}') {
+            (self.items.clone(), input.to_string())
+        } else {
+            (self.items.clone(), format!("{};", input))
+        };
+
+        let entry = self.compile(&items, &statements)?;
+        let value = self
+            .vm
+            .resume(entry.program)
+            .map_err(|e| miette::Report::new(self.vm.report(e)))?;
+        self.items = items;
+        self.bindings.extend(entry.bindings);
+        self.locals = entry.locals;
+        self.vtables = entry.vtables;
+
+        Ok(match value.tag {
+            VMData::TAG_UNIT => None,
+            VMData::TAG_STR => Some(format!(
+                "{:?}",
+                self.vm.object_map.get(value.as_object()).string()
+            )),
+            _ => Some(value.to_string()),
+        })
+    }
+
+    fn compile(&self, items: &str, statements: &str) -> miette::Result<CompiledEntry<'run>> {
+        let source = format!(
+            "{}func main() {{\n{}\n{}\n}}\n",
+            items,
+            self.bindings.join("\n"),
+            statements
+        );
+        //User imports are relative to the current directory
+        let path = std::env::current_dir()
+            .unwrap_or_default()
+            .join("repl.atlas");
+
+        let file_name = path.to_string_lossy().to_string();
+        let bump = Bump::new();
+        let ast_arena = AstArena::new(&bump);
+        let ast = parse(&file_name, &ast_arena, source.clone())?;
+
+        let hir_arena = HirArena::new();
+        let lower = AstSyntaxLoweringPass::new(
+            &hir_arena,
+            &ast,
+            &ast_arena,
+            PathBuf::from(&path),
+            source.clone(),
+        );
+        let mut hir = lower.lower()?;
+        let mut type_checker = TypeChecker::new(&hir_arena, source.clone());
+        type_checker.check(&mut hir)?;
+
+        //The previous statements already ran, only their bindings are needed
+        let main = hir.body.functions.get_mut("main").unwrap();
+        main.body.statements.drain(..self.bindings.len());
+        let bindings = main
+            .body
+            .statements
+            .iter()
+            .filter_map(|stmt| match stmt {
+                HirStatement::Let(l) | HirStatement::Const(l) => {
+                    let stmt = &source[l.span.start()..l.span.end()];
+                    Some(format!("{};", stmt.trim_end_matches(';')))
+                }
+                _ => None,
+            })
+            .collect();
+        //The value of a trailing expression is the result of the entry
+        if let Some(HirStatement::Expr(e)) = main.body.statements.last() {
+            if !matches!(e.expr, HirExpr::Assign(_)) && !matches!(e.expr.ty(), HirTy::Unit(_)) {
+                let Some(HirStatement::Expr(e)) = main.body.statements.pop() else {
+                    unreachable!()
+                };
+                main.body.statements.push(HirStatement::Return(HirReturn {
+                    span: e.span,
+                    ty: e.expr.ty(),
+                    value: e.expr,
+                }));
+            }
+        }
+
+        let arena = CodeGenArena::new(self.bump);
+        let locals = self.locals.iter().map(String::as_str).collect();
+        let vtables = self
+            .vtables
+            .iter()
+            .map(|(class, trait_name)| (class.as_str(), trait_name.as_str()))
+            .collect();
+        let mut codegen = CodeGenUnit::new(hir, arena, source)
+            .with_entry_locals(locals)
+            .with_vtables(vtables);
+        let program = codegen.compile()?;
+        let locals = codegen
+            .entry_locals()
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let vtables = program
+            .vtables
+            .iter()
+            .map(|v| (v.class.clone(), v.trait_name.clone()))
+            .collect();
+
+        Ok(CompiledEntry {
+            program,
+            bindings,
+            locals,
+            vtables,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_all(repl: &mut Repl, entries: &[&str]) -> Vec<Option<String>> {
+        entries.iter().map(|e| repl.eval(e).unwrap()).collect()
+    }
+
+    #[test]
+    fn keeps_bindings_and_items() {
+        let bump = Bump::new();
+        let mut repl = Repl::new(&bump);
+        let values = eval_all(
+            &mut repl,
+            &[
+                "let x = 5;",
+                "func triple(n: i64) -> i64 { return n * 3; }",
+                "if x > 1 { let y = 3; x = x + y; }",
+                "triple(x)",
+                "let x = \"five\";",
+                "x",
+            ],
+        );
+        assert_eq!(
+            values,
+            vec![
+                None,
+                None,
+                None,
+                Some("24".to_string()),
+                None,
+                Some("\"five\"".to_string())
+            ]
+        );
+        //A failing entry leaves the session as it was
+        assert!(repl.eval("triple(x)").is_err());
+        assert!(repl
+            .eval("func triple(n: i64) -> i64 { return n; }")
+            .is_err());
+        assert_eq!(repl.eval("triple(2)").unwrap(), Some("6".to_string()));
+    }
+
+    #[test]
+    fn keeps_closures_and_trait_objects() {
+        let bump = Bump::new();
+        let mut repl = Repl::new(&bump);
+        let values = eval_all(
+            &mut repl,
+            &[
+                "trait Named { func id(&self) -> i64; }",
+                "class One : Named { public: func new() -> One { return One {}; } #[override(Named::id)] func id(&self) -> i64 { return 1; } }",
+                "let n: Named = One::new();",
+                "let step = 10;",
+                "let f: (i64) -> i64 = \\(x: i64) ~> x + step;",
+                //Items added afterwards move the code of the functions around
+                "func aaa() -> i64 { return 100; }",
+                "class Two : Named { public: func new() -> Two { return Two {}; } #[override(Named::id)] func id(&self) -> i64 { return 2; } }",
+                "let m: Named = Two::new();",
+                "f(1) + n.id() * 100 + m.id() * 1000 + aaa()",
+                "f(2)",
+            ],
+        );
+        assert_eq!(values[8], Some("2211".to_string()));
+        assert_eq!(values[9], Some("12".to_string()));
+    }
+}
@@ -0,0 +1,60 @@
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::{
+    atlas_frontend::parser::error::ParseError, atlas_hir::error::HirError,
+    atlas_vm::bytecode::BytecodeError, atlas_vm::errors::RuntimeError, declare_error_type,
+};
+
+declare_error_type! {
+    #[error("atlas error: {0}")]
+    pub enum AtlasError {
+        Io(IoError),
+        Syntax(ParseError),
+        Semantic(HirError),
+        Bytecode(BytecodeError),
+        Runtime(RuntimeError),
+        Call(CallError),
+    }
+}
+
+pub type AtlasResult<T> = Result<T, AtlasError>;
+
+#[derive(Error, Diagnostic, Debug)]
+#[diagnostic(code(runtime::io))]
+#[error("could not read {path}")]
+pub struct IoError {
+    pub path: String,
+    #[source]
+    pub source: std::io::Error,
+}
+
+/// A call from Rust that doesn't match the function it calls
+#[derive(Error, Diagnostic, Debug)]
+pub enum CallError {
+    #[error("the module has no function `{0}`")]
+    #[diagnostic(code(runtime::unknown_function))]
+    UnknownFunction(String),
+    #[error("`{function}` takes {expected} arguments but {found} were given")]
+    #[diagnostic(code(runtime::arity_mismatch))]
+    ArityMismatch {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("argument {index} of `{function}` has type {expected}, not {found}")]
+    #[diagnostic(code(runtime::argument_type_mismatch))]
+    ArgumentTypeMismatch {
+        function: String,
+        index: usize,
+        expected: String,
+        found: String,
+    },
+    #[error("`{function}` returns {expected}, not {found}")]
+    #[diagnostic(code(runtime::return_type_mismatch))]
+    ReturnTypeMismatch {
+        function: String,
+        expected: String,
+        found: String,
+    },
+}
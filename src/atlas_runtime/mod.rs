@@ -1,3 +1,1037 @@
 /// This crate will contain a small runtime for the compiler.
 #[deprecated]
 pub mod visitor;
+
+pub mod error;
+
+use std::{collections::HashMap, path::PathBuf};
+
+use bumpalo::Bump;
+
+use crate::{
+    atlas_codegen::{arena::CodeGenArena, CodeGenUnit},
+    atlas_frontend::{parse, parser::arena::AstArena},
+    atlas_hir::{
+        arena::HirArena, signature::HirModuleSignature,
+        syntax_lowering_pass::AstSyntaxLoweringPass, ty::HirTy, type_check_pass::TypeChecker,
+    },
+    atlas_memory::{
+        object_map::{MemoryStats, DEFAULT_HEAP_LIMIT},
+        stack::DEFAULT_STACK_LIMIT,
+    },
+    atlas_vm::{
+        bytecode,
+        convert::{FromAtlas, IntoArgs},
+        debug::{RuntimeReport, StackTrace},
+        errors::RuntimeError,
+        instruction::{ImportedLibrary, Program},
+        linker::{self, LinkedProgram},
+        native::{NativeModule, NativeType},
+        Atlas77VM,
+    },
+};
+use error::{AtlasResult, CallError, IoError};
+
+/// Compiles Atlas code into modules & runs them along the native modules of the host.
+///
+/// ```ignore
+/// let runtime = Runtime::builder()
+///     .module(NativeModule::new("host").function("double", |x: i64| x * 2))
+///     .build();
+/// let module = runtime.compile("func score(a: i64, b: i64) -> i64 { return a * b; }")?;
+/// let mut vm = runtime.instantiate(&module)?;
+/// let score: i64 = vm.call("score", (3, 4))?;
+/// ```
+#[derive(Clone, Default)]
+pub struct Runtime {
+    modules: Vec<NativeModule>,
+    /// Number of objects the heap of an instance may hold, [`DEFAULT_HEAP_LIMIT`] when `None`
+    heap_limit: Option<usize>,
+    /// Number of values the stack of an instance may hold, [`DEFAULT_STACK_LIMIT`] when `None`
+    stack_limit: Option<usize>,
+}
+
+#[derive(Clone, Default)]
+pub struct RuntimeBuilder {
+    modules: Vec<NativeModule>,
+    heap_limit: Option<usize>,
+    stack_limit: Option<usize>,
+}
+
+impl RuntimeBuilder {
+    /// Registers `module`, Atlas code can then `import` it by its name
+    pub fn module(mut self, module: NativeModule) -> Self {
+        self.modules.push(module);
+        self
+    }
+    /// Caps the heap of each instance to `objects` objects, allocating past it is an
+    /// [`RuntimeError::OutOfMemory`](crate::atlas_vm::errors::RuntimeError::OutOfMemory)
+    pub fn heap_limit(mut self, objects: usize) -> Self {
+        self.heap_limit = Some(objects);
+        self
+    }
+    /// Caps the stack of each instance to `values` values, which bounds how deep calls can go
+    pub fn stack_limit(mut self, values: usize) -> Self {
+        self.stack_limit = Some(values);
+        self
+    }
+    pub fn build(self) -> Runtime {
+        Runtime {
+            modules: self.modules,
+            heap_limit: self.heap_limit,
+            stack_limit: self.stack_limit,
+        }
+    }
+}
+
+/// A compiled program, it can be instantiated any number of times
+#[derive(Debug, Clone)]
+pub struct Module {
+    linked: LinkedProgram,
+    libraries: Vec<ImportedLibrary>,
+    functions: HashMap<String, FunctionSignature>,
+}
+
+/// Types of the parameters & result of a function of a module, as seen from Rust.
+///
+/// The types without a Rust counterpart (functions, type parameters) are `None` & not checked.
+#[derive(Debug, Clone)]
+pub struct FunctionSignature {
+    pub params: Vec<Option<NativeType>>,
+    pub ret: Option<NativeType>,
+}
+
+impl Module {
+    pub fn function(&self, name: &str) -> Option<&FunctionSignature> {
+        self.functions.get(name)
+    }
+}
+
+impl Runtime {
+    pub fn builder() -> RuntimeBuilder {
+        RuntimeBuilder::default()
+    }
+    pub fn modules(&self) -> &[NativeModule] {
+        &self.modules
+    }
+    /// Compiles `source`, its imports are relative to the current directory
+    pub fn compile(&self, source: &str) -> AtlasResult<Module> {
+        let path = std::env::current_dir()
+            .unwrap_or_default()
+            .join("module.atlas");
+        self.compile_source(path, source.to_string())
+    }
+    pub fn compile_file(&self, path: &str) -> AtlasResult<Module> {
+        let source = std::fs::read_to_string(path).map_err(|source| IoError {
+            path: path.to_string(),
+            source,
+        })?;
+        let path = PathBuf::from(path);
+        let path = match path.is_absolute() {
+            true => path,
+            false => std::env::current_dir().unwrap_or_default().join(path),
+        };
+        self.compile_source(path, source)
+    }
+    fn compile_source(&self, path: PathBuf, source: String) -> AtlasResult<Module> {
+        let file_name = path.to_string_lossy().to_string();
+        let bump = Bump::new();
+        let ast_arena = AstArena::new(&bump);
+        let ast = parse(&file_name, &ast_arena, source.clone())?;
+
+        let hir_arena = HirArena::new();
+        let mut hir =
+            AstSyntaxLoweringPass::new(&hir_arena, &ast, &ast_arena, path, source.clone())
+                .with_natives(&self.modules)
+                .lower()?;
+        TypeChecker::new(&hir_arena, source.clone()).check(&mut hir)?;
+        let functions = hir
+            .body
+            .functions
+            .iter()
+            .map(|(name, function)| {
+                let signature = FunctionSignature {
+                    params: function
+                        .signature
+                        .params
+                        .iter()
+                        .map(|p| native_type(p.ty, &hir.signature))
+                        .collect(),
+                    ret: native_type(function.signature.return_ty, &hir.signature),
+                };
+                (name.to_string(), signature)
+            })
+            .collect();
+
+        let bump = Bump::new();
+        let program = CodeGenUnit::new(hir, CodeGenArena::new(&bump), source).compile()?;
+        bytecode::check_libraries(&program, &self.modules)?;
+        Ok(Module {
+            linked: linker::link(&program)?,
+            libraries: program.libraries.iter().map(|l| (*l).clone()).collect(),
+            functions,
+        })
+    }
+    /// A fresh VM running `module`, with its own memory
+    pub fn instantiate(&self, module: &Module) -> AtlasResult<Instance> {
+        let mut vm = Atlas77VM::new(Program::new())
+            .with_natives(&self.modules)
+            .with_heap_limit(self.heap_limit.unwrap_or(DEFAULT_HEAP_LIMIT))
+            .with_stack_limit(self.stack_limit.unwrap_or(DEFAULT_STACK_LIMIT));
+        vm.load(module.linked.clone(), &module.libraries)?;
+        Ok(Instance {
+            vm,
+            functions: module.functions.clone(),
+        })
+    }
+}
+
+/// A module loaded in its own VM, the objects it allocates live as long as the instance
+pub struct Instance {
+    vm: Atlas77VM<'static>,
+    functions: HashMap<String, FunctionSignature>,
+}
+
+impl Instance {
+    /// Calls the function `name` with the tuple `args`, the arguments & the result are checked
+    /// against the signature of the function
+    pub fn call<R: FromAtlas>(&mut self, name: &str, args: impl IntoArgs) -> AtlasResult<R> {
+        let signature = self
+            .functions
+            .get(name)
+            .ok_or_else(|| CallError::UnknownFunction(name.to_string()))?;
+        let types = Self::arg_types(&args);
+        if types.len() != signature.params.len() {
+            return Err(CallError::ArityMismatch {
+                function: name.to_string(),
+                expected: signature.params.len(),
+                found: types.len(),
+            }
+            .into());
+        }
+        for (index, (expected, found)) in signature.params.iter().zip(types).enumerate() {
+            if let Some(expected) = expected.as_ref().filter(|ty| **ty != found) {
+                return Err(CallError::ArgumentTypeMismatch {
+                    function: name.to_string(),
+                    index,
+                    expected: expected.to_string(),
+                    found: found.to_string(),
+                }
+                .into());
+            }
+        }
+        if let Some(expected) = signature.ret.as_ref().filter(|ty| **ty != R::atlas_type()) {
+            return Err(CallError::ReturnTypeMismatch {
+                function: name.to_string(),
+                expected: expected.to_string(),
+                found: R::atlas_type().to_string(),
+            }
+            .into());
+        }
+
+        let target = self
+            .vm
+            .linked_function(name)
+            .ok_or_else(|| CallError::UnknownFunction(name.to_string()))?;
+        let args = args.into_args(&mut self.vm.object_map)?;
+        let res = self.vm.call(target, &args)?;
+        Ok(R::from_atlas(res, &self.vm.object_map)?)
+    }
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.vm.memory_stats()
+    }
+    /// Calls that were in progress when the instance last failed, the innermost one first
+    pub fn stack_trace(&self) -> &StackTrace {
+        self.vm.stack_trace()
+    }
+    /// Renders `error` with the code of the failing expression & of every call that led to it
+    pub fn report(&self, error: RuntimeError) -> RuntimeReport {
+        self.vm.report(error)
+    }
+    /// Runs `main`
+    pub fn run<R: FromAtlas>(&mut self) -> AtlasResult<R> {
+        self.call("main", ())
+    }
+    fn arg_types<A: IntoArgs>(_: &A) -> Vec<NativeType> {
+        A::types()
+    }
+}
+
+/// The Rust counterpart of `ty`, if it has one
+fn native_type(ty: &HirTy, signature: &HirModuleSignature) -> Option<NativeType> {
+    Some(match ty {
+        HirTy::Int64(_) => NativeType::Int64,
+        HirTy::Float64(_) => NativeType::Float64,
+        HirTy::UInt64(_) => NativeType::UInt64,
+        HirTy::Boolean(_) => NativeType::Bool,
+        HirTy::String(_) => NativeType::Str,
+        HirTy::Unit(_) => NativeType::Unit,
+        HirTy::List(l) => NativeType::List(Box::new(native_type(l.inner, signature)?)),
+        HirTy::Named(n) => NativeType::Named(
+            n.name.to_string(),
+            signature
+                .structs
+                .get(n.name)
+                .map(|s| s.fields.iter().map(|f| f.name.to_string()).collect())
+                .unwrap_or_default(),
+        ),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        atlas_hir::error::HirError,
+        atlas_memory::vm_data::VMData,
+        atlas_runtime::error::AtlasError,
+        atlas_struct,
+        atlas_vm::{
+            errors::RuntimeError,
+            native::{NativeModule, NativeType},
+            vm_state::VMState,
+            RuntimeResult,
+        },
+    };
+
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+    atlas_struct!(Point { x, y });
+
+    fn runtime() -> Runtime {
+        Runtime::builder()
+            .module(
+                NativeModule::new("host")
+                    .function("double", |x: i64| x * 2)
+                    .function("mirror", |p: Point| Point { x: p.y, y: p.x })
+                    .function("join", |words: Vec<String>| words.join(" ")),
+            )
+            .build()
+    }
+
+    fn run(source: &str) -> AtlasResult<i64> {
+        let runtime = runtime();
+        runtime.instantiate(&runtime.compile(source)?)?.run()
+    }
+
+    /// Writes `files` to a directory of their own & runs its `main.atlas`
+    fn run_files(name: &str, files: &[(&str, &str)]) -> AtlasResult<i64> {
+        let dir = std::env::temp_dir().join(format!("atlas_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, source) in files {
+            std::fs::write(dir.join(file), source).unwrap();
+        }
+        let runtime = runtime();
+        let result = runtime
+            .compile_file(&dir.join("main.atlas").display().to_string())
+            .and_then(|module| runtime.instantiate(&module)?.run());
+        std::fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    #[test]
+    fn reports_index_out_of_bounds() {
+        let source = r#"
+func main() -> i64 {
+    let l: [i64] = [1];
+    return l[5];
+}
+"#;
+        assert!(matches!(
+            run(source),
+            Err(AtlasError::Runtime(RuntimeError::IndexOutOfBounds))
+        ));
+    }
+    #[test]
+    fn imports_modules() {
+        let shared = r#"
+struct Pair {
+    a: i64;
+    b: i64;
+}
+func sum(p: Pair) -> i64 {
+    return p.a + p.b;
+}
+"#;
+        let left = r#"
+import "shared"
+import "std/math"
+func left() -> Pair {
+    return Pair { a: abs(1 - 3), b: 1 };
+}
+"#;
+        let right = r#"
+import "shared"
+func right() -> Pair {
+    return Pair { a: 30, b: 40 };
+}
+"#;
+        let main = r#"
+import "left"
+import "right"
+import "std/math"
+func main() -> i64 {
+    return sum(left()) + sum(right()) + abs(0 - 100);
+}
+"#;
+        let files = [
+            ("shared.atlas", shared),
+            ("left.atlas", left),
+            ("right.atlas", right),
+            ("main.atlas", main),
+        ];
+        assert_eq!(run_files("diamond", &files).unwrap(), 173);
+    }
+
+    #[test]
+    fn resolves_aliased_imports() {
+        let word = r#"
+func len(w: str) -> i64 {
+    return 5;
+}
+"#;
+        let numbers = r#"
+func double(x: i64) -> i64 {
+    return x * 2;
+}
+func len(x: i64) -> i64 {
+    return double(x);
+}
+"#;
+        let main = r#"
+import "std/math" as m;
+import "word" as w;
+import "numbers" as n;
+func double(x: i64) -> i64 {
+    return x * 1000;
+}
+func main() -> i64 {
+    return m::abs(0 - 3) + w::len("hello") * 10 + n::len(21) * 100 + double(1);
+}
+"#;
+        let files = [
+            ("word.atlas", word),
+            ("numbers.atlas", numbers),
+            ("main.atlas", main),
+        ];
+        assert_eq!(run_files("alias", &files).unwrap(), 5253);
+    }
+    #[test]
+    fn namespaces_aliased_types() {
+        let geo = r#"
+struct Point {
+    x: i64;
+    y: i64;
+}
+enum Shape {
+    Dot(Point),
+    Empty
+}
+class Counter {
+    private:
+        n: i64;
+    public:
+        func new(n: i64) -> Counter {
+            return Counter { n: n };
+        }
+        func get(&self) -> i64 {
+            return self.n;
+        }
+}
+func make(x: i64) -> Point {
+    return Point { x: x, y: 2 };
+}
+func size(s: Shape) -> i64 {
+    return match s {
+        Shape::Dot(p) ~> p.x,
+        _ ~> 0
+    };
+}
+"#;
+        let lib = r#"
+import "geo" as g;
+func origin() -> g::Point {
+    return g::Point { x: 7, y: 0 };
+}
+"#;
+        //The local `Point` doesn't clash with the one of `geo`
+        let main = r#"
+import "geo" as u;
+import "lib" as l;
+struct Point {
+    z: i64;
+}
+func main() -> i64 {
+    let local: Point = Point { z: 1000 };
+    let p: u::Point = u::make(3);
+    let q: u::Point = u::Point { x: 20, y: 0 };
+    let o: l::g::Point = l::origin();
+    let c: u::Counter = u::Counter::new(400);
+    let n: i64 = match u::Shape::Dot(q) {
+        u::Shape::Dot(d) ~> d.x,
+        _ ~> 0
+    };
+    return local.z + c.get() + n + p.x + o.x * 10000 + u::size(u::Shape::Empty);
+}
+"#;
+        let files = [("geo.atlas", geo), ("lib.atlas", lib), ("main.atlas", main)];
+        assert_eq!(run_files("types", &files).unwrap(), 71423);
+        let mixed = r#"
+import "geo" as u;
+struct Point {
+    z: i64;
+}
+func main() -> i64 {
+    let p: Point = u::make(3);
+    return p.z;
+}
+"#;
+        let files = [("geo.atlas", geo), ("main.atlas", mixed)];
+        assert!(matches!(
+            run_files("mixed_types", &files),
+            Err(AtlasError::Semantic(HirError::TypeMismatch(_)))
+        ));
+    }
+
+    #[test]
+    fn links_calls_closures_and_vtables() {
+        let source = r#"
+trait Scaled {
+    func scale(&self) -> i64;
+}
+class Ten : Scaled {
+    public:
+        func new() -> Ten {
+            return Ten {};
+        }
+        #[override(Scaled::scale)]
+        func scale(&self) -> i64 {
+            return 10;
+        }
+}
+func fact(n: i64) -> i64 {
+    if n < 2 {
+        return 1;
+    }
+    return n * fact(n - 1);
+}
+func apply(f: (i64) -> i64, x: i64) -> i64 {
+    return f(x);
+}
+func scaled(s: Scaled, x: i64) -> i64 {
+    return s.scale() * x;
+}
+func main() -> i64 {
+    return scaled(Ten::new(), apply(fact, 4));
+}
+"#;
+        let runtime = runtime();
+        let module = runtime.compile(source).unwrap();
+        //Every instance runs the same linked code
+        for _ in 0..2 {
+            let mut vm = runtime.instantiate(&module).unwrap();
+            assert_eq!(vm.call::<i64>("fact", (5_i64,)).unwrap(), 120);
+            assert_eq!(vm.run::<i64>().unwrap(), 240);
+        }
+    }
+
+    #[test]
+    fn reports_integer_overflow() {
+        let source = r#"
+func main() -> i64 {
+    let x: i64 = 9223372036854775807;
+    return x + 1;
+}
+"#;
+        assert!(matches!(
+            run(source),
+            Err(AtlasError::Runtime(RuntimeError::IntegerOverflow))
+        ));
+    }
+
+    #[test]
+    fn reports_invalid_std_arguments() {
+        let failures = [
+            "pow(2, 100)",
+            "pow(2, 0 - 1)",
+            "abs(min - 1)",
+            "random(3, 3)",
+            "get(l, 3_u64)",
+            "set(l, 3_u64, 0)",
+            "slice(l, 2_u64, 4_u64)",
+            "slice(l, 2_u64, 1_u64)",
+        ];
+        for call in failures {
+            let source = format!(
+                r#"
+import "std/math"
+import "std/list"
+func main() -> i64 {{
+    let l: [i64] = [1, 2, 3];
+    let min: i64 = 0 - 9223372036854775807;
+    {};
+    return 0;
+}}
+"#,
+                call
+            );
+            assert!(
+                matches!(
+                    run(&source),
+                    Err(AtlasError::Runtime(
+                        RuntimeError::IntegerOverflow
+                            | RuntimeError::IndexOutOfBounds
+                            | RuntimeError::NativeFunctionFailed(_)
+                    ))
+                ),
+                "{}",
+                call
+            );
+        }
+    }
+
+    #[test]
+    fn calls_native_function() {
+        let source = r#"
+import "host"
+extern double(x: i64) -> i64;
+func main() -> i64 {
+    return double(21);
+}
+"#;
+        assert_eq!(run(source).unwrap(), 42);
+    }
+
+    #[test]
+    fn converts_structs() {
+        let source = r#"
+import "host"
+struct Point {
+    x: i64;
+    y: i64;
+}
+extern mirror(p: Point) -> Point;
+func main() -> i64 {
+    let p: Point = mirror(Point { x: 1, y: 2 });
+    return p.x * 10 + p.y;
+}
+"#;
+        assert_eq!(run(source).unwrap(), 21);
+    }
+
+    #[test]
+    fn rejects_wrong_signature() {
+        let source = r#"
+import "host"
+extern double(x: f64) -> i64;
+func main() -> i64 {
+    return double(1.0);
+}
+"#;
+        assert!(matches!(
+            run(source),
+            Err(AtlasError::Semantic(HirError::NativeSignatureMismatch(_)))
+        ));
+        //`mirror` takes & returns a `Point { x, y }`, declared after the function or not
+        for (fields, matches) in [
+            ("x: i64; y: i64;", true),
+            ("y: i64; x: i64;", false),
+            ("x: i64;", false),
+        ] {
+            let source = format!(
+                r#"
+import "host"
+extern mirror(p: Point) -> Point;
+struct Point {{
+    {}
+}}
+func main() -> i64 {{
+    return 0;
+}}
+"#,
+                fields
+            );
+            let result = run(&source);
+            assert_eq!(
+                !matches!(
+                    result,
+                    Err(AtlasError::Semantic(HirError::NativeSignatureMismatch(_)))
+                ),
+                matches,
+                "{}",
+                fields
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_native_modules_apart() {
+        let runtime = Runtime::builder()
+            .module(
+                NativeModule::new("twice")
+                    .function("apply", |x: i64| x * 2)
+                    .function("len", |x: u64| x * 10),
+            )
+            .module(NativeModule::new("thrice").function("apply", |x: i64| x * 3))
+            .build();
+        let run = |source: &str| -> AtlasResult<u64> {
+            runtime.instantiate(&runtime.compile(source)?)?.run()
+        };
+        let apply = r#"
+extern apply(x: i64) -> i64;
+func main() -> u64 {
+    if apply(7) == 21 {
+        return 1_u64;
+    }
+    return 0_u64;
+}
+"#;
+
+        //The function called is the one of the module whose signature was checked
+        assert_eq!(
+            run(&format!(
+                r#"
+import "thrice"
+{}"#,
+                apply
+            ))
+            .unwrap(),
+            1
+        );
+        assert_eq!(
+            run(&format!(
+                r#"
+import "twice"
+{}"#,
+                apply
+            ))
+            .unwrap(),
+            0
+        );
+        assert!(matches!(
+            run(&format!(
+                r#"
+import "twice"
+import "thrice"
+{}"#,
+                apply
+            )),
+            Err(AtlasError::Semantic(HirError::NativeConflict(_)))
+        ));
+        assert!(matches!(
+            run(apply),
+            Err(AtlasError::Semantic(HirError::UnknownNative(_)))
+        ));
+        //A function of the host doesn't replace the one of the standard library it shadows
+        let source = r#"
+import "std/list" as l;
+import "twice"
+extern len(x: u64) -> u64;
+func main() -> u64 {
+    return len(l::len([1, 2]));
+}
+"#;
+        assert_eq!(run(source).unwrap(), 20);
+    }
+
+    #[test]
+    fn reports_natives_registered_twice() {
+        let runtime = Runtime::builder()
+            .module(NativeModule::new("host").function("apply", |x: i64| x * 2))
+            .module(NativeModule::new("host").function("apply", |x: i64| x * 3))
+            .build();
+        let source = r#"
+import "host"
+extern apply(x: i64) -> i64;
+func main() -> i64 {
+    return apply(7);
+}
+"#;
+        assert!(matches!(
+            runtime.compile(source),
+            Err(AtlasError::Semantic(HirError::NativeConflict(_)))
+        ));
+        //Both are still registered when the program doesn't use them
+        let source = r#"
+import "host"
+func main() -> i64 {
+    return 0;
+}
+"#;
+        let module = runtime.compile(source).unwrap();
+        assert!(matches!(
+            runtime.instantiate(&module),
+            Err(AtlasError::Runtime(RuntimeError::DuplicateExtern { .. }))
+        ));
+    }
+
+    #[test]
+    fn derives_signatures() {
+        let module = runtime().modules()[0].clone();
+        assert_eq!(module.get("double").unwrap().signature(), "(i64) -> i64");
+        assert_eq!(
+            module.get("mirror").unwrap().signature(),
+            "(Point) -> Point"
+        );
+        assert_eq!(module.get("join").unwrap().signature(), "([str]) -> str");
+    }
+
+    #[test]
+    fn calls_atlas_functions() {
+        let runtime = runtime();
+        let module = runtime
+            .compile(
+                r#"
+struct Point {
+    x: i64;
+    y: i64;
+}
+func score(a: i64, b: i64) -> i64 {
+    return a * 10 + b;
+}
+func origin(p: Point) -> bool {
+    if p.x == 0 {
+        return p.y == 0;
+    }
+    return false;
+}
+func shout(s: str) -> str {
+    return s + "!";
+}
+"#,
+            )
+            .unwrap();
+        let mut vm = runtime.instantiate(&module).unwrap();
+        assert_eq!(vm.call::<i64>("score", (4_i64, 2_i64)).unwrap(), 42);
+        assert_eq!(vm.call::<i64>("score", (1_i64, 2_i64)).unwrap(), 12);
+        assert!(vm.call::<bool>("origin", (Point { x: 0, y: 0 },)).unwrap());
+        assert_eq!(
+            vm.call::<String>("shout", ("hi".to_string(),)).unwrap(),
+            "hi!"
+        );
+    }
+
+    #[test]
+    fn reports_call_errors() {
+        let runtime = runtime();
+        let module = runtime
+            .compile(
+                r#"
+func at(l: [i64], i: i64) -> i64 {
+    return l[i];
+}
+"#,
+            )
+            .unwrap();
+        let mut vm = runtime.instantiate(&module).unwrap();
+        assert!(matches!(
+            vm.call::<i64>("missing", ()),
+            Err(AtlasError::Call(CallError::UnknownFunction(_)))
+        ));
+        assert!(matches!(
+            vm.call::<i64>("at", (vec![1_i64],)),
+            Err(AtlasError::Call(CallError::ArityMismatch { .. }))
+        ));
+        assert!(matches!(
+            vm.call::<i64>("at", (vec![1.0], 0_i64)),
+            Err(AtlasError::Call(CallError::ArgumentTypeMismatch {
+                index: 0,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            vm.call::<String>("at", (vec![1_i64], 0_i64)),
+            Err(AtlasError::Call(CallError::ReturnTypeMismatch { .. }))
+        ));
+        assert!(matches!(
+            vm.call::<i64>("at", (vec![1_i64], 5_i64)),
+            Err(AtlasError::Runtime(RuntimeError::IndexOutOfBounds))
+        ));
+        assert_eq!(vm.call::<i64>("at", (vec![4_i64, 2], 1_i64)).unwrap(), 2);
+    }
+
+    #[test]
+    fn collects_garbage() {
+        let source = r#"
+func main() -> i64 {
+    let keep: [str] = ["kept"];
+    let i: i64 = 0;
+    let s: str = "";
+    while i < 5000 {
+        s = "x" + "y";
+        i = i + 1;
+    }
+    return i;
+}
+"#;
+        //Every iteration allocates strings, the loop only gets through if the garbage is reclaimed
+        let runtime = Runtime::builder().heap_limit(16).build();
+        let module = runtime.compile(source).unwrap();
+        let mut vm = runtime.instantiate(&module).unwrap();
+        assert_eq!(vm.run::<i64>().unwrap(), 5000);
+        let stats = vm.memory_stats();
+        assert!(stats.collections > 100);
+        assert!(stats.peak <= 16);
+    }
+
+    #[test]
+    fn keeps_objects_natives_allocate() {
+        //The second allocation collects the heap while the first string is only held by Rust
+        fn keep(mut state: VMState) -> RuntimeResult<VMData> {
+            let first = state.alloc("first".to_string())?;
+            state.alloc("second".to_string())?;
+            Ok(first)
+        }
+        let source = r#"
+import "gc"
+extern keep() -> str;
+func main() -> i64 {
+    let kept: i64 = 0;
+    let i: i64 = 0;
+    while i < 100 {
+        if keep() == "first" {
+            kept = kept + 1;
+        }
+        i = i + 1;
+    }
+    return kept;
+}
+"#;
+        let runtime = Runtime::builder()
+            .module(NativeModule::new("gc").raw_function("keep", &[], NativeType::Str, keep))
+            .heap_limit(4)
+            .build();
+        let module = runtime.compile(source).unwrap();
+        let mut vm = runtime.instantiate(&module).unwrap();
+        assert_eq!(vm.run::<i64>().unwrap(), 100);
+        assert!(vm.memory_stats().live <= 4);
+    }
+
+    #[test]
+    fn grows_heap_up_to_limit() {
+        let source = r#"
+import "std/list"
+func records(n: u64) -> u64 {
+    let l: [str] = [];
+    let i: u64 = 0_u64;
+    while i < n {
+        push(l, "record");
+        i = i + 1_u64;
+    }
+    return len(l);
+}
+"#;
+        let module = runtime().compile(source).unwrap();
+        let mut vm = runtime().instantiate(&module).unwrap();
+        assert_eq!(vm.call::<u64>("records", (5000_u64,)).unwrap(), 5000);
+        assert!(vm.memory_stats().peak > 5000);
+
+        let runtime = Runtime::builder().heap_limit(1000).build();
+        let mut vm = runtime.instantiate(&module).unwrap();
+        assert!(matches!(
+            vm.call::<u64>("records", (5000_u64,)),
+            Err(AtlasError::Runtime(RuntimeError::OutOfMemory {
+                limit: 1000,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn recurses_up_to_stack_limit() {
+        let source = r#"
+func depth(n: i64) -> i64 {
+    if n == 0 {
+        return 0;
+    }
+    return 1 + depth(n - 1);
+}
+"#;
+        let module = runtime().compile(source).unwrap();
+        let mut vm = runtime().instantiate(&module).unwrap();
+        assert_eq!(vm.call::<i64>("depth", (100_000_i64,)).unwrap(), 100_000);
+
+        let runtime = Runtime::builder().stack_limit(1000).build();
+        let mut vm = runtime.instantiate(&module).unwrap();
+        match vm.call::<i64>("depth", (100_000_i64,)) {
+            Err(AtlasError::Runtime(RuntimeError::StackOverflow { depth, function })) => {
+                assert!(depth > 100);
+                assert_eq!(function.as_deref(), Some("depth"));
+            }
+            _ => panic!("expected a stack overflow"),
+        }
+    }
+
+    #[test]
+    fn keeps_locals_of_recursive_frames() {
+        let source = r#"
+func sum(n: i64) -> i64 {
+    if n == 0 {
+        return 0;
+    }
+    let a: i64 = n * 10;
+    if n > 0 {
+        let b: i64 = n;
+        let rest: i64 = sum(n - 1);
+        return a + b + rest;
+    }
+    return 0;
+}
+"#;
+        let module = runtime().compile(source).unwrap();
+        let mut vm = runtime().instantiate(&module).unwrap();
+        //`a` & `b` are read back after the calls below them returned
+        assert_eq!(vm.call::<i64>("sum", (4_i64,)).unwrap(), 110);
+
+        //Each frame reserves its 4 slots (`n`, `a`, `b` & `rest`) on entry, the argument of the
+        //next call is pushed right after them: 4 more values make room for exactly one more frame
+        let depth = |limit: usize| {
+            let runtime = Runtime::builder().stack_limit(limit).build();
+            let mut vm = runtime.instantiate(&module).unwrap();
+            match vm.call::<i64>("sum", (100_000_i64,)) {
+                Err(AtlasError::Runtime(RuntimeError::StackOverflow { depth, .. })) => depth,
+                _ => panic!("expected a stack overflow"),
+            }
+        };
+        for limit in 1000..1004 {
+            assert_eq!(depth(limit + 4), depth(limit) + 1);
+        }
+        assert_eq!(depth(1400), depth(1000) + 100);
+        //The failed call dropped its frames
+        assert_eq!(vm.call::<i64>("sum", (4_i64,)).unwrap(), 110);
+    }
+
+    #[test]
+    fn traces_runtime_errors() {
+        let source = r#"
+func at(l: [i64], i: i64) -> i64 {
+    return l[i];
+}
+func first(l: [i64]) -> i64 {
+    return at(l, 3);
+}
+"#;
+        let module = runtime().compile(source).unwrap();
+        let mut vm = runtime().instantiate(&module).unwrap();
+        assert!(matches!(
+            vm.call::<i64>("first", (vec![1_i64],)),
+            Err(AtlasError::Runtime(RuntimeError::IndexOutOfBounds))
+        ));
+        let code = vm
+            .stack_trace()
+            .frames
+            .iter()
+            .map(|frame| {
+                let location = frame.location.unwrap();
+                (
+                    frame.function.as_str(),
+                    &source[location.start..location.end],
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(code, [("at", "l[i]"), ("first", "at(l, 3)")]);
+    }
+}
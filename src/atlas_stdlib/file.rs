@@ -1,5 +1,5 @@
 use crate::{
-    atlas_memory::{object_map::Object, vm_data::VMData},
+    atlas_memory::vm_data::VMData,
     atlas_vm::{errors::RuntimeError, vm_state::VMState, CallBack},
 };
 
@@ -13,65 +13,48 @@ pub const FILE_FUNCTIONS: [(&str, CallBack); 5] = [
     ("remove_file", remove_file),
 ];
 
-pub fn read_dir(state: VMState) -> Result<VMData, RuntimeError> {
-    let path_ptr = state.stack.pop()?.as_object();
-    let path = state.object_map.get(path_ptr).string();
+/// The error of an operation on `path`, as reported to the program
+fn io_error(path: &str, e: std::io::Error) -> RuntimeError {
+    RuntimeError::NativeFunctionFailed(format!("{}: {}", path, e))
+}
+
+pub fn read_dir(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let path: String = state.pop()?;
 
-    let entries = std::fs::read_dir(path).unwrap();
+    let entries = std::fs::read_dir(&path).map_err(|e| io_error(&path, e))?;
     let mut list = Vec::new();
     for entry in entries {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        let path_str = path.to_str().unwrap();
-        let obj_idx = state.object_map.put(Object::String(path_str.to_string()));
-        match obj_idx {
-            Ok(index) => list.push(VMData::new_string(index)),
-            Err(_) => return Err(RuntimeError::OutOfMemory),
-        }
-    }
-
-    let list_idx = state.object_map.put(Object::List(list));
-    match list_idx {
-        Ok(index) => Ok(VMData::new_list(257, index)),
-        Err(_) => Err(RuntimeError::OutOfMemory),
+        let entry = entry.map_err(|e| io_error(&path, e))?;
+        list.push(entry.path().to_string_lossy().to_string());
     }
+    state.alloc(list)
 }
 
-pub fn read_file(state: VMState) -> Result<VMData, RuntimeError> {
-    let path_ptr = state.stack.pop()?.as_object();
-    let path = state.object_map.get(path_ptr).string();
+pub fn read_file(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let path: String = state.pop()?;
 
-    let content = std::fs::read_to_string(path).unwrap();
-    let obj_idx = state.object_map.put(Object::String(content));
-    match obj_idx {
-        Ok(index) => Ok(VMData::new_string(index)),
-        Err(_) => Err(RuntimeError::OutOfMemory),
-    }
+    let content = std::fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+    state.alloc(content)
 }
 
-pub fn write_file(state: VMState) -> Result<VMData, RuntimeError> {
-    let content_ptr = state.stack.pop()?.as_object();
-    let path_ptr = state.stack.pop()?.as_object();
-
-    let path = state.object_map.get(path_ptr).string();
-    let content = state.object_map.get(content_ptr).string();
+pub fn write_file(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let content: String = state.pop()?;
+    let path: String = state.pop()?;
 
-    std::fs::write(path, content).unwrap();
+    std::fs::write(&path, content).map_err(|e| io_error(&path, e))?;
     Ok(VMData::new_unit())
 }
 
-pub fn file_exists(state: VMState) -> Result<VMData, RuntimeError> {
-    let path_ptr = state.stack.pop()?.as_object();
-    let path = state.object_map.get(path_ptr).string();
+pub fn file_exists(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let path: String = state.pop()?;
 
     let exists = std::path::Path::new(&path).exists();
     Ok(VMData::new_bool(exists))
 }
 
-pub fn remove_file(state: VMState) -> Result<VMData, RuntimeError> {
-    let path_ptr = state.stack.pop()?.as_object();
-    let path = state.object_map.get(path_ptr).string();
+pub fn remove_file(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let path: String = state.pop()?;
 
-    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(&path).map_err(|e| io_error(&path, e))?;
     Ok(VMData::new_unit())
 }
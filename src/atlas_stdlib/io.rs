@@ -1,6 +1,6 @@
 use crate::{
-    atlas_memory::{object_map::Object, vm_data::VMData},
-    atlas_vm::{errors::RuntimeError, vm_state::VMState, CallBack},
+    atlas_memory::vm_data::VMData,
+    atlas_vm::{convert::FromAtlas, errors::RuntimeError, vm_state::VMState, CallBack},
 };
 
 pub const IO_ATLAS: &str = include_str!("io.atlas");
@@ -14,46 +14,31 @@ pub const IO_FUNCTIONS: [(&str, CallBack); 7] = [
     ("print_bool", println),
     ("print_uint", println),
 ];
-pub fn println(state: VMState) -> Result<VMData, RuntimeError> {
-    let val = state.stack.pop()?;
-    match val.tag {
-        VMData::TAG_BOOL | VMData::TAG_U64 | VMData::TAG_I64 | VMData::TAG_FLOAT => {
-            println!("{}", val)
-        }
-        VMData::TAG_STR => {
-            println!("{}", state.object_map.get(val.as_object()).string())
-        }
-        _ => {
-            println!("{}", state.object_map.get(val.as_object()))
-        }
-    }
+
+/// Pops a value of any type & renders it, the content of a string or the object it refers to
+fn pop_displayed(state: &mut VMState) -> Result<String, RuntimeError> {
+    let val: VMData = state.pop()?;
+    Ok(match val.tag {
+        VMData::TAG_STR => String::from_atlas(val, state.object_map)?,
+        _ if val.is_object() => state.object_map.get(val.as_object()).to_string(),
+        _ => val.to_string(),
+    })
+}
+
+pub fn println(mut state: VMState) -> Result<VMData, RuntimeError> {
+    println!("{}", pop_displayed(&mut state)?);
     Ok(VMData::new_unit())
 }
 
-pub fn print(state: VMState) -> Result<VMData, RuntimeError> {
-    let val = state.stack.pop()?;
-    match val.tag {
-        VMData::TAG_BOOL | VMData::TAG_U64 | VMData::TAG_I64 | VMData::TAG_FLOAT => {
-            print!("{}", val)
-        }
-        VMData::TAG_STR => {
-            print!("{}", state.object_map.get(val.as_object()).string())
-        }
-        _ => {
-            print!("{}", state.object_map.get(val.as_object()))
-        }
-    }
+pub fn print(mut state: VMState) -> Result<VMData, RuntimeError> {
+    print!("{}", pop_displayed(&mut state)?);
     Ok(VMData::new_unit())
 }
 
-pub fn input(state: VMState) -> Result<VMData, RuntimeError> {
+pub fn input(mut state: VMState) -> Result<VMData, RuntimeError> {
     let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
-    let obj_index = state
-        .object_map
-        .put(Object::String(input.trim().to_string()));
-    match obj_index {
-        Ok(index) => Ok(VMData::new_string(index)),
-        Err(_) => Err(RuntimeError::OutOfMemory),
-    }
+    std::io::stdin()
+        .read_line(&mut input)
+        .map_err(|e| RuntimeError::NativeFunctionFailed(format!("stdin: {}", e)))?;
+    state.alloc(input.trim().to_string())
 }
@@ -1,6 +1,6 @@
 use crate::{
-    atlas_memory::{object_map::Object, vm_data::VMData},
-    atlas_vm::{errors::RuntimeError, vm_state::VMState, CallBack},
+    atlas_memory::vm_data::VMData,
+    atlas_vm::{convert::ListHandle, errors::RuntimeError, vm_state::VMState, CallBack},
 };
 
 pub const LIST_ATLAS: &str = include_str!("list.atlas");
@@ -15,58 +15,66 @@ pub const LIST_FUNCTIONS: [(&str, CallBack); 7] = [
     ("slice", slice),
 ];
 
-pub fn len(state: VMState) -> Result<VMData, RuntimeError> {
-    let list_ptr = state.stack.pop()?.as_object();
-    let list = state.object_map.get(list_ptr).list();
-    Ok(VMData::new_i64(list.len() as i64))
+pub fn len(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let list: ListHandle = state.pop()?;
+    let len = list.items(state.object_map)?.len();
+    Ok(VMData::new_u64(len as u64))
 }
 
-pub fn get(state: VMState) -> Result<VMData, RuntimeError> {
-    let index = state.stack.pop()?.as_i64();
-    let list_ptr = state.stack.pop()?.as_object();
-    let list = state.object_map.get(list_ptr).list();
-    Ok(list[index as usize])
+pub fn get(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let index: u64 = state.pop()?;
+    let list: ListHandle = state.pop()?;
+    list.items(state.object_map)?
+        .get(index as usize)
+        .copied()
+        .ok_or(RuntimeError::IndexOutOfBounds)
 }
 
-pub fn set(state: VMState) -> Result<VMData, RuntimeError> {
-    let value = state.stack.pop()?;
-    let index = state.stack.pop()?.as_i64();
-    let list_ptr = state.stack.pop()?.as_object();
-    let list = state.object_map.get_mut(list_ptr).list_mut();
-    list[index as usize] = value;
+pub fn set(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let value: VMData = state.pop()?;
+    let index: u64 = state.pop()?;
+    let list: ListHandle = state.pop()?;
+    *list
+        .items_mut(state.object_map)?
+        .get_mut(index as usize)
+        .ok_or(RuntimeError::IndexOutOfBounds)? = value;
     Ok(VMData::new_unit())
 }
 
-pub fn push(state: VMState) -> Result<VMData, RuntimeError> {
-    let value = state.stack.pop()?;
-    let list_ptr = state.stack.pop()?.as_object();
-    let list = state.object_map.get_mut(list_ptr).list_mut();
-    list.push(value);
+pub fn push(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let value: VMData = state.pop()?;
+    let list: ListHandle = state.pop()?;
+    list.items_mut(state.object_map)?.push(value);
     Ok(VMData::new_unit())
 }
 
-pub fn pop(state: VMState) -> Result<VMData, RuntimeError> {
-    let list_ptr = state.stack.pop()?.as_object();
-    let list = state.object_map.get_mut(list_ptr).list_mut();
-    Ok(list.pop().unwrap())
+pub fn pop(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let list: ListHandle = state.pop()?;
+    list.items_mut(state.object_map)?
+        .pop()
+        .ok_or(RuntimeError::IndexOutOfBounds)
 }
 
-pub fn remove(state: VMState) -> Result<VMData, RuntimeError> {
-    let index = state.stack.pop()?.as_i64();
-    let list_ptr = state.stack.pop()?.as_object();
-    let list = state.object_map.get_mut(list_ptr).list_mut();
-    Ok(list.remove(index as usize))
+pub fn remove(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let index: u64 = state.pop()?;
+    let list: ListHandle = state.pop()?;
+    let items = list.items_mut(state.object_map)?;
+    if index as usize >= items.len() {
+        return Err(RuntimeError::IndexOutOfBounds);
+    }
+    Ok(items.remove(index as usize))
 }
 
-pub fn slice(state: VMState) -> Result<VMData, RuntimeError> {
-    let end = state.stack.pop()?.as_i64();
-    let start = state.stack.pop()?.as_i64();
-    let list_ptr = state.stack.pop()?.as_object();
-    let list = state.object_map.get(list_ptr).list();
-    let sliced = list[start as usize..end as usize].to_vec();
-    let obj_idx = state.object_map.put(Object::List(sliced));
-    match obj_idx {
-        Ok(index) => Ok(VMData::new_list(257, index)),
-        Err(_) => Err(RuntimeError::OutOfMemory),
-    }
+pub fn slice(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let end: u64 = state.pop()?;
+    let start: u64 = state.pop()?;
+    let list: ListHandle = state.pop()?;
+    //The items are only reachable from the list while the slice is allocated
+    state.pin(list.value());
+    let sliced = list
+        .items(state.object_map)?
+        .get(start as usize..end as usize)
+        .ok_or(RuntimeError::IndexOutOfBounds)?
+        .to_vec();
+    state.alloc(sliced)
 }
@@ -18,42 +18,61 @@ pub const MATH_FUNCTIONS: [(&str, CallBack); 7] = [
     ("random", random),
 ];
 
-pub fn abs(state: VMState) -> Result<VMData, RuntimeError> {
-    let val = state.stack.pop()?.as_i64();
-    Ok(VMData::new_i64(val.abs()))
+pub fn abs(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let val: i64 = state.pop()?;
+    val.checked_abs()
+        .map(VMData::new_i64)
+        .ok_or(RuntimeError::IntegerOverflow)
 }
 
-pub fn pow(state: VMState) -> Result<VMData, RuntimeError> {
-    let exponent = state.stack.pop()?.as_i64();
-    let base = state.stack.pop()?.as_i64();
-    Ok(VMData::new_i64(base.pow(exponent as u32)))
+pub fn pow(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let exponent: i64 = state.pop()?;
+    let base: i64 = state.pop()?;
+    if exponent < 0 {
+        return Err(RuntimeError::NativeFunctionFailed(format!(
+            "pow: negative exponent {}",
+            exponent
+        )));
+    }
+    u32::try_from(exponent)
+        .ok()
+        .and_then(|exponent| base.checked_pow(exponent))
+        .map(VMData::new_i64)
+        .ok_or(RuntimeError::IntegerOverflow)
 }
 
-pub fn sqrt(state: VMState) -> Result<VMData, RuntimeError> {
-    let val = state.stack.pop()?.as_f64();
+pub fn sqrt(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let val: f64 = state.pop()?;
     Ok(VMData::new_f64(val.sqrt()))
 }
 
-pub fn min(state: VMState) -> Result<VMData, RuntimeError> {
-    let v1 = state.stack.pop()?.as_i64();
-    let v2 = state.stack.pop()?.as_i64();
+pub fn min(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let v1: i64 = state.pop()?;
+    let v2: i64 = state.pop()?;
     Ok(VMData::new_i64(std::cmp::min(v1, v2)))
 }
 
-pub fn max(state: VMState) -> Result<VMData, RuntimeError> {
-    let v1 = state.stack.pop()?.as_i64();
-    let v2 = state.stack.pop()?.as_i64();
+pub fn max(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let v1: i64 = state.pop()?;
+    let v2: i64 = state.pop()?;
     Ok(VMData::new_i64(std::cmp::max(v1, v2)))
 }
 
-pub fn round(state: VMState) -> Result<VMData, RuntimeError> {
-    let val = state.stack.pop()?.as_f64();
+pub fn round(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let val: f64 = state.pop()?;
     Ok(VMData::new_i64(val.round() as i64))
 }
 
-pub fn random(state: VMState) -> Result<VMData, RuntimeError> {
-    let range = (state.stack.pop()?.as_i64(), state.stack.pop()?.as_i64());
+pub fn random(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let max: i64 = state.pop()?;
+    let min: i64 = state.pop()?;
+    if min >= max {
+        return Err(RuntimeError::NativeFunctionFailed(format!(
+            "random: the range {}..{} is empty",
+            min, max
+        )));
+    }
     let mut rng = thread_rng();
-    let random = rng.gen_range(range.1..range.0);
+    let random = rng.gen_range(min..max);
     Ok(VMData::new_i64(random))
 }
@@ -4,3 +4,6 @@ pub mod list;
 pub mod math;
 pub mod string;
 pub mod time;
+
+/// Standard libraries the runtime provides native functions for, as imported with `std/<name>`
+pub const STD_LIBRARIES: [&str; 6] = ["file", "io", "list", "math", "string", "time"];
@@ -1,11 +1,6 @@
-// All of this is assuming that Time is of type: Time(sec: i64, nsec: i64)
-// Time will have a tag of 256 as it will be the first type defined by the compiler (0-255 are reserved for the compiler)
-
 use crate::{
-    atlas_memory::{
-        object_map::{Object, Structure},
-        vm_data::VMData,
-    },
+    atlas_memory::vm_data::VMData,
+    atlas_struct,
     atlas_vm::{errors::RuntimeError, vm_state::VMState, CallBack},
 };
 
@@ -20,93 +15,81 @@ pub const TIME_FUNCTIONS: [(&str, CallBack); 4] = [
     ("elapsed", elapsed),
 ];
 
-//now() -> &Time
-pub fn now(state: VMState) -> Result<VMData, RuntimeError> {
-    let time = std::time::SystemTime::now();
-    let duration = time.duration_since(std::time::UNIX_EPOCH).unwrap();
+/// The `Time` struct of `time.atlas`
+pub struct Time {
+    pub sec: i64,
+    pub nsec: i64,
+}
 
-    let sec = duration.as_secs();
-    let nsec = duration.subsec_nanos();
+atlas_struct!(Time { sec, nsec });
 
-    let obj_idx = state.object_map.put(Object::Structure(Structure {
-        fields: vec![VMData::new_i64(sec as i64), VMData::new_i64(nsec as i64)],
-    }));
-    match obj_idx {
-        Ok(index) => Ok(VMData::new_object(25, index)),
-        Err(_) => Err(RuntimeError::OutOfMemory),
+impl Time {
+    fn to_date_time(&self) -> Result<OffsetDateTime, RuntimeError> {
+        OffsetDateTime::from_unix_timestamp(self.sec)
+            .map_err(|e| time_error(e.to_string()))?
+            .checked_add(time::Duration::nanoseconds(self.nsec))
+            .ok_or_else(|| time_error(format!("{}s {}ns is out of range", self.sec, self.nsec)))
     }
 }
 
-//format_time_iso(time: &Time) -> &string
-pub fn format_time_iso(state: VMState) -> Result<VMData, RuntimeError> {
-    let time_ptr = state.stack.pop()?.as_object();
-    let time_obj = state.object_map.get(time_ptr).structure();
-
-    let sec = time_obj.fields[0].as_i64();
-    let nsec = time_obj.fields[1].as_i64();
-
-    let time =
-        OffsetDateTime::from_unix_timestamp(sec).unwrap() + time::Duration::nanoseconds(nsec);
+fn time_error(reason: String) -> RuntimeError {
+    RuntimeError::NativeFunctionFailed(format!("time: {}", reason))
+}
 
-    let fmt =
-        format_description::parse("[year]-[month]-[day]T[hour]:[minute]:[second].[frac][offset]")
-            .unwrap();
-    let formatted = time.format(&fmt).unwrap();
+//now() -> &Time
+pub fn now(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let time = std::time::SystemTime::now();
+    let duration = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| time_error(e.to_string()))?;
+
+    state.alloc(Time {
+        sec: duration.as_secs() as i64,
+        nsec: duration.subsec_nanos() as i64,
+    })
+}
 
-    let obj_idx = state.object_map.put(Object::String(formatted));
-    match obj_idx {
-        Ok(index) => Ok(VMData::new_string(index)),
-        Err(_) => Err(RuntimeError::OutOfMemory),
-    }
+//format_time_iso(time: &Time) -> &string
+pub fn format_time_iso(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let time: Time = state.pop()?;
+
+    let fmt = format_description::parse_borrowed::<2>(
+        "[year]-[month]-[day]T[hour]:[minute]:[second].[frac][offset]",
+    )
+    .map_err(|e| time_error(e.to_string()))?;
+    let formatted = time
+        .to_date_time()?
+        .format(&fmt)
+        .map_err(|e| time_error(e.to_string()))?;
+
+    state.alloc(formatted)
 }
 
 //format_time(time: &Time, format: &string) -> &string
-pub fn format_time(state: VMState) -> Result<VMData, RuntimeError> {
-    let format_ptr = state.stack.pop()?.as_object(); // a string is an object
-    let time_ptr = state.stack.pop()?.as_object();
-
-    let fmt_str = state.object_map.get(format_ptr).string();
-    let time_obj = state.object_map.get(time_ptr).structure();
-
-    let sec = time_obj.fields[0].as_i64();
-    let nsec = time_obj.fields[1].as_i64();
-
-    let time =
-        OffsetDateTime::from_unix_timestamp(sec).unwrap() + time::Duration::nanoseconds(nsec);
-
-    let fmt = format_description::parse(fmt_str).unwrap();
-    let formatted = time.format(&fmt).unwrap();
-
-    let obj_idx = state.object_map.put(Object::String(formatted));
-    match obj_idx {
-        Ok(index) => Ok(VMData::new_string(index)),
-        Err(_) => Err(RuntimeError::OutOfMemory),
-    }
+pub fn format_time(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let fmt_str: String = state.pop()?;
+    let time: Time = state.pop()?;
+
+    let fmt = format_description::parse_borrowed::<2>(&fmt_str)
+        .map_err(|e| time_error(format!("{:?}: {}", fmt_str, e)))?;
+    let formatted = time
+        .to_date_time()?
+        .format(&fmt)
+        .map_err(|e| time_error(e.to_string()))?;
+
+    state.alloc(formatted)
 }
 
 // elapsed(start: &Time, end: &Time) -> &Time
-pub fn elapsed(state: VMState) -> Result<VMData, RuntimeError> {
-    let end_ptr = state.stack.pop()?.as_object();
-    let start_ptr = state.stack.pop()?.as_object();
-
-    let start_obj = state.object_map.get(start_ptr).structure();
-    let end_obj = state.object_map.get(end_ptr).structure();
-
-    let start_sec = start_obj.fields[0].as_i64();
-    let start_nsec = start_obj.fields[1].as_i64();
-
-    let end_sec = end_obj.fields[0].as_i64();
-    let end_nsec = end_obj.fields[1].as_i64();
-
-    let elapsed_sec = end_sec - start_sec;
-    let elapsed_nsec = end_nsec - start_nsec;
-
-    let obj_idx = state.object_map.put(Object::Structure(Structure {
-        fields: vec![VMData::new_i64(elapsed_sec), VMData::new_i64(elapsed_nsec)],
-    }));
-
-    match obj_idx {
-        Ok(index) => Ok(VMData::new_object(25, index)),
-        Err(_) => Err(RuntimeError::OutOfMemory),
-    }
+pub fn elapsed(mut state: VMState) -> Result<VMData, RuntimeError> {
+    let end: Time = state.pop()?;
+    let start: Time = state.pop()?;
+
+    let elapsed = end
+        .sec
+        .checked_sub(start.sec)
+        .zip(end.nsec.checked_sub(start.nsec))
+        .map(|(sec, nsec)| Time { sec, nsec })
+        .ok_or(RuntimeError::IntegerOverflow)?;
+    state.alloc(elapsed)
 }
@@ -0,0 +1,953 @@
+//! Binary format of compiled programs (`.atlasc` files).
+//!
+//! A file is laid out as:
+//! - the header: [`MAGIC`] followed by the [`FORMAT_VERSION`] as a little endian u16
+//! - the constant pool: the string pool, then the function pool
+//! - the entry point, as an index in the function pool
+//! - the imported libraries
+//! - the vtables
+//! - the labels, with the size of their frame & their instructions
+//! - the debug info, if the program has some: the source files, then the location of each
+//!   instruction
+//!
+//! Integers are LEB128 encoded (zigzag for the signed ones), floats are 8 little endian bytes.
+//! Every string is stored once in the constant pool & referred to by its index.
+
+use std::collections::HashMap;
+
+use bumpalo::Bump;
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::{
+    atlas_codegen::arena::CodeGenArena,
+    atlas_stdlib::STD_LIBRARIES,
+    atlas_vm::{
+        debug::{DebugInfo, SourceFile, SourceLocation},
+        instruction::{ConstantPool, ImportedLibrary, Instruction, Label, Program, VTable},
+        native::NativeModule,
+    },
+};
+
+/// First bytes of every bytecode file
+pub const MAGIC: [u8; 4] = *b"A77C";
+/// Bumped whenever the layout of the file or the instruction set changes
+pub const FORMAT_VERSION: u16 = 5;
+
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq)]
+pub enum BytecodeError {
+    #[error("not an Atlas77 bytecode file")]
+    #[diagnostic(code(bytecode::invalid_magic))]
+    InvalidMagic,
+    #[error("unsupported bytecode format version {found}")]
+    #[diagnostic(
+        code(bytecode::unsupported_version),
+        help("this runtime reads version {expected}, rebuild the program with it")
+    )]
+    UnsupportedVersion { found: u16, expected: u16 },
+    #[error("unexpected end of the bytecode file")]
+    #[diagnostic(code(bytecode::unexpected_eof))]
+    UnexpectedEof,
+    #[error("unknown opcode {0:#04x}")]
+    #[diagnostic(code(bytecode::invalid_opcode))]
+    InvalidOpcode(u8),
+    #[error("no constant at index {0} of the {1} pool")]
+    #[diagnostic(code(bytecode::invalid_constant))]
+    InvalidConstant(usize, &'static str),
+    #[error("invalid UTF-8 string in the constant pool")]
+    #[diagnostic(code(bytecode::invalid_utf8))]
+    InvalidUtf8,
+    #[error("integer out of range in the bytecode file")]
+    #[diagnostic(code(bytecode::integer_overflow))]
+    IntegerOverflow,
+    #[error("{0} unexpected bytes at the end of the bytecode file")]
+    #[diagnostic(code(bytecode::trailing_bytes))]
+    TrailingBytes(usize),
+    #[error("instruction {index} of `{function}` {reason}")]
+    #[diagnostic(
+        code(bytecode::invalid_instruction),
+        help("the file may be corrupted, rebuild the program")
+    )]
+    InvalidInstruction {
+        function: String,
+        index: usize,
+        reason: &'static str,
+    },
+    #[error("`{0}` isn't a function of the program")]
+    #[diagnostic(code(bytecode::unknown_function))]
+    UnknownFunction(String),
+    #[error("the program imports `{0}`, which this runtime doesn't provide")]
+    #[diagnostic(code(bytecode::unknown_library))]
+    UnknownLibrary(String),
+}
+
+pub type BytecodeResult<T> = Result<T, BytecodeError>;
+
+/// Serializes `program` into the binary format
+pub fn encode(program: &Program) -> Vec<u8> {
+    let mut strings = Pool::default();
+    let mut functions = Pool::default();
+    functions.add(program.entry_point);
+    for label in program.labels {
+        functions.add(&label.name);
+        for instr in label.body {
+            match instr {
+                Instruction::PushString(s) => strings.add(s),
+                Instruction::ExternCall { library, name, .. } => {
+                    strings.add(library);
+                    strings.add(name);
+                }
+                Instruction::CallFunction { name: f, .. }
+                | Instruction::MakeClosure { function: f, .. } => {
+                    functions.add(f);
+                }
+                _ => {}
+            }
+        }
+    }
+    for lib in program.libraries {
+        strings.add(&lib.name);
+    }
+    for vtable in program.vtables {
+        strings.add(&vtable.class);
+        strings.add(&vtable.trait_name);
+        for method in &vtable.methods {
+            functions.add(method);
+        }
+    }
+    let pool = ConstantPool {
+        string_pool: &strings.values,
+        function_pool: &functions.values,
+    };
+
+    let mut w = Writer::default();
+    w.bytes.extend_from_slice(&MAGIC);
+    w.bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    for pool in [pool.string_pool, pool.function_pool] {
+        w.uint(pool.len());
+        for s in pool {
+            w.str(s);
+        }
+    }
+    w.uint(functions.get(program.entry_point));
+    w.uint(program.libraries.len());
+    for lib in program.libraries {
+        w.uint(strings.get(&lib.name));
+        w.bytes.push(lib.is_std as u8);
+    }
+    w.uint(program.vtables.len());
+    for vtable in program.vtables {
+        w.uint(strings.get(&vtable.class));
+        w.uint(strings.get(&vtable.trait_name));
+        w.uint(vtable.methods.len());
+        for method in &vtable.methods {
+            w.uint(functions.get(method));
+        }
+    }
+    w.uint(program.labels.len());
+    for label in program.labels {
+        w.uint(functions.get(&label.name));
+        w.uint(label.position);
+        w.uint(label.locals);
+        w.uint(label.body.len());
+        for instr in label.body {
+            w.instruction(instr, &strings, &functions);
+        }
+    }
+    match program.debug {
+        Some(debug) => {
+            w.bytes.push(1);
+            w.uint(debug.files.len());
+            for file in &debug.files {
+                w.str(&file.path);
+                w.str(&file.src);
+            }
+            w.uint(debug.locations.len());
+            //0 stands for an instruction without location, the files are numbered from 1
+            for location in &debug.locations {
+                match location {
+                    Some(l) => {
+                        w.uint(l.file + 1);
+                        w.uint(l.start);
+                        w.uint(l.end - l.start);
+                    }
+                    None => w.uint(0),
+                }
+            }
+        }
+        None => w.bytes.push(0),
+    }
+    w.bytes
+}
+
+/// Reads back a program serialized by [`encode`], everything it refers to is allocated in `bump`
+pub fn decode<'vm>(bytes: &[u8], bump: &'vm Bump) -> BytecodeResult<Program<'vm>> {
+    let arena = CodeGenArena::new(bump);
+    let mut r = Reader { bytes, pos: 0 };
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err(BytecodeError::InvalidMagic);
+    }
+    let version = u16::from_le_bytes([r.byte()?, r.byte()?]);
+    if version != FORMAT_VERSION {
+        return Err(BytecodeError::UnsupportedVersion {
+            found: version,
+            expected: FORMAT_VERSION,
+        });
+    }
+    let mut pools = [Vec::new(), Vec::new()];
+    for pool in &mut pools {
+        for _ in 0..r.uint()? {
+            pool.push(&*bump.alloc_str(r.str()?));
+        }
+    }
+    let [string_pool, function_pool] = pools;
+    let pool = ConstantPool {
+        string_pool: bump.alloc_slice_copy(&string_pool),
+        function_pool: bump.alloc_slice_copy(&function_pool),
+    };
+
+    let entry_point = r.function(&pool)?;
+    let mut libraries = Vec::new();
+    for _ in 0..r.uint()? {
+        libraries.push(ImportedLibrary {
+            name: r.string(&pool)?.to_string(),
+            is_std: r.byte()? != 0,
+        });
+    }
+    let mut vtables = Vec::new();
+    for _ in 0..r.uint()? {
+        let class = r.string(&pool)?.to_string();
+        let trait_name = r.string(&pool)?.to_string();
+        let mut methods = Vec::new();
+        for _ in 0..r.uint()? {
+            methods.push(r.function(&pool)?.to_string());
+        }
+        vtables.push(VTable {
+            class,
+            trait_name,
+            methods,
+        });
+    }
+    let mut labels = Vec::new();
+    for _ in 0..r.uint()? {
+        let name = r.function(&pool)?.to_string();
+        let position = r.uint()?;
+        let locals = r.uint()?;
+        let mut body = Vec::new();
+        for _ in 0..r.uint()? {
+            body.push(r.instruction(&pool)?);
+        }
+        labels.push(Label {
+            name,
+            position,
+            locals,
+            body: arena.alloc_vec(body),
+        });
+    }
+    let debug = match r.byte()? {
+        0 => None,
+        _ => {
+            let mut debug = DebugInfo::default();
+            for _ in 0..r.uint()? {
+                debug.files.push(SourceFile {
+                    path: r.str()?.to_string(),
+                    src: r.str()?.into(),
+                });
+            }
+            for _ in 0..r.uint()? {
+                debug.locations.push(match r.uint()? {
+                    0 => None,
+                    file => {
+                        let start = r.uint()?;
+                        let end = start
+                            .checked_add(r.uint()?)
+                            .ok_or(BytecodeError::IntegerOverflow)?;
+                        Some(SourceLocation {
+                            file: file - 1,
+                            start,
+                            end,
+                        })
+                    }
+                });
+            }
+            Some(&*bump.alloc(debug))
+        }
+    };
+    if r.pos != bytes.len() {
+        return Err(BytecodeError::TrailingBytes(bytes.len() - r.pos));
+    }
+
+    Ok(Program {
+        labels: arena.alloc_vec(labels),
+        entry_point,
+        libraries: arena.alloc_vec(libraries),
+        vtables: arena.alloc_vec(vtables),
+        debug,
+    })
+}
+
+/// Checks the operands of every instruction against the layout of `program`, so a decoded
+/// program can be run: locals are in the frame of their function, jumps stay in their function,
+/// calls go to the start of a function & virtual calls to a method some vtable has.
+///
+/// The indices of constants are checked by [`decode`] & field indices when the fields are read,
+/// as structures are also built by native functions.
+pub fn verify(program: &Program) -> BytecodeResult<()> {
+    let mut starts = HashMap::new();
+    let mut start = 0;
+    for label in program.labels {
+        starts.insert(label.name.as_str(), start);
+        start += label.body.len();
+    }
+    let function = |name: &str| match starts.contains_key(name) {
+        true => Ok(()),
+        false => Err(BytecodeError::UnknownFunction(name.to_string())),
+    };
+    function(program.entry_point)?;
+    for vtable in program.vtables {
+        vtable.methods.iter().try_for_each(|m| function(m))?;
+    }
+    let methods = program
+        .vtables
+        .iter()
+        .map(|vtable| vtable.methods.len())
+        .max()
+        .unwrap_or(0);
+
+    for label in program.labels {
+        let len = label.body.len();
+        //Relative jumps go from the jump itself, `JmpZ` from the instruction after it
+        let jump = |index: usize, offset: isize| {
+            index
+                .checked_add_signed(offset)
+                .is_some_and(|target| target < len)
+        };
+        for (index, instr) in label.body.iter().enumerate() {
+            let reason = match instr {
+                Instruction::StoreLocal(slot) | Instruction::LoadLocal(slot)
+                    if *slot as usize >= label.locals =>
+                {
+                    "uses a slot outside of the frame of its function"
+                }
+                Instruction::Jmp { pos } if !jump(index, *pos) => "jumps out of its function",
+                Instruction::JmpZ { pos } if !jump(index + 1, *pos) => "jumps out of its function",
+                Instruction::JmpTable { targets }
+                    if !targets.iter().all(|pos| jump(index, *pos)) =>
+                {
+                    "jumps out of its function"
+                }
+                Instruction::Call { target, .. } | Instruction::NewClosure { target, .. }
+                    if !starts.values().any(|start| start == target) =>
+                {
+                    "refers to an instruction that doesn't start a function"
+                }
+                Instruction::CallFunction { name, .. }
+                | Instruction::MakeClosure { function: name, .. } => {
+                    function(name)?;
+                    continue;
+                }
+                Instruction::CallVirtual { method, .. } if *method >= methods => {
+                    "calls a method no vtable has"
+                }
+                _ => continue,
+            };
+            return Err(BytecodeError::InvalidInstruction {
+                function: label.name.clone(),
+                index,
+                reason,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Makes sure the runtime or the host (`natives`) provides every library `program` imports
+pub fn check_libraries(program: &Program, natives: &[NativeModule]) -> BytecodeResult<()> {
+    for lib in program.libraries {
+        let provided = match lib.is_std {
+            true => lib
+                .name
+                .strip_prefix("std/")
+                .is_some_and(|name| STD_LIBRARIES.contains(&name)),
+            false => natives.iter().any(|m| m.name == lib.name),
+        };
+        if !provided {
+            return Err(BytecodeError::UnknownLibrary(lib.name.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Strings of a constant pool in the order they were first met
+#[derive(Default)]
+struct Pool<'a> {
+    values: Vec<&'a str>,
+    indices: HashMap<&'a str, usize>,
+}
+
+impl<'a> Pool<'a> {
+    fn add(&mut self, s: &'a str) {
+        if !self.indices.contains_key(s) {
+            self.indices.insert(s, self.values.len());
+            self.values.push(s);
+        }
+    }
+
+    /// Index of a string already added to the pool
+    fn get(&self, s: &str) -> usize {
+        self.indices[s]
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u64(&mut self, mut v: u64) {
+        loop {
+            let byte = (v & 0x7f) as u8;
+            v >>= 7;
+            if v == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn uint(&mut self, v: usize) {
+        self.u64(v as u64)
+    }
+
+    fn int(&mut self, v: i64) {
+        self.u64(((v << 1) ^ (v >> 63)) as u64)
+    }
+
+    fn str(&mut self, s: &str) {
+        self.uint(s.len());
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    fn instruction(&mut self, instr: &Instruction, strings: &Pool, functions: &Pool) {
+        self.bytes.push(opcode(instr));
+        match instr {
+            Instruction::PushInt(i) => self.int(*i),
+            Instruction::PushFloat(f) => self.bytes.extend_from_slice(&f.to_le_bytes()),
+            Instruction::PushUnsignedInt(u) => self.u64(*u),
+            Instruction::PushBool(b) => self.bytes.push(*b as u8),
+            Instruction::PushString(s) => self.uint(strings.get(s)),
+            Instruction::StoreLocal(slot) | Instruction::LoadLocal(slot) => {
+                self.uint(*slot as usize)
+            }
+            Instruction::NewObj { fields: n }
+            | Instruction::NewEnum { fields: n }
+            | Instruction::GetField { field: n }
+            | Instruction::SetField { field: n }
+            | Instruction::NewList { items: n } => self.uint(*n),
+            Instruction::Jmp { pos } | Instruction::JmpZ { pos } => self.int(*pos as i64),
+            Instruction::JmpTable { targets } => {
+                self.uint(targets.len());
+                for pos in targets {
+                    self.int(*pos as i64);
+                }
+            }
+            Instruction::CallFunction { name, args } => {
+                self.uint(functions.get(name));
+                self.bytes.push(*args);
+            }
+            Instruction::Call { target, args } => {
+                self.uint(*target);
+                self.bytes.push(*args);
+            }
+            Instruction::MakeClosure { function, captures } => {
+                self.uint(functions.get(function));
+                self.uint(*captures);
+            }
+            Instruction::NewClosure { target, captures } => {
+                self.uint(*target);
+                self.uint(*captures);
+            }
+            Instruction::CallIndirect { args } => self.bytes.push(*args),
+            Instruction::CallVirtual { method, args } => {
+                self.uint(*method);
+                self.bytes.push(*args);
+            }
+            Instruction::ExternCall {
+                library,
+                name,
+                args,
+            } => {
+                self.uint(strings.get(library));
+                self.uint(strings.get(name));
+                self.bytes.push(*args);
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    pos: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, n: usize) -> BytecodeResult<&'b [u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(BytecodeError::UnexpectedEof)?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> BytecodeResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> BytecodeResult<u64> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            v |= ((byte & 0x7f) as u64)
+                .checked_shl(shift)
+                .filter(|bits| bits >> shift == (byte & 0x7f) as u64)
+                .ok_or(BytecodeError::IntegerOverflow)?;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(BytecodeError::IntegerOverflow)
+    }
+
+    fn uint(&mut self) -> BytecodeResult<usize> {
+        usize::try_from(self.u64()?).map_err(|_| BytecodeError::IntegerOverflow)
+    }
+
+    fn int(&mut self) -> BytecodeResult<i64> {
+        let v = self.u64()?;
+        Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+
+    fn isize(&mut self) -> BytecodeResult<isize> {
+        isize::try_from(self.int()?).map_err(|_| BytecodeError::IntegerOverflow)
+    }
+
+    fn str(&mut self) -> BytecodeResult<&'b str> {
+        let len = self.uint()?;
+        std::str::from_utf8(self.take(len)?).map_err(|_| BytecodeError::InvalidUtf8)
+    }
+
+    fn string<'vm>(&mut self, pool: &ConstantPool<'vm>) -> BytecodeResult<&'vm str> {
+        let idx = self.uint()?;
+        pool.string_pool
+            .get(idx)
+            .copied()
+            .ok_or(BytecodeError::InvalidConstant(idx, "string"))
+    }
+
+    fn function<'vm>(&mut self, pool: &ConstantPool<'vm>) -> BytecodeResult<&'vm str> {
+        let idx = self.uint()?;
+        pool.function_pool
+            .get(idx)
+            .copied()
+            .ok_or(BytecodeError::InvalidConstant(idx, "function"))
+    }
+
+    fn instruction(&mut self, pool: &ConstantPool) -> BytecodeResult<Instruction> {
+        use Instruction::*;
+        let opcode = self.byte()?;
+        Ok(match opcode {
+            0x00 => PushInt(self.int()?),
+            0x01 => PushFloat(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            0x02 => PushUnsignedInt(self.u64()?),
+            0x03 => PushBool(self.byte()? != 0),
+            0x04 => PushString(self.string(pool)?.to_string()),
+            0x05 => PushUnit,
+            0x06 => Pop,
+            0x07 => Dup,
+            0x08 | 0x09 => {
+                let slot =
+                    u16::try_from(self.uint()?).map_err(|_| BytecodeError::IntegerOverflow)?;
+                if opcode == 0x08 {
+                    StoreLocal(slot)
+                } else {
+                    LoadLocal(slot)
+                }
+            }
+            0x0a => NewObj {
+                fields: self.uint()?,
+            },
+            0x0b => GetField {
+                field: self.uint()?,
+            },
+            0x0c => SetField {
+                field: self.uint()?,
+            },
+            0x0d => NewList {
+                items: self.uint()?,
+            },
+            0x0e => ListLoad,
+            0x0f => ListStore,
+            0x10 => AddI64,
+            0x11 => AddF64,
+            0x12 => AddU64,
+            0x13 => SubI64,
+            0x14 => SubF64,
+            0x15 => SubU64,
+            0x16 => MulI64,
+            0x17 => MulF64,
+            0x18 => MulU64,
+            0x19 => DivI64,
+            0x1a => DivF64,
+            0x1b => DivU64,
+            0x1c => ModI64,
+            0x1d => ModF64,
+            0x1e => ModU64,
+            0x1f => StrConcat,
+            0x20 => StrCmp,
+            0x21 => Eq,
+            0x22 => Neq,
+            0x23 => Gt,
+            0x24 => Gte,
+            0x25 => Lt,
+            0x26 => Lte,
+            0x27 => Jmp { pos: self.isize()? },
+            0x28 => JmpZ { pos: self.isize()? },
+            0x29 => {
+                let mut targets = Vec::new();
+                for _ in 0..self.uint()? {
+                    targets.push(self.isize()?);
+                }
+                JmpTable { targets }
+            }
+            0x2a => CallFunction {
+                name: self.function(pool)?.to_string(),
+                args: self.byte()?,
+            },
+            0x2b => Call {
+                target: self.uint()?,
+                args: self.byte()?,
+            },
+            0x2c => MakeClosure {
+                function: self.function(pool)?.to_string(),
+                captures: self.uint()?,
+            },
+            0x2d => NewClosure {
+                target: self.uint()?,
+                captures: self.uint()?,
+            },
+            0x2e => CallIndirect { args: self.byte()? },
+            0x2f => CallVirtual {
+                method: self.uint()?,
+                args: self.byte()?,
+            },
+            0x30 => ExternCall {
+                library: self.string(pool)?.to_string(),
+                name: self.string(pool)?.to_string(),
+                args: self.byte()?,
+            },
+            0x31 => Return,
+            0x32 => Halt,
+            0x33 => NewEnum {
+                fields: self.uint()?,
+            },
+            _ => return Err(BytecodeError::InvalidOpcode(opcode)),
+        })
+    }
+}
+
+fn opcode(instr: &Instruction) -> u8 {
+    use Instruction::*;
+    match instr {
+        PushInt(_) => 0x00,
+        PushFloat(_) => 0x01,
+        PushUnsignedInt(_) => 0x02,
+        PushBool(_) => 0x03,
+        PushString(_) => 0x04,
+        PushUnit => 0x05,
+        Pop => 0x06,
+        Dup => 0x07,
+        StoreLocal(_) => 0x08,
+        LoadLocal(_) => 0x09,
+        NewObj { .. } => 0x0a,
+        GetField { .. } => 0x0b,
+        SetField { .. } => 0x0c,
+        NewList { .. } => 0x0d,
+        ListLoad => 0x0e,
+        ListStore => 0x0f,
+        AddI64 => 0x10,
+        AddF64 => 0x11,
+        AddU64 => 0x12,
+        SubI64 => 0x13,
+        SubF64 => 0x14,
+        SubU64 => 0x15,
+        MulI64 => 0x16,
+        MulF64 => 0x17,
+        MulU64 => 0x18,
+        DivI64 => 0x19,
+        DivF64 => 0x1a,
+        DivU64 => 0x1b,
+        ModI64 => 0x1c,
+        ModF64 => 0x1d,
+        ModU64 => 0x1e,
+        StrConcat => 0x1f,
+        StrCmp => 0x20,
+        Eq => 0x21,
+        Neq => 0x22,
+        Gt => 0x23,
+        Gte => 0x24,
+        Lt => 0x25,
+        Lte => 0x26,
+        Jmp { .. } => 0x27,
+        JmpZ { .. } => 0x28,
+        JmpTable { .. } => 0x29,
+        CallFunction { .. } => 0x2a,
+        Call { .. } => 0x2b,
+        MakeClosure { .. } => 0x2c,
+        NewClosure { .. } => 0x2d,
+        CallIndirect { .. } => 0x2e,
+        CallVirtual { .. } => 0x2f,
+        ExternCall { .. } => 0x30,
+        Return => 0x31,
+        Halt => 0x32,
+        NewEnum { .. } => 0x33,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(bump: &Bump) -> Program<'_> {
+        let arena = CodeGenArena::new(bump);
+        let main = vec![
+            Instruction::PushString("hello".to_string()),
+            Instruction::ExternCall {
+                library: "std/io".to_string(),
+                name: "println".to_string(),
+                args: 1,
+            },
+            Instruction::Pop,
+            Instruction::PushInt(-42),
+            Instruction::PushFloat(1.5),
+            Instruction::PushUnsignedInt(u64::MAX),
+            Instruction::MakeClosure {
+                function: "square".to_string(),
+                captures: 0,
+            },
+            Instruction::CallIndirect { args: 1 },
+            Instruction::NewEnum { fields: 2 },
+            Instruction::JmpTable {
+                targets: vec![1, -300, isize::MAX],
+            },
+            Instruction::Halt,
+        ];
+        let square = vec![
+            Instruction::LoadLocal(0),
+            Instruction::Dup,
+            Instruction::MulI64,
+            Instruction::Return,
+        ];
+        Program {
+            labels: arena.alloc_vec(vec![
+                Label {
+                    name: "main".to_string(),
+                    position: 0,
+                    locals: 0,
+                    body: arena.alloc_vec(main),
+                },
+                Label {
+                    name: "square".to_string(),
+                    position: 11,
+                    locals: 1,
+                    body: arena.alloc_vec(square),
+                },
+            ]),
+            entry_point: "main",
+            libraries: arena.alloc_vec(vec![ImportedLibrary {
+                name: "std/io".to_string(),
+                is_std: true,
+            }]),
+            vtables: arena.alloc_vec(vec![VTable {
+                class: "Square".to_string(),
+                trait_name: "Shape".to_string(),
+                methods: vec!["square".to_string()],
+            }]),
+            debug: Some(bump.alloc(DebugInfo {
+                files: vec![SourceFile {
+                    path: "square.atlas".to_string(),
+                    src: "square(4)".into(),
+                }],
+                locations: vec![
+                    Some(SourceLocation {
+                        file: 0,
+                        start: 0,
+                        end: 9,
+                    }),
+                    None,
+                ],
+            })),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let bump = Bump::new();
+        let program = program(&bump);
+        let bytes = encode(&program);
+        let decoded_bump = Bump::new();
+        assert_eq!(decode(&bytes, &decoded_bump), Ok(program));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let bump = Bump::new();
+        let mut bytes = encode(&program(&bump));
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            decode(&bytes, &bump),
+            Err(BytecodeError::UnsupportedVersion {
+                found: FORMAT_VERSION + 1,
+                expected: FORMAT_VERSION,
+            })
+        );
+        assert_eq!(decode(b"nope", &bump), Err(BytecodeError::InvalidMagic));
+    }
+
+    /// `main` calling `square`, which takes one argument
+    fn calling<'a>(bump: &'a Bump, main: Vec<Instruction>) -> Program<'a> {
+        let arena = CodeGenArena::new(bump);
+        let square = vec![
+            Instruction::LoadLocal(0),
+            Instruction::Dup,
+            Instruction::MulI64,
+            Instruction::Return,
+        ];
+        Program {
+            labels: arena.alloc_vec(vec![
+                Label {
+                    name: "main".to_string(),
+                    position: 0,
+                    locals: 1,
+                    body: arena.alloc_vec(main),
+                },
+                Label {
+                    name: "square".to_string(),
+                    position: 0,
+                    locals: 1,
+                    body: arena.alloc_vec(square),
+                },
+            ]),
+            entry_point: "main",
+            libraries: arena.alloc_vec(Vec::new()),
+            vtables: arena.alloc_vec(Vec::new()),
+            debug: None,
+        }
+    }
+
+    #[test]
+    fn verifies_instructions() {
+        let bump = Bump::new();
+        //`main` is 7 instructions long, `square` starts right after it
+        let valid = vec![
+            Instruction::PushBool(true),
+            Instruction::JmpZ { pos: 1 },
+            Instruction::PushInt(3),
+            Instruction::StoreLocal(0),
+            Instruction::LoadLocal(0),
+            Instruction::Call { target: 7, args: 1 },
+            Instruction::Halt,
+        ];
+        assert_eq!(verify(&calling(&bump, valid.clone())), Ok(()));
+
+        let corruptions = [
+            (3, Instruction::StoreLocal(1), "uses a slot"),
+            (1, Instruction::JmpZ { pos: 5 }, "jumps out"),
+            (6, Instruction::Jmp { pos: -7 }, "jumps out"),
+            (
+                6,
+                Instruction::JmpTable {
+                    targets: vec![0, isize::MIN],
+                },
+                "jumps out",
+            ),
+            (5, Instruction::Call { target: 8, args: 1 }, "doesn't start"),
+            (
+                5,
+                Instruction::NewClosure {
+                    target: 100,
+                    captures: 0,
+                },
+                "doesn't start",
+            ),
+            (
+                5,
+                Instruction::CallVirtual { method: 0, args: 1 },
+                "no vtable",
+            ),
+        ];
+        for (index, instr, reason) in corruptions {
+            let mut main = valid.clone();
+            main[index] = instr;
+            match verify(&calling(&bump, main)) {
+                Err(BytecodeError::InvalidInstruction {
+                    function,
+                    index: found,
+                    reason: found_reason,
+                }) => {
+                    assert_eq!((function.as_str(), found), ("main", index));
+                    assert!(found_reason.contains(reason), "{}", found_reason);
+                }
+                res => panic!("instruction {} should be rejected, got {:?}", index, res),
+            }
+        }
+        let mut main = valid.clone();
+        main[5] = Instruction::CallFunction {
+            name: "cube".to_string(),
+            args: 1,
+        };
+        assert_eq!(
+            verify(&calling(&bump, main)),
+            Err(BytecodeError::UnknownFunction("cube".to_string()))
+        );
+
+        //A slot changed in the file itself decodes, but doesn't verify
+        let mut bytes = encode(&calling(&bump, valid));
+        let slot = bytes
+            .windows(2)
+            .rposition(|w| w == [opcode(&Instruction::LoadLocal(0)), 0])
+            .unwrap();
+        bytes[slot + 1] = 5;
+        let program = decode(&bytes, &bump).unwrap();
+        assert!(matches!(
+            verify(&program),
+            Err(BytecodeError::InvalidInstruction { index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_overflowing_locations() {
+        let bump = Bump::new();
+        let mut program = calling(&bump, vec![Instruction::Halt]);
+        program.debug = Some(bump.alloc(DebugInfo {
+            files: vec![SourceFile {
+                path: "main.atlas".to_string(),
+                src: "".into(),
+            }],
+            locations: vec![Some(SourceLocation {
+                file: 0,
+                start: usize::MAX,
+                end: usize::MAX,
+            })],
+        }));
+        let mut bytes = encode(&program);
+        //The length of the last location, 0 so far
+        *bytes.last_mut().unwrap() = 1;
+        assert_eq!(decode(&bytes, &bump), Err(BytecodeError::IntegerOverflow));
+    }
+}
@@ -0,0 +1,343 @@
+//! Conversions between Rust values & the values of the VM.
+//!
+//! Reading a value checks its tag first: a value of the wrong type is a
+//! [`RuntimeError::TypeMismatchError`] instead of a reinterpretation of the raw data.
+
+use crate::{
+    atlas_memory::{
+        object_map::{Memory, Object, ObjectIndex, Structure},
+        vm_data::VMData,
+    },
+    atlas_vm::{errors::RuntimeError, native::NativeType, RuntimeResult},
+};
+
+/// A Rust type with an Atlas counterpart
+pub trait AtlasType {
+    fn atlas_type() -> NativeType;
+}
+
+/// Reads a value of the VM, objects are looked up in `memory`
+pub trait FromAtlas: AtlasType + Sized {
+    fn from_atlas(value: VMData, memory: &Memory) -> RuntimeResult<Self>;
+}
+
+/// Turns a Rust value into a value of the VM, objects are allocated in `memory`
+pub trait IntoAtlas: AtlasType {
+    fn into_atlas(self, memory: &mut Memory) -> RuntimeResult<VMData>;
+}
+
+macro_rules! primitive {
+    ($ty:ty, $native:ident, $tag:ident, $getter:ident, $new:ident) => {
+        impl AtlasType for $ty {
+            fn atlas_type() -> NativeType {
+                NativeType::$native
+            }
+        }
+
+        impl FromAtlas for $ty {
+            fn from_atlas(value: VMData, _: &Memory) -> RuntimeResult<Self> {
+                match value.tag {
+                    VMData::$tag => Ok(value.$getter()),
+                    _ => Err(RuntimeError::TypeMismatchError),
+                }
+            }
+        }
+
+        impl IntoAtlas for $ty {
+            fn into_atlas(self, _: &mut Memory) -> RuntimeResult<VMData> {
+                Ok(VMData::$new(self))
+            }
+        }
+    };
+}
+
+primitive!(i64, Int64, TAG_I64, as_i64, new_i64);
+primitive!(u64, UInt64, TAG_U64, as_u64, new_u64);
+primitive!(f64, Float64, TAG_FLOAT, as_f64, new_f64);
+primitive!(bool, Bool, TAG_BOOL, as_bool, new_bool);
+
+impl AtlasType for () {
+    fn atlas_type() -> NativeType {
+        NativeType::Unit
+    }
+}
+
+impl FromAtlas for () {
+    fn from_atlas(value: VMData, _: &Memory) -> RuntimeResult<Self> {
+        match value.tag {
+            VMData::TAG_UNIT => Ok(()),
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+}
+
+impl IntoAtlas for () {
+    fn into_atlas(self, _: &mut Memory) -> RuntimeResult<VMData> {
+        Ok(VMData::new_unit())
+    }
+}
+
+impl AtlasType for String {
+    fn atlas_type() -> NativeType {
+        NativeType::Str
+    }
+}
+
+impl FromAtlas for String {
+    fn from_atlas(value: VMData, memory: &Memory) -> RuntimeResult<Self> {
+        match value.tag {
+            VMData::TAG_STR => match memory.get(value.as_object()) {
+                Object::String(s) => Ok(s.clone()),
+                _ => Err(RuntimeError::TypeMismatchError),
+            },
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+}
+
+impl IntoAtlas for String {
+    fn into_atlas(self, memory: &mut Memory) -> RuntimeResult<VMData> {
+        let index = memory.put(Object::String(self))?;
+        Ok(VMData::new_string(index))
+    }
+}
+
+impl<T: AtlasType> AtlasType for Vec<T> {
+    fn atlas_type() -> NativeType {
+        NativeType::List(Box::new(T::atlas_type()))
+    }
+}
+
+impl<T: FromAtlas> FromAtlas for Vec<T> {
+    fn from_atlas(value: VMData, memory: &Memory) -> RuntimeResult<Self> {
+        match value.tag {
+            VMData::TAG_LIST => match memory.get(value.as_object()) {
+                Object::List(items) => items
+                    .iter()
+                    .map(|item| T::from_atlas(*item, memory))
+                    .collect(),
+                _ => Err(RuntimeError::TypeMismatchError),
+            },
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+}
+
+impl<T: IntoAtlas> IntoAtlas for Vec<T> {
+    fn into_atlas(self, memory: &mut Memory) -> RuntimeResult<VMData> {
+        let items = self
+            .into_iter()
+            .map(|item| item.into_atlas(memory))
+            .collect::<RuntimeResult<Vec<_>>>()?;
+        let index = memory.put(Object::List(items))?;
+        Ok(VMData::new_list(VMData::TAG_LIST, index))
+    }
+}
+
+/// Any value, as taken & returned by generic native functions
+impl AtlasType for VMData {
+    fn atlas_type() -> NativeType {
+        NativeType::Generic
+    }
+}
+
+impl FromAtlas for VMData {
+    fn from_atlas(value: VMData, _: &Memory) -> RuntimeResult<Self> {
+        Ok(value)
+    }
+}
+
+impl IntoAtlas for VMData {
+    fn into_atlas(self, _: &mut Memory) -> RuntimeResult<VMData> {
+        Ok(self)
+    }
+}
+
+/// A list of the VM whatever the type of its items, used in place instead of being copied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListHandle(ObjectIndex);
+
+impl ListHandle {
+    /// The list as a value of the VM, e.g. to pin it
+    pub fn value(self) -> VMData {
+        VMData::new_list(VMData::TAG_LIST, self.0)
+    }
+
+    pub fn items(self, memory: &Memory) -> RuntimeResult<&Vec<VMData>> {
+        match memory.get(self.0) {
+            Object::List(items) => Ok(items),
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+
+    pub fn items_mut(self, memory: &mut Memory) -> RuntimeResult<&mut Vec<VMData>> {
+        match memory.get_mut(self.0) {
+            Object::List(items) => Ok(items),
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+}
+
+impl AtlasType for ListHandle {
+    fn atlas_type() -> NativeType {
+        NativeType::List(Box::new(NativeType::Generic))
+    }
+}
+
+impl FromAtlas for ListHandle {
+    fn from_atlas(value: VMData, memory: &Memory) -> RuntimeResult<Self> {
+        match value.tag {
+            VMData::TAG_LIST => match memory.get(value.as_object()) {
+                Object::List(_) => Ok(Self(value.as_object())),
+                _ => Err(RuntimeError::TypeMismatchError),
+            },
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+}
+
+impl IntoAtlas for ListHandle {
+    fn into_atlas(self, _: &mut Memory) -> RuntimeResult<VMData> {
+        Ok(self.value())
+    }
+}
+
+/// Lets a native function fail, the error stops the program
+impl<T: AtlasType> AtlasType for RuntimeResult<T> {
+    fn atlas_type() -> NativeType {
+        T::atlas_type()
+    }
+}
+
+impl<T: IntoAtlas> IntoAtlas for RuntimeResult<T> {
+    fn into_atlas(self, memory: &mut Memory) -> RuntimeResult<VMData> {
+        self?.into_atlas(memory)
+    }
+}
+
+/// Arguments of a call made from Rust: a tuple of values convertible to Atlas
+pub trait IntoArgs {
+    fn types() -> Vec<NativeType>;
+    fn into_args(self, memory: &mut Memory) -> RuntimeResult<Vec<VMData>>;
+}
+
+macro_rules! into_args {
+    ($($arg:ident),*) => {
+        impl<$($arg: IntoAtlas,)*> IntoArgs for ($($arg,)*) {
+            fn types() -> Vec<NativeType> {
+                vec![$($arg::atlas_type()),*]
+            }
+            #[allow(non_snake_case, unused_variables)]
+            fn into_args(self, memory: &mut Memory) -> RuntimeResult<Vec<VMData>> {
+                let ($($arg,)*) = self;
+                Ok(vec![$($arg.into_atlas(memory)?),*])
+            }
+        }
+    };
+}
+
+into_args!();
+into_args!(A);
+into_args!(A, B);
+into_args!(A, B, C);
+into_args!(A, B, C, D);
+into_args!(A, B, C, D, E);
+into_args!(A, B, C, D, E, G);
+
+/// Fields of the structure `value` points to, it must have `len` of them.
+///
+/// Used by [`atlas_struct!`](crate::atlas_struct).
+pub fn structure_fields(value: VMData, memory: &Memory, len: usize) -> RuntimeResult<&[VMData]> {
+    match value.tag {
+        VMData::TAG_OBJECT => match memory.get(value.as_object()) {
+            Object::Structure(s) if s.fields.len() == len => Ok(&s.fields),
+            _ => Err(RuntimeError::TypeMismatchError),
+        },
+        _ => Err(RuntimeError::TypeMismatchError),
+    }
+}
+
+/// Allocates a structure holding `fields`, in the order the Atlas struct declares them
+pub fn new_structure(fields: Vec<VMData>, memory: &mut Memory) -> RuntimeResult<VMData> {
+    let index = memory.put(Object::Structure(Structure { fields }))?;
+    Ok(VMData::new_object(VMData::TAG_OBJECT, index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_tags() {
+        let mut memory = Memory::new(16);
+        let value = 3.5f64.into_atlas(&mut memory).unwrap();
+        assert!(matches!(
+            i64::from_atlas(value, &memory),
+            Err(RuntimeError::TypeMismatchError)
+        ));
+        assert!(matches!(
+            String::from_atlas(value, &memory),
+            Err(RuntimeError::TypeMismatchError)
+        ));
+        assert_eq!(f64::from_atlas(value, &memory).unwrap(), 3.5);
+    }
+
+    #[test]
+    fn round_trips_lists() {
+        let mut memory = Memory::new(16);
+        let words = vec!["a".to_string(), "b".to_string()];
+        let value = words.clone().into_atlas(&mut memory).unwrap();
+        assert_eq!(Vec::<String>::from_atlas(value, &memory).unwrap(), words);
+        assert!(matches!(
+            Vec::<i64>::from_atlas(value, &memory),
+            Err(RuntimeError::TypeMismatchError)
+        ));
+    }
+
+    #[test]
+    fn uses_lists_in_place() {
+        let mut memory = Memory::new(16);
+        let value = vec![1i64, 2].into_atlas(&mut memory).unwrap();
+        let list = ListHandle::from_atlas(value, &memory).unwrap();
+        list.items_mut(&mut memory)
+            .unwrap()
+            .push(VMData::new_i64(3));
+        assert_eq!(Vec::<i64>::from_atlas(value, &memory).unwrap(), [1, 2, 3]);
+
+        let word = "word".to_string().into_atlas(&mut memory).unwrap();
+        assert!(matches!(
+            ListHandle::from_atlas(word, &memory),
+            Err(RuntimeError::TypeMismatchError)
+        ));
+    }
+
+    #[test]
+    fn std_functions_check_tags() {
+        use crate::{
+            atlas_memory::stack::Stack,
+            atlas_stdlib::{list, time},
+            atlas_vm::vm_state::VMState,
+        };
+
+        let mut stack = Stack::new();
+        let mut memory = Memory::new(16);
+        let consts = std::collections::HashMap::new();
+        //A structure whose fields aren't the ones of `Time`
+        let not_time = new_structure(vec![VMData::new_f64(1.0); 2], &mut memory).unwrap();
+        let calls: [(crate::atlas_vm::CallBack, Vec<VMData>); 4] = [
+            (list::len, vec![VMData::new_i64(1)]),
+            (list::get, vec![not_time, VMData::new_u64(0)]),
+            (time::format_time_iso, vec![not_time]),
+            (time::elapsed, vec![not_time, VMData::new_i64(0)]),
+        ];
+        for (function, args) in calls {
+            for arg in args {
+                stack.push(arg).unwrap();
+            }
+            assert!(matches!(
+                function(VMState::new(&mut stack, &mut memory, &consts)),
+                Err(RuntimeError::TypeMismatchError)
+            ));
+        }
+    }
+}
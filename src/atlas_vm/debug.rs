@@ -0,0 +1,129 @@
+//! Side table mapping the instructions of a program back to the code they were generated from,
+//! & the stack traces the VM renders with it when a program fails.
+
+use std::sync::Arc;
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+
+use crate::atlas_vm::errors::RuntimeError;
+
+/// Number of frames kept in a stack trace, the outermost ones are dropped first
+pub const MAX_TRACE_FRAMES: usize = 16;
+
+/// A file a program was compiled from
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd)]
+pub struct SourceFile {
+    pub path: String,
+    pub src: Arc<str>,
+}
+
+/// Code an instruction was generated from, `file` is an index in `DebugInfo::files`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
+pub struct SourceLocation {
+    pub file: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd)]
+pub struct DebugInfo {
+    pub files: Vec<SourceFile>,
+    /// Location of each instruction, indexed by its position once the program is linked
+    pub locations: Vec<Option<SourceLocation>>,
+}
+
+impl DebugInfo {
+    pub fn location(&self, pc: usize) -> Option<SourceLocation> {
+        self.locations.get(pc).copied().flatten()
+    }
+}
+
+/// A call that was in progress when the program failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    pub function: String,
+    /// The instruction that failed for the innermost frame, the call for the others
+    pub location: Option<SourceLocation>,
+}
+
+/// The calls in progress when the program failed, the innermost one first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StackTrace {
+    pub frames: Vec<TraceFrame>,
+    /// Number of outer frames left out after [`MAX_TRACE_FRAMES`]
+    pub omitted: usize,
+}
+
+/// A runtime error along with where it happened & the functions that led there
+#[derive(Error, Diagnostic, Debug)]
+#[error("{error}")]
+#[diagnostic(code(runtime::error))]
+pub struct RuntimeReport {
+    pub error: RuntimeError,
+    function: String,
+    #[source_code]
+    src: Option<NamedSource<Arc<str>>>,
+    #[label("in `{function}`")]
+    span: Option<SourceSpan>,
+    #[related]
+    callers: Vec<CallerReport>,
+    #[help]
+    omitted: Option<String>,
+}
+
+/// A function that was waiting on the call below it
+#[derive(Error, Diagnostic, Debug)]
+#[error("called from `{function}`")]
+#[diagnostic(severity(Advice))]
+pub struct CallerReport {
+    function: String,
+    #[source_code]
+    src: Option<NamedSource<Arc<str>>>,
+    #[label("called here")]
+    span: Option<SourceSpan>,
+}
+
+impl RuntimeReport {
+    pub fn new(error: RuntimeError, trace: &StackTrace, debug: Option<&DebugInfo>) -> Self {
+        let locate = |frame: &TraceFrame| {
+            let location = frame.location.zip(debug).and_then(|(location, debug)| {
+                let file = debug.files.get(location.file)?;
+                Some((file, location))
+            });
+            match location {
+                Some((file, location)) => (
+                    Some(NamedSource::new(&file.path, file.src.clone())),
+                    Some(SourceSpan::from(location.start..location.end)),
+                ),
+                None => (None, None),
+            }
+        };
+        let mut frames = trace.frames.iter();
+        let (function, src, span) = match frames.next() {
+            Some(frame) => {
+                let (src, span) = locate(frame);
+                (frame.function.clone(), src, span)
+            }
+            None => (String::new(), None, None),
+        };
+        let callers = frames
+            .map(|frame| {
+                let (src, span) = locate(frame);
+                CallerReport {
+                    function: frame.function.clone(),
+                    src,
+                    span,
+                }
+            })
+            .collect();
+        Self {
+            error,
+            function,
+            src,
+            span,
+            callers,
+            omitted: (trace.omitted > 0).then(|| format!("{} more calls", trace.omitted)),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::atlas_vm::instruction::{Instruction, Program};
+
+/// Lists the instructions of every label of `program` alongside their absolute offset.
+///
+/// Jump destinations get a local label (`.L0`, `.L1`, ...) printed right before the
+/// instruction they land on, and calls are annotated with their arity (`fib/1`). Each label
+/// tells how many slots its frame has.
+pub fn disassemble(program: &Program) -> String {
+    let mut functions = BTreeMap::new();
+    let mut offset = 0;
+    for label in program.labels {
+        functions.insert(offset, label.name.as_str());
+        offset += label.body.len();
+    }
+
+    //Every jump destination gets its own label, numbered in order of appearance
+    let mut destinations = BTreeMap::new();
+    let mut offset = 0;
+    for label in program.labels {
+        for instr in label.body {
+            destinations.extend(jump_targets(offset, instr).into_iter().map(|t| (t, 0)));
+            offset += 1;
+        }
+    }
+    for (n, id) in destinations.values_mut().enumerate() {
+        *id = n;
+    }
+
+    let mut out = String::new();
+    let mut offset = 0;
+    for label in program.labels {
+        writeln!(out, "{}:    ; {} locals", label.name, label.locals).unwrap();
+        for instr in label.body {
+            if let Some(id) = destinations.get(&offset) {
+                writeln!(out, ".L{}:", id).unwrap();
+            }
+            let local = |target: usize| match destinations.get(&target) {
+                Some(id) => format!(".L{} (@{})", id, target),
+                None => format!("@{}", target),
+            };
+            let function = |target: usize| match functions.get(&target) {
+                Some(name) => format!("{} (@{})", name, target),
+                None => format!("@{}", target),
+            };
+            let text = match instr {
+                Instruction::Jmp { .. } => format!("Jmp {}", local(jump_targets(offset, instr)[0])),
+                Instruction::JmpZ { .. } => {
+                    format!("JmpZ {}", local(jump_targets(offset, instr)[0]))
+                }
+                Instruction::JmpTable { .. } => format!(
+                    "JmpTable [{}]",
+                    jump_targets(offset, instr)
+                        .into_iter()
+                        .map(local)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                Instruction::CallFunction { name, args } => {
+                    format!("CallFunction {}/{}", name, args)
+                }
+                Instruction::ExternCall {
+                    library,
+                    name,
+                    args,
+                } => format!("ExternCall {}::{}/{}", library, name, args),
+                Instruction::Call { target, args } => {
+                    format!("Call {}/{}", function(*target), args)
+                }
+                Instruction::NewClosure { target, captures } => {
+                    format!("NewClosure {} captures: {}", function(*target), captures)
+                }
+                Instruction::MakeClosure { function, captures } => {
+                    format!("MakeClosure {} captures: {}", function, captures)
+                }
+                Instruction::CallIndirect { args } => format!("CallIndirect /{}", args),
+                Instruction::CallVirtual { method, args } => {
+                    format!("CallVirtual #{}/{}", method, args)
+                }
+                instr => format!("{:?}", instr),
+            };
+            writeln!(out, "    {:>6}  {}", offset, text).unwrap();
+            offset += 1;
+        }
+        writeln!(out).unwrap();
+    }
+    out
+}
+
+/// Absolute offsets the instruction at `offset` may jump to, following the semantics of the VM
+fn jump_targets(offset: usize, instr: &Instruction) -> Vec<usize> {
+    let relative = |pos: isize| (offset as isize + pos) as usize;
+    match instr {
+        Instruction::Jmp { pos } => vec![relative(*pos)],
+        Instruction::JmpZ { pos } => vec![relative(*pos + 1)],
+        Instruction::JmpTable { targets } => targets.iter().map(|&pos| relative(pos)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+
+    use super::*;
+    use crate::{atlas_codegen::arena::CodeGenArena, atlas_vm::instruction::Label};
+
+    #[test]
+    fn resolves_jumps() {
+        let bump = Bump::new();
+        let arena = CodeGenArena::new(&bump);
+        //while (x) { x = false; }
+        let main = vec![
+            Instruction::PushBool(true),
+            Instruction::StoreLocal(0),
+            Instruction::LoadLocal(0),
+            Instruction::JmpZ { pos: 3 },
+            Instruction::PushBool(false),
+            Instruction::StoreLocal(0),
+            Instruction::Jmp { pos: -4 },
+            Instruction::ExternCall {
+                library: "std/io".to_string(),
+                name: "print_int".to_string(),
+                args: 1,
+            },
+        ];
+        let program = Program {
+            labels: arena.alloc_vec(vec![Label {
+                name: "main".to_string(),
+                position: 0,
+                locals: 1,
+                body: arena.alloc_vec(main),
+            }]),
+            entry_point: "main",
+            ..Program::new()
+        };
+        let listing = disassemble(&program);
+        let lines = listing.lines().collect::<Vec<_>>();
+        assert_eq!(lines[3], ".L0:");
+        assert_eq!(lines[4], "         2  LoadLocal(0)");
+        assert_eq!(lines[5], "         3  JmpZ .L1 (@7)");
+        assert_eq!(lines[8], "         6  Jmp .L0 (@2)");
+        assert_eq!(lines[9], ".L1:");
+        assert_eq!(lines[10], "         7  ExternCall std/io::print_int/1");
+    }
+}
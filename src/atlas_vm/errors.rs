@@ -2,32 +2,80 @@ pub type RuntimeResult<T> = Result<T, RuntimeError>;
 
 #[derive(Debug, Clone)]
 pub enum RuntimeError {
-    OutOfMemory,
-    StackOverflow,
+    /// The heap reached its limit & nothing could be collected to make room for a new `object`
+    OutOfMemory {
+        object: &'static str,
+        limit: usize,
+    },
+    /// The stack reached its limit `depth` calls deep, in `function` if it's known
+    StackOverflow {
+        depth: usize,
+        function: Option<String>,
+    },
     StackUndeflow,
     NullReference,
     DivisionByZero,
+    /// An integer operation whose result doesn't fit in its type
+    IntegerOverflow,
     IndexOutOfBounds,
     InvalidOperation,
     TypeMismatchError,
     EntryPointNotFound(String),
+    UnknownLabel(String),
+    UnknownLibrary(String),
+    /// An `ExternCall` to a function no imported library provides
+    UnknownExtern(String),
+    /// A library registering `name` more than once, e.g. two modules of the host with the same
+    /// name exporting the same function
+    DuplicateExtern {
+        library: String,
+        name: String,
+    },
+    /// A native function couldn't do its job, e.g. reading a file that doesn't exist
+    NativeFunctionFailed(String),
 }
 
 impl std::fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use RuntimeError::*;
         match self {
-            OutOfMemory => writeln!(f, "No more memory bozo"),
-            StackOverflow => writeln!(f, "No more stack bozo"),
-            StackUndeflow => writeln!(f, "Too little stack bozo"),
-            NullReference => writeln!(f, "Null Reference error"),
-            DivisionByZero => writeln!(f, "There are no infinity, you can't divide by zero"),
-            IndexOutOfBounds => writeln!(f, "Index out of bounds"),
-            InvalidOperation => writeln!(f, "Invalid Operation (default error)"),
-            TypeMismatchError => writeln!(f, "Incorrect types bozo"),
+            OutOfMemory { object, limit } => write!(
+                f,
+                "No more memory bozo: can't allocate a {}, the heap is full at {} objects",
+                object, limit
+            ),
+            StackOverflow { depth, function } => {
+                write!(f, "No more stack bozo: {} calls deep", depth)?;
+                match function {
+                    Some(function) => write!(f, " in {}", function),
+                    None => Ok(()),
+                }
+            }
+            StackUndeflow => write!(f, "Too little stack bozo"),
+            NullReference => write!(f, "Null Reference error"),
+            DivisionByZero => write!(f, "There are no infinity, you can't divide by zero"),
+            IntegerOverflow => write!(f, "Integer overflow, the result doesn't fit in 64 bits"),
+            IndexOutOfBounds => write!(f, "Index out of bounds"),
+            InvalidOperation => write!(f, "Invalid Operation (default error)"),
+            TypeMismatchError => write!(f, "Incorrect types bozo"),
             EntryPointNotFound(entry_point) => {
-                writeln!(f, "Entry point {} not found", entry_point)
+                write!(f, "Entry point {} not found", entry_point)
+            }
+            UnknownLabel(label) => write!(f, "Label {} not found", label),
+            UnknownLibrary(library) => write!(f, "Library {} is not provided", library),
+            UnknownExtern(name) => write!(f, "Native function {} not found", name),
+            DuplicateExtern { library, name } => {
+                write!(
+                    f,
+                    "Native function {} is provided twice by {}",
+                    name, library
+                )
             }
+            NativeFunctionFailed(reason) => write!(f, "Native function failed: {}", reason),
         }
     }
 }
+
+impl std::error::Error for RuntimeError {}
+
+impl miette::Diagnostic for RuntimeError {}
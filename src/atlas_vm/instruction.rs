@@ -1,10 +1,10 @@
 //NB: This is a dumb down version of the instruction set.
 //A more powerful version will be done for the v0.5.2 & v0.5.3
 
-use std::ops::Index;
-
 use serde::Serialize;
 
+use crate::atlas_vm::debug::DebugInfo;
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize)]
 pub enum Instruction {
     PushInt(i64),
@@ -15,38 +15,39 @@ pub enum Instruction {
     PushUnit,
 
     Pop,
+    /// Push a copy of the value on top of the stack
+    Dup,
+
+    /// Pop a value and store it in the local at `slot` of the current frame
+    StoreLocal(u16),
+    /// Push the value of the local at `slot` of the current frame
+    LoadLocal(u16),
 
-    /// Store an i64 value in a variable from the stack
-    StoreI64 {
-        var_name: String,
+    /// Pop `fields` values (the last one being on top) and push a new structure holding them
+    NewObj {
+        fields: usize,
     },
-    /// Store an f64 value in a variable from the stack
-    StoreF64 {
-        var_name: String,
+    /// Pop `fields` values, the discriminant then the payload, and push a new enum value holding them
+    NewEnum {
+        fields: usize,
     },
-    /// Store an u64 value in a variable from the stack
-    StoreU64 {
-        var_name: String,
+    /// Pop an object and push the value of its field at index `field`
+    GetField {
+        field: usize,
     },
-    StoreBool {
-        var_name: String,
+    /// Pop a value, then an object, and store the value in the field at index `field`
+    SetField {
+        field: usize,
     },
 
-    /// Load an i64 value from a variable to the stack
-    LoadI64 {
-        var_name: String,
-    },
-    /// Load an f64 value from a variable to the stack
-    LoadF64 {
-        var_name: String,
-    },
-    /// Load an u64 value from a variable to the stack
-    LoadU64 {
-        var_name: String,
-    },
-    LoadBool {
-        var_name: String,
+    /// Pop `items` values (the last one being on top) and push a new list holding them
+    NewList {
+        items: usize,
     },
+    /// Pop an index, then a list, and push the element at that index
+    ListLoad,
+    /// Pop a value, an index, then a list, and store the value at that index
+    ListStore,
 
     //Math
     AddI64,
@@ -69,6 +70,11 @@ pub enum Instruction {
     ModF64,
     ModU64,
 
+    /// Pop two strings and push their concatenation as a new string
+    StrConcat,
+    /// Pop two strings and push their ordering as an i64 (-1, 0 or 1)
+    StrCmp,
+
     Eq,
     Neq,
     Gt,
@@ -84,12 +90,47 @@ pub enum Instruction {
     JmpZ {
         pos: isize,
     },
+    /// Pop an u64 `n` and jump relatively by `targets[n]`
+    JmpTable {
+        targets: Vec<isize>,
+    },
 
+    /// Call the function at the label `name`, replaced by `Call` when the program is linked
     CallFunction {
         name: String,
         args: u8,
     },
+    /// Call the function starting at the instruction `target`
+    Call {
+        target: usize,
+        args: u8,
+    },
+    /// Pop `captures` values (the last one being on top) and push a closure of `function` holding them.
+    /// Replaced by `NewClosure` when the program is linked
+    MakeClosure {
+        function: String,
+        captures: usize,
+    },
+    /// Pop `captures` values (the last one being on top) and push a closure of the function
+    /// starting at the instruction `target` holding them
+    NewClosure {
+        target: usize,
+        captures: usize,
+    },
+    /// Pop a closure and call it with the `args` values below it, its captures are pushed after them
+    CallIndirect {
+        args: u8,
+    },
+    /// Call the method in slot `method` of the vtable of the trait object found below the `args`
+    /// values, the receiver being the first of them. The trait object is replaced by the
+    /// instance it wraps before the call.
+    CallVirtual {
+        method: usize,
+        args: u8,
+    },
+    /// Call the native function `name` of the imported `library`
     ExternCall {
+        library: String,
         name: String,
         args: u8,
     },
@@ -98,6 +139,15 @@ pub enum Instruction {
     Halt,
 }
 
+/// The methods of a class implementing a trait, in the order the trait declares them
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize)]
+pub struct VTable {
+    pub class: String,
+    pub trait_name: String,
+    /// Labels of the methods
+    pub methods: Vec<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize)]
 /// Read by the VM before execution to import the related functions
 pub struct ImportedLibrary {
@@ -110,21 +160,11 @@ pub struct Program<'vm> {
     pub labels: &'vm [&'vm Label<'vm>],
     pub entry_point: &'vm str,
     pub libraries: &'vm [&'vm ImportedLibrary],
-}
-
-impl Index<usize> for Program<'_> {
-    type Output = Instruction;
-
-    fn index(&self, index: usize) -> &Self::Output {
-        let mut current_index = 0;
-        for label in self.labels {
-            if current_index + label.body.len() > index {
-                return label.body[index - current_index];
-            }
-            current_index += label.body.len();
-        }
-        panic!("Index out of bounds");
-    }
+    /// Indexed by the vtable id stored in each trait object
+    pub vtables: &'vm [&'vm VTable],
+    /// Where each instruction comes from, used to locate runtime errors
+    #[serde(skip)]
+    pub debug: Option<&'vm DebugInfo>,
 }
 
 impl Default for Program<'_> {
@@ -145,6 +185,8 @@ impl Program<'_> {
             labels: &[],
             entry_point: "",
             libraries: &[],
+            vtables: &[],
+            debug: None,
         }
     }
 }
@@ -159,5 +201,7 @@ pub struct ConstantPool<'vm> {
 pub struct Label<'vm> {
     pub name: String,
     pub position: usize,
+    /// Number of slots of the frame of the function: its arguments, captures & locals
+    pub locals: usize,
     pub body: &'vm [&'vm Instruction],
 }
@@ -0,0 +1,304 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::atlas_vm::{
+    debug::{DebugInfo, SourceLocation},
+    errors::RuntimeError,
+    instruction::{Instruction, Program},
+    RuntimeResult,
+};
+
+/// A program whose labels are laid out one after the other in a single instruction vector.
+///
+/// Calls, closures & vtables refer to the position of their function instead of its label name.
+#[derive(Debug, Clone, Default)]
+pub struct LinkedProgram {
+    pub code: Vec<Instruction>,
+    /// Position of the first instruction of each function
+    pub functions: HashMap<String, usize>,
+    /// Position of each method of each vtable, indexed like `Program::vtables`
+    pub vtables: Vec<Vec<usize>>,
+    /// Every function in the order they're laid out, a function is identified by its index here
+    pub layouts: Vec<FunctionLayout>,
+    /// Where each instruction comes from, shared by the VMs running the program
+    pub debug: Option<Arc<DebugInfo>>,
+}
+
+/// Where a function starts & how many slots its frame has
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionLayout {
+    pub name: String,
+    pub start: usize,
+    /// Number of slots of the frame: arguments, captures & locals
+    pub locals: usize,
+}
+
+impl LinkedProgram {
+    /// Position of the first instruction of the function `name`
+    pub fn function(&self, name: &str) -> Option<usize> {
+        self.functions.get(name).copied()
+    }
+    /// Index in `layouts` of the function the instruction at `pc` belongs to
+    pub fn function_id(&self, pc: usize) -> Option<usize> {
+        self.layouts
+            .partition_point(|layout| layout.start <= pc)
+            .checked_sub(1)
+    }
+    /// Name of the function the instruction at `pc` belongs to
+    pub fn function_at(&self, pc: usize) -> Option<&str> {
+        self.function_id(pc)
+            .map(|id| self.layouts[id].name.as_str())
+    }
+}
+
+/// Flattens the labels of `program` & resolves every label name it refers to
+pub fn link(program: &Program) -> RuntimeResult<LinkedProgram> {
+    let mut positions = HashMap::new();
+    let mut layouts = Vec::with_capacity(program.labels.len());
+    let mut len = 0;
+    for label in program.labels {
+        positions.insert(label.name.as_str(), len);
+        layouts.push(FunctionLayout {
+            name: label.name.clone(),
+            start: len,
+            locals: label.locals,
+        });
+        len += label.body.len();
+    }
+    let resolve = |name: &str| {
+        positions
+            .get(name)
+            .copied()
+            .ok_or_else(|| RuntimeError::UnknownLabel(name.to_string()))
+    };
+
+    let mut code = Vec::with_capacity(len);
+    for label in program.labels {
+        for instr in label.body {
+            code.push(match instr {
+                Instruction::CallFunction { name, args } => Instruction::Call {
+                    target: resolve(name)?,
+                    args: *args,
+                },
+                Instruction::MakeClosure { function, captures } => Instruction::NewClosure {
+                    target: resolve(function)?,
+                    captures: *captures,
+                },
+                instr => (*instr).clone(),
+            });
+        }
+    }
+    let vtables = program
+        .vtables
+        .iter()
+        .map(|vtable| vtable.methods.iter().map(|m| resolve(m)).collect())
+        .collect::<RuntimeResult<_>>()?;
+    let functions = positions
+        .into_iter()
+        .map(|(name, position)| (name.to_string(), position))
+        .collect();
+
+    Ok(LinkedProgram {
+        code,
+        functions,
+        vtables,
+        layouts,
+        debug: program.debug.cloned().map(Arc::new),
+    })
+}
+
+/// Links `program` after the code of `previous`, which stays where it is.
+///
+/// The closures made by the code of `previous` still point to valid code, while calls by name
+/// now go to the functions of `program`.
+pub fn link_after(previous: &LinkedProgram, program: &Program) -> RuntimeResult<LinkedProgram> {
+    let base = previous.code.len();
+    let linked = link(program)?;
+    let mut code = previous.code.clone();
+    code.extend(linked.code.into_iter().map(|instr| match instr {
+        Instruction::Call { target, args } => Instruction::Call {
+            target: target + base,
+            args,
+        },
+        Instruction::NewClosure { target, captures } => Instruction::NewClosure {
+            target: target + base,
+            captures,
+        },
+        instr => instr,
+    }));
+    let mut layouts = previous.layouts.clone();
+    layouts.extend(linked.layouts.into_iter().map(|layout| FunctionLayout {
+        start: layout.start + base,
+        ..layout
+    }));
+
+    let mut debug = previous.debug.as_deref().cloned().unwrap_or_default();
+    debug.locations.resize(base, None);
+    if let Some(linked) = linked.debug.as_deref() {
+        let files = debug.files.len();
+        debug.files.extend(linked.files.iter().cloned());
+        debug
+            .locations
+            .extend(linked.locations.iter().map(|location| {
+                location.map(|l| SourceLocation {
+                    file: l.file + files,
+                    ..l
+                })
+            }));
+    }
+
+    Ok(LinkedProgram {
+        code,
+        functions: linked
+            .functions
+            .into_iter()
+            .map(|(name, position)| (name, position + base))
+            .collect(),
+        vtables: linked
+            .vtables
+            .into_iter()
+            .map(|methods| methods.into_iter().map(|m| m + base).collect())
+            .collect(),
+        layouts,
+        debug: Some(Arc::new(debug)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+
+    use super::*;
+    use crate::{
+        atlas_codegen::arena::CodeGenArena,
+        atlas_vm::instruction::{Label, VTable},
+    };
+
+    fn program<'a>(arena: &CodeGenArena<'a>, main: Vec<Instruction>) -> Program<'a> {
+        let square = vec![
+            Instruction::LoadLocal(0),
+            Instruction::Dup,
+            Instruction::MulI64,
+            Instruction::Return,
+        ];
+        Program {
+            labels: arena.alloc_vec(vec![
+                Label {
+                    name: "main".to_string(),
+                    position: 0,
+                    locals: 2,
+                    body: arena.alloc_vec(main),
+                },
+                Label {
+                    name: "square".to_string(),
+                    position: 0,
+                    locals: 1,
+                    body: arena.alloc_vec(square),
+                },
+            ]),
+            entry_point: "main",
+            libraries: arena.alloc_vec(Vec::new()),
+            vtables: arena.alloc_vec(vec![VTable {
+                class: "Square".to_string(),
+                trait_name: "Shape".to_string(),
+                methods: vec!["square".to_string(), "main".to_string()],
+            }]),
+            debug: None,
+        }
+    }
+
+    #[test]
+    fn resolves_labels() {
+        let bump = Bump::new();
+        let arena = CodeGenArena::new(&bump);
+        let main = vec![
+            Instruction::PushInt(3),
+            Instruction::CallFunction {
+                name: "square".to_string(),
+                args: 1,
+            },
+            Instruction::MakeClosure {
+                function: "square".to_string(),
+                captures: 0,
+            },
+            Instruction::Halt,
+        ];
+        let linked = link(&program(&arena, main)).unwrap();
+
+        assert_eq!(linked.code.len(), 8);
+        assert_eq!(linked.code[1], Instruction::Call { target: 4, args: 1 });
+        assert_eq!(
+            linked.code[2],
+            Instruction::NewClosure {
+                target: 4,
+                captures: 0
+            }
+        );
+        assert_eq!(linked.code[4], Instruction::LoadLocal(0));
+        assert_eq!(linked.function("main"), Some(0));
+        assert_eq!(linked.function("square"), Some(4));
+        assert_eq!(linked.vtables, vec![vec![4, 0]]);
+        assert_eq!(
+            linked.layouts,
+            vec![
+                FunctionLayout {
+                    name: "main".to_string(),
+                    start: 0,
+                    locals: 2,
+                },
+                FunctionLayout {
+                    name: "square".to_string(),
+                    start: 4,
+                    locals: 1,
+                },
+            ]
+        );
+        let ids = (0..linked.code.len())
+            .map(|pc| linked.function_id(pc).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(linked.function_at(7), Some("square"));
+    }
+
+    #[test]
+    fn links_after_previous_code() {
+        let bump = Bump::new();
+        let arena = CodeGenArena::new(&bump);
+        let main = vec![
+            Instruction::MakeClosure {
+                function: "square".to_string(),
+                captures: 0,
+            },
+            Instruction::Halt,
+        ];
+        let previous = link(&program(&arena, main.clone())).unwrap();
+        let linked = link_after(&previous, &program(&arena, main)).unwrap();
+
+        assert_eq!(linked.code[..6], previous.code[..]);
+        assert_eq!(
+            linked.code[6],
+            Instruction::NewClosure {
+                target: 8,
+                captures: 0
+            }
+        );
+        assert_eq!(linked.function("square"), Some(8));
+        assert_eq!(linked.vtables, vec![vec![8, 6]]);
+        assert_eq!(linked.function_at(3), Some("square"));
+        assert_eq!(linked.function_id(7), Some(2));
+        assert_eq!(linked.layouts.len(), 4);
+    }
+
+    #[test]
+    fn reports_unknown_labels() {
+        let bump = Bump::new();
+        let arena = CodeGenArena::new(&bump);
+        let main = vec![Instruction::CallFunction {
+            name: "cube".to_string(),
+            args: 1,
+        }];
+        assert!(matches!(
+            link(&program(&arena, main)),
+            Err(RuntimeError::UnknownLabel(name)) if name == "cube"
+        ));
+    }
+}
@@ -1,14 +1,27 @@
+pub mod bytecode;
+pub mod convert;
+pub mod debug;
+pub mod disasm;
 pub mod errors;
 pub mod instruction;
+pub mod linker;
+pub mod native;
 pub mod vm_state;
 
-use std::collections::HashMap;
+use std::{cmp::Ordering, collections::HashMap, sync::Arc};
 
+use debug::{RuntimeReport, StackTrace, TraceFrame, MAX_TRACE_FRAMES};
 use errors::RuntimeError;
-use instruction::{Instruction, Program};
+use instruction::{ImportedLibrary, Instruction, Program};
+use linker::LinkedProgram;
+use native::NativeModule;
 
 use crate::{
-    atlas_memory::{object_map::Memory, stack::Stack, vm_data::VMData},
+    atlas_memory::{
+        object_map::{Closure, Memory, MemoryStats, Object, Structure, DEFAULT_HEAP_LIMIT},
+        stack::Stack,
+        vm_data::VMData,
+    },
     atlas_stdlib::{
         file::FILE_FUNCTIONS, io::IO_FUNCTIONS, list::LIST_FUNCTIONS, math::MATH_FUNCTIONS,
         string::STRING_FUNCTIONS, time::TIME_FUNCTIONS,
@@ -17,333 +30,1014 @@ use crate::{
 
 pub type RuntimeResult<T> = Result<T, RuntimeError>;
 pub type CallBack = fn(vm_state::VMState) -> RuntimeResult<VMData>;
+/// A native function of the standard library or of the host
+pub type NativeCallBack =
+    Arc<dyn Fn(vm_state::VMState) -> RuntimeResult<VMData> + Send + Sync + 'static>;
+
+/// A call in progress, the arguments, captures & locals of the function live on the stack at
+/// fixed offsets from `bp`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    /// Index of the function in `LinkedProgram::layouts`
+    pub function: usize,
+    /// Position of the first slot of the frame on the stack, the arguments come first
+    pub bp: usize,
+    /// Number of slots of the frame
+    pub locals: usize,
+    /// Position of the call that created the frame, `None` for the entry point
+    pub return_pc: Option<usize>,
+}
 
 pub struct Atlas77VM<'run> {
     pub program: Program<'run>,
+    /// The program as it's executed, filled in by `run`
+    linked: LinkedProgram,
     pub(crate) stack: Stack,
-    stack_frame: Vec<(usize, usize)>, //previous pc and previous stack top
-    pub(crate) _object_map: Memory,
-    pub varmap: Vec<HashMap<String, VMData>>, //need to be changed
-    pub extern_fn: HashMap<&'run str, CallBack>,
+    /// Calls in progress, the current one last
+    frames: Vec<CallFrame>,
+    /// `bp` of the current frame
+    bp: usize,
+    /// Calls that were in progress when the program last failed
+    trace: StackTrace,
+    pub(crate) object_map: Memory,
+    /// The native functions of the imported libraries, by library & name
+    pub extern_fn: HashMap<(String, String), NativeCallBack>,
+    /// Modules of the host the program may import besides the standard libraries
+    natives: Vec<NativeModule>,
     pub pc: usize,
 }
 
 impl<'run> Atlas77VM<'run> {
     pub fn new(program: Program<'run>) -> Self {
-        let mut extern_fn: HashMap<&str, CallBack> = HashMap::new();
-        program.libraries.iter().for_each(|lib| {
-            if lib.is_std {
-                let lib_name = lib.name.split('/').last().unwrap();
-                match lib_name {
-                    "file" => {
-                        FILE_FUNCTIONS.iter().for_each(|(name, func)| {
-                            extern_fn.insert(name, *func);
-                        });
-                    }
-                    "io" => {
-                        IO_FUNCTIONS.iter().for_each(|(name, func)| {
-                            extern_fn.insert(name, *func);
-                        });
-                    }
-                    "list" => {
-                        LIST_FUNCTIONS.iter().for_each(|(name, func)| {
-                            extern_fn.insert(name, *func);
-                        });
-                    }
-                    "math" => {
-                        MATH_FUNCTIONS.iter().for_each(|(name, func)| {
-                            extern_fn.insert(name, *func);
-                        });
-                    }
-                    "string" => {
-                        STRING_FUNCTIONS.iter().for_each(|(name, func)| {
-                            extern_fn.insert(name, *func);
-                        });
-                    }
-                    "time" => {
-                        TIME_FUNCTIONS.iter().for_each(|(name, func)| {
-                            extern_fn.insert(name, *func);
-                        });
-                    }
-                    _ => panic!("Unknown standard library"),
-                }
-            }
-        });
+        let stack = Stack::new();
         Self {
             program,
-            stack: Stack::new(),
-            stack_frame: Vec::new(),
-            _object_map: Memory::new(1024),
-            varmap: vec![HashMap::new()],
-            extern_fn,
+            linked: LinkedProgram::default(),
+            bp: stack.top,
+            stack,
+            frames: Vec::new(),
+            trace: StackTrace::default(),
+            object_map: Memory::with_limit(1024, DEFAULT_HEAP_LIMIT),
+            extern_fn: HashMap::new(),
+            natives: Vec::new(),
             pc: 0,
         }
     }
+    /// Makes the functions of `modules` available to the programs importing them
+    pub fn with_natives(mut self, modules: &[NativeModule]) -> Self {
+        self.natives.extend_from_slice(modules);
+        self
+    }
+    /// Lets the heap grow up to `objects` objects
+    pub fn with_heap_limit(mut self, objects: usize) -> Self {
+        self.object_map.set_limit(objects);
+        self
+    }
+    /// Lets the stack grow up to `values` values, which bounds how deep calls can go
+    pub fn with_stack_limit(mut self, values: usize) -> Self {
+        self.stack.set_limit(values);
+        self
+    }
+    /// Registers the native functions of the libraries the program imports.
+    ///
+    /// Each function is registered under its library, a library can't provide a name twice.
+    fn import_libraries<'lib>(
+        &mut self,
+        libraries: impl IntoIterator<Item = &'lib ImportedLibrary>,
+    ) -> RuntimeResult<()> {
+        for lib in libraries {
+            let functions: Vec<(&str, NativeCallBack)> = match lib.name.strip_prefix("std/") {
+                _ if !lib.is_std => {
+                    let mut modules = self
+                        .natives
+                        .iter()
+                        .filter(|m| m.name == lib.name)
+                        .peekable();
+                    if modules.peek().is_none() {
+                        return Err(RuntimeError::UnknownLibrary(lib.name.clone()));
+                    }
+                    modules
+                        .flat_map(|m| m.functions.iter())
+                        .map(|f| (f.name.as_str(), f.callback.clone()))
+                        .collect()
+                }
+                Some(std) => {
+                    let functions: &[(&str, CallBack)] = match std {
+                        "file" => &FILE_FUNCTIONS,
+                        "io" => &IO_FUNCTIONS,
+                        "list" => &LIST_FUNCTIONS,
+                        "math" => &MATH_FUNCTIONS,
+                        "string" => &STRING_FUNCTIONS,
+                        "time" => &TIME_FUNCTIONS,
+                        _ => return Err(RuntimeError::UnknownLibrary(lib.name.clone())),
+                    };
+                    functions
+                        .iter()
+                        .map(|(name, f)| (*name, Arc::new(*f) as NativeCallBack))
+                        .collect()
+                }
+                None => return Err(RuntimeError::UnknownLibrary(lib.name.clone())),
+            };
+            let mut registered = HashMap::new();
+            for (name, callback) in functions {
+                let key = (lib.name.clone(), name.to_string());
+                if registered.insert(key, callback).is_some() {
+                    return Err(RuntimeError::DuplicateExtern {
+                        library: lib.name.clone(),
+                        name: name.to_string(),
+                    });
+                }
+            }
+            //Importing a library again, e.g. from the next entry of the REPL, replaces it
+            self.extern_fn.extend(registered);
+        }
+        Ok(())
+    }
     pub fn run(&mut self) -> RuntimeResult<VMData> {
-        let label = self
-            .program
-            .labels
+        self.import_libraries(self.program.libraries.iter().copied())?;
+        self.linked = linker::link_after(&self.linked, &self.program)?;
+        let entry_point = self.entry_point()?;
+        self.frames.clear();
+        self.enter(entry_point, self.stack.top, None)?;
+        self.execute()
+    }
+    /// Loads a program linked ahead of time, its functions are then run with `call`
+    pub fn load(
+        &mut self,
+        linked: LinkedProgram,
+        libraries: &[ImportedLibrary],
+    ) -> RuntimeResult<()> {
+        self.import_libraries(libraries)?;
+        self.linked = linked;
+        Ok(())
+    }
+    /// Runs the function starting at `target` with `args` & returns its result.
+    ///
+    /// The stack is left as it was, objects are kept.
+    pub fn call(&mut self, target: usize, args: &[VMData]) -> RuntimeResult<VMData> {
+        let (top, bp) = (self.stack.top, self.bp);
+        let res = args
             .iter()
-            .find(|label| label.name == self.program.entry_point);
-        if let Some(label) = label {
-            self.pc = label.position;
-        } else {
-            return Err(RuntimeError::EntryPointNotFound(
-                self.program.entry_point.to_string(),
-            ));
+            .try_for_each(|arg| self.stack.push(*arg))
+            .and_then(|_| {
+                self.enter(target, top, None)?;
+                self.execute()
+            });
+        self.frames.clear();
+        self.stack.truncate(top);
+        self.bp = bp;
+        res
+    }
+    /// Frees the objects the program can't reach anymore, returns how many were freed.
+    ///
+    /// Every value on the stack is a root: the locals & temporaries of all the frames.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.object_map.collect(self.stack.values().iter().copied())
+    }
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.object_map.stats()
+    }
+    /// Called before the instructions that may allocate, while their operands are still on the stack
+    #[inline(always)]
+    fn gc_safepoint(&mut self) {
+        if self.object_map.should_collect() {
+            self.collect_garbage();
         }
-        while self.pc < self.program.len() {
-            let instr = self.program[self.pc].clone();
-            self.execute_instruction(instr)?;
+    }
+    /// Position of the function `name` in the loaded program
+    pub fn linked_function(&self, name: &str) -> Option<usize> {
+        self.linked.function(name)
+    }
+    /// Calls in progress, the current one last
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+    /// Calls that were in progress when the program last failed, the innermost one first
+    pub fn stack_trace(&self) -> &StackTrace {
+        &self.trace
+    }
+    /// Renders `error` along with the stack trace of the last failure
+    pub fn report(&self, error: RuntimeError) -> RuntimeReport {
+        RuntimeReport::new(error, &self.trace, self.linked.debug.as_deref())
+    }
+    /// Walks the frames from the current one, each caller is located at its call
+    fn walk_frames(&self) -> StackTrace {
+        let debug = self.linked.debug.as_deref();
+        let mut trace = StackTrace::default();
+        let mut pc = Some(self.pc);
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            if depth == MAX_TRACE_FRAMES {
+                trace.omitted = self.frames.len() - depth;
+                break;
+            }
+            trace.frames.push(TraceFrame {
+                function: self
+                    .function_name(frame.function)
+                    .unwrap_or("<unknown>")
+                    .to_string(),
+                location: pc.zip(debug).and_then(|(pc, debug)| debug.location(pc)),
+            });
+            pc = frame.return_pc;
         }
-        self.stack.pop()
+        trace
+    }
+    /// Name of the function with the index `id` in the loaded program
+    pub fn function_name(&self, id: usize) -> Option<&str> {
+        self.linked
+            .layouts
+            .get(id)
+            .map(|layout| layout.name.as_str())
+    }
+    /// Starts running the function at `target` in a new frame beginning at `bp`.
+    ///
+    /// The arguments & captures are already on the stack, the other locals start as unit.
+    fn enter(&mut self, target: usize, bp: usize, return_pc: Option<usize>) -> RuntimeResult<()> {
+        let function = self
+            .linked
+            .function_id(target)
+            .ok_or(RuntimeError::InvalidOperation)?;
+        let locals = self.linked.layouts[function].locals;
+        for _ in self.stack.top - bp..locals {
+            self.stack.push(VMData::new_unit())?;
+        }
+        self.frames.push(CallFrame {
+            function,
+            bp,
+            locals,
+            return_pc,
+        });
+        self.bp = bp;
+        self.pc = target;
+        Ok(())
+    }
+    fn entry_point(&self) -> RuntimeResult<usize> {
+        self.linked
+            .function(self.program.entry_point)
+            .ok_or_else(|| RuntimeError::EntryPointNotFound(self.program.entry_point.to_string()))
+    }
+    /// Runs the entry point of `program` in the frame the previous entry point ran in, so the
+    /// locals it declared are still there: they are the first locals of the new entry point.
+    ///
+    /// Objects are kept as well. If the program fails, the locals it declared are dropped.
+    /// The code of the previous programs stays after it so the closures they made still point to
+    /// their functions, trait objects are only valid if `program` keeps the ids of their vtables.
+    pub fn resume(&mut self, program: Program<'run>) -> RuntimeResult<VMData> {
+        let (top, bp) = (self.stack.top, self.bp);
+        self.program = program;
+        self.import_libraries(self.program.libraries.iter().copied())?;
+        self.linked = linker::link_after(&self.linked, &self.program)?;
+        let entry_point = self.entry_point()?;
+        self.frames.clear();
+        let res = self
+            .enter(entry_point, bp, None)
+            .and_then(|_| self.execute());
+        if res.is_err() {
+            self.frames.clear();
+            self.stack.truncate(top);
+            self.bp = bp;
+        }
+        res
+    }
+    fn execute(&mut self) -> RuntimeResult<VMData> {
+        while self.pc < self.linked.code.len() {
+            let instr = self.linked.code[self.pc].clone();
+            if let Err(err) = self.execute_instruction(instr) {
+                self.trace = self.walk_frames();
+                return Err(match err {
+                    RuntimeError::StackOverflow { .. } => RuntimeError::StackOverflow {
+                        depth: self.frames.len(),
+                        function: self.trace.frames.first().map(|f| f.function.clone()),
+                    },
+                    err => err,
+                });
+            }
+        }
+        //`main` doesn't have to leave anything on the stack
+        Ok(self.stack.pop().unwrap_or(VMData::new_unit()))
+    }
+    /// Fields of the structure `obj` refers to, a program read from a file isn't trusted to only
+    /// use structures as such
+    fn fields(&mut self, obj: VMData) -> RuntimeResult<&mut Vec<VMData>> {
+        if !obj.is_object() {
+            return Err(RuntimeError::TypeMismatchError);
+        }
+        match self.object_map.get_mut(obj.as_object()) {
+            Object::Structure(s) => Ok(&mut s.fields),
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+    /// Items of the list `value` refers to
+    fn list(&mut self, value: VMData) -> RuntimeResult<&mut Vec<VMData>> {
+        if value.tag != VMData::TAG_LIST {
+            return Err(RuntimeError::TypeMismatchError);
+        }
+        match self.object_map.get_mut(value.as_object()) {
+            Object::List(items) => Ok(items),
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+    /// Content of the string `value` refers to
+    fn string(&self, value: VMData) -> RuntimeResult<&str> {
+        if value.tag != VMData::TAG_STR {
+            return Err(RuntimeError::TypeMismatchError);
+        }
+        match self.object_map.get(value.as_object()) {
+            Object::String(s) => Ok(s),
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+    /// The closure `value` refers to
+    fn closure(&self, value: VMData) -> RuntimeResult<&Closure> {
+        if value.tag != VMData::TAG_CLOSURE {
+            return Err(RuntimeError::TypeMismatchError);
+        }
+        match self.object_map.get(value.as_object()) {
+            Object::Closure(c) => Ok(c),
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+    /// Pops the two operands of an arithmetic instruction, which both have to be tagged `tag`.
+    /// The left one comes first.
+    fn operands(&mut self, tag: u64) -> RuntimeResult<(VMData, VMData)> {
+        let rhs = self.stack.pop()?;
+        let lhs = self.stack.pop()?;
+        if lhs.tag != tag || rhs.tag != tag {
+            return Err(RuntimeError::TypeMismatchError);
+        }
+        Ok((lhs, rhs))
+    }
+    /// Moves `pc` by `offset`, the target has to be an instruction of the program
+    fn jump(&mut self, offset: isize) -> RuntimeResult<()> {
+        self.pc = self
+            .pc
+            .checked_add_signed(offset)
+            .filter(|&target| target < self.linked.code.len())
+            .ok_or(RuntimeError::InvalidOperation)?;
+        Ok(())
+    }
+    /// Position on the stack of the first of the `args` arguments of a call
+    fn args_start(&self, args: u8) -> RuntimeResult<usize> {
+        self.stack
+            .top
+            .checked_sub(args as usize)
+            .ok_or(RuntimeError::StackUndeflow)
+    }
+    /// Lists can be indexed by both signed and unsigned integers, negative ones are out of bounds
+    fn list_index(index: VMData) -> RuntimeResult<usize> {
+        match index.tag {
+            VMData::TAG_U64 => Ok(index.as_u64() as usize),
+            VMData::TAG_I64 => {
+                usize::try_from(index.as_i64()).map_err(|_| RuntimeError::IndexOutOfBounds)
+            }
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+    /// Orders two numbers of the same type, `None` if one of them is NaN.
+    ///
+    /// Strings are turned into an ordering by `StrCmp` first, nothing else can be ordered.
+    fn order(a: VMData, b: VMData) -> RuntimeResult<Option<Ordering>> {
+        match (a.tag, b.tag) {
+            (VMData::TAG_I64, VMData::TAG_I64)
+            | (VMData::TAG_U64, VMData::TAG_U64)
+            | (VMData::TAG_FLOAT, VMData::TAG_FLOAT) => Ok(a.partial_cmp(&b)),
+            _ => Err(RuntimeError::TypeMismatchError),
+        }
+    }
+    /// Strings are compared by content, as generic code compares them without `StrCmp`.
+    /// Enum values are equal when they hold the same variant & equal payloads.
+    fn values_eq(&self, a: VMData, b: VMData) -> RuntimeResult<bool> {
+        let mut pending = vec![(a, b)];
+        while let Some((a, b)) = pending.pop() {
+            let equal = match (a.tag, b.tag) {
+                (VMData::TAG_STR, VMData::TAG_STR) => self.string(a)? == self.string(b)?,
+                (VMData::TAG_ENUM, VMData::TAG_ENUM) => {
+                    match (
+                        self.object_map.get(a.as_object()),
+                        self.object_map.get(b.as_object()),
+                    ) {
+                        (Object::Structure(a), Object::Structure(b)) => {
+                            pending.extend(a.fields.iter().copied().zip(b.fields.iter().copied()));
+                            a.fields.len() == b.fields.len()
+                        }
+                        _ => return Err(RuntimeError::TypeMismatchError),
+                    }
+                }
+                _ => a == b,
+            };
+            if !equal {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+    /// Pops `fields` values & pushes a structure holding them, tagged with `tag`
+    fn new_structure(&mut self, fields: usize, tag: u64) -> RuntimeResult<()> {
+        self.gc_safepoint();
+        let mut values = Vec::with_capacity(fields.min(self.stack.top));
+        for _ in 0..fields {
+            values.push(self.stack.pop()?);
+        }
+        values.reverse();
+        let idx = self
+            .object_map
+            .put(Object::Structure(Structure { fields: values }))?;
+        self.stack.push(VMData::new_object(tag, idx))
     }
     /// TODO: Add check for unsigned int
     pub fn execute_instruction(&mut self, instr: Instruction) -> RuntimeResult<()> {
         match instr {
             Instruction::PushInt(i) => {
                 let val = VMData::new_i64(i);
-                self.stack.push(val).unwrap();
+                self.stack.push(val)?;
                 self.pc += 1;
             }
             Instruction::PushFloat(f) => {
                 let val = VMData::new_f64(f);
-                self.stack.push(val).unwrap();
+                self.stack.push(val)?;
                 self.pc += 1;
             }
             Instruction::PushUnsignedInt(u) => {
                 let val = VMData::new_u64(u);
-                self.stack.push(val).unwrap();
+                self.stack.push(val)?;
+                self.pc += 1;
+            }
+            Instruction::PushBool(b) => {
+                self.stack.push(VMData::new_bool(b))?;
+                self.pc += 1;
+            }
+            Instruction::PushUnit => {
+                self.stack.push(VMData::new_unit())?;
+                self.pc += 1;
+            }
+            Instruction::PushString(s) => {
+                self.gc_safepoint();
+                let idx = self.object_map.put(Object::String(s))?;
+                self.stack.push(VMData::new_string(idx))?;
+                self.pc += 1;
+            }
+            Instruction::StrConcat => {
+                self.gc_safepoint();
+                let a = self.stack.pop()?;
+                let b = self.stack.pop()?;
+                let res = format!("{}{}", self.string(b)?, self.string(a)?);
+                let idx = self.object_map.put(Object::String(res))?;
+                self.stack.push(VMData::new_string(idx))?;
+                self.pc += 1;
+            }
+            Instruction::StrCmp => {
+                let a = self.stack.pop()?;
+                let b = self.stack.pop()?;
+                let ordering = self.string(b)?.cmp(self.string(a)?);
+                self.stack.push(VMData::new_i64(ordering as i64))?;
                 self.pc += 1;
             }
             Instruction::Lt => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_bool(b.as_i64() < a.as_i64());
-                self.stack.push(res).unwrap();
+                let a = self.stack.pop()?;
+                let b = self.stack.pop()?;
+                let res = VMData::new_bool(Self::order(b, a)?.is_some_and(Ordering::is_lt));
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::Lte => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_bool(b.as_i64() <= a.as_i64());
-                self.stack.push(res).unwrap();
+                let a = self.stack.pop()?;
+                let b = self.stack.pop()?;
+                let res = VMData::new_bool(Self::order(b, a)?.is_some_and(Ordering::is_le));
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::Gt => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_bool(b.as_i64() > a.as_i64());
-                self.stack.push(res).unwrap();
+                let a = self.stack.pop()?;
+                let b = self.stack.pop()?;
+                let res = VMData::new_bool(Self::order(b, a)?.is_some_and(Ordering::is_gt));
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::Gte => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_bool(b.as_i64() >= a.as_i64());
-                self.stack.push(res).unwrap();
+                let a = self.stack.pop()?;
+                let b = self.stack.pop()?;
+                let res = VMData::new_bool(Self::order(b, a)?.is_some_and(Ordering::is_ge));
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::Eq => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_bool(b.as_i64() == a.as_i64());
-                self.stack.push(res).unwrap();
+                let a = self.stack.pop()?;
+                let b = self.stack.pop()?;
+                let res = VMData::new_bool(self.values_eq(b, a)?);
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::Neq => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_bool(b.as_i64() != a.as_i64());
-                self.stack.push(res).unwrap();
+                let a = self.stack.pop()?;
+                let b = self.stack.pop()?;
+                let res = VMData::new_bool(!self.values_eq(b, a)?);
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::JmpZ { pos } => {
-                let cond = self.stack.pop().unwrap();
+                let cond = self.stack.pop()?;
+                if cond.tag != VMData::TAG_BOOL {
+                    return Err(RuntimeError::TypeMismatchError);
+                }
                 if !cond.as_bool() {
-                    self.pc += (pos + 1) as usize;
+                    self.jump(pos.checked_add(1).ok_or(RuntimeError::InvalidOperation)?)?;
                 } else {
                     self.pc += 1;
                 }
             }
             Instruction::Jmp { pos } => {
-                self.pc = (self.pc as isize + pos) as usize;
+                self.jump(pos)?;
             }
-            Instruction::StoreI64 { var_name } => {
-                let val = self.stack.pop().unwrap();
-                self.varmap.last_mut().unwrap().insert(var_name, val);
+            Instruction::JmpTable { targets } => {
+                let n = self.stack.pop()?;
+                if n.tag != VMData::TAG_U64 {
+                    return Err(RuntimeError::TypeMismatchError);
+                }
+                let pos = usize::try_from(n.as_u64())
+                    .ok()
+                    .and_then(|n| targets.get(n))
+                    .ok_or(RuntimeError::InvalidOperation)?;
+                self.jump(*pos)?;
+            }
+            Instruction::StoreLocal(slot) => {
+                let val = self.stack.pop()?;
+                self.stack.set(self.bp + slot as usize, val)?;
+                self.pc += 1;
+            }
+            Instruction::LoadLocal(slot) => {
+                let val = self.stack.get(self.bp + slot as usize)?;
+                self.stack.push(val)?;
+                self.pc += 1;
+            }
+            Instruction::Pop => {
+                self.stack.pop()?;
                 self.pc += 1;
             }
-            Instruction::StoreF64 { var_name } => {
-                let val = self.stack.pop().unwrap();
-                self.varmap.last_mut().unwrap().insert(var_name, val);
+            Instruction::Dup => {
+                let val = self.stack.pop()?;
+                self.stack.push(val)?;
+                self.stack.push(val)?;
                 self.pc += 1;
             }
-            Instruction::StoreU64 { var_name } => {
-                let val = self.stack.pop().unwrap();
-                self.varmap.last_mut().unwrap().insert(var_name, val);
+            Instruction::NewObj { fields } => {
+                self.new_structure(fields, VMData::TAG_OBJECT)?;
                 self.pc += 1;
             }
-            Instruction::LoadI64 { var_name } => {
-                let val = self.varmap.last().unwrap().get(&var_name).unwrap();
-                self.stack.push(*val).unwrap();
+            Instruction::NewEnum { fields } => {
+                self.new_structure(fields, VMData::TAG_ENUM)?;
                 self.pc += 1;
             }
-            Instruction::LoadF64 { var_name } => {
-                let val = self.varmap.last().unwrap().get(&var_name).unwrap();
-                self.stack.push(*val).unwrap();
+            Instruction::GetField { field } => {
+                let obj = self.stack.pop()?;
+                let val = *self
+                    .fields(obj)?
+                    .get(field)
+                    .ok_or(RuntimeError::IndexOutOfBounds)?;
+                self.stack.push(val)?;
                 self.pc += 1;
             }
-            Instruction::LoadU64 { var_name } => {
-                let val = self.varmap.last().unwrap().get(&var_name).unwrap();
-                self.stack.push(*val).unwrap();
+            Instruction::SetField { field } => {
+                let val = self.stack.pop()?;
+                let obj = self.stack.pop()?;
+                *self
+                    .fields(obj)?
+                    .get_mut(field)
+                    .ok_or(RuntimeError::IndexOutOfBounds)? = val;
+                self.pc += 1;
+            }
+            Instruction::NewList { items } => {
+                self.gc_safepoint();
+                let mut values = Vec::with_capacity(items.min(self.stack.top));
+                for _ in 0..items {
+                    values.push(self.stack.pop()?);
+                }
+                values.reverse();
+                let idx = self.object_map.put(Object::List(values))?;
+                self.stack.push(VMData::new_list(VMData::TAG_LIST, idx))?;
+                self.pc += 1;
+            }
+            Instruction::ListLoad => {
+                let index = self.stack.pop()?;
+                let list = self.stack.pop()?;
+                let list = self.list(list)?;
+                let val = *list
+                    .get(Self::list_index(index)?)
+                    .ok_or(RuntimeError::IndexOutOfBounds)?;
+                self.stack.push(val)?;
+                self.pc += 1;
+            }
+            Instruction::ListStore => {
+                let val = self.stack.pop()?;
+                let index = self.stack.pop()?;
+                let list = self.stack.pop()?;
+                let list = self.list(list)?;
+                let slot = list
+                    .get_mut(Self::list_index(index)?)
+                    .ok_or(RuntimeError::IndexOutOfBounds)?;
+                *slot = val;
                 self.pc += 1;
             }
             Instruction::MulI64 => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_i64(b.as_i64() * a.as_i64());
-                self.stack.push(res).unwrap();
+                let (b, a) = self.operands(VMData::TAG_I64)?;
+                let res = b
+                    .as_i64()
+                    .checked_mul(a.as_i64())
+                    .ok_or(RuntimeError::IntegerOverflow)?;
+                let res = VMData::new_i64(res);
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::MulF64 => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
+                let (b, a) = self.operands(VMData::TAG_FLOAT)?;
                 let res = VMData::new_f64(b.as_f64() * a.as_f64());
-                self.stack.push(res).unwrap();
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::MulU64 => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_u64(b.as_u64() * a.as_u64());
-                self.stack.push(res).unwrap();
+                let (b, a) = self.operands(VMData::TAG_U64)?;
+                let res = b
+                    .as_u64()
+                    .checked_mul(a.as_u64())
+                    .ok_or(RuntimeError::IntegerOverflow)?;
+                let res = VMData::new_u64(res);
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::DivI64 => {
-                let a = self.stack.pop().unwrap();
+                let (b, a) = self.operands(VMData::TAG_I64)?;
                 if a == VMData::new_i64(0) {
                     return Err(RuntimeError::DivisionByZero);
                 }
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_i64(b.as_i64() / a.as_i64());
-                self.stack.push(res).unwrap();
+                let res = b
+                    .as_i64()
+                    .checked_div(a.as_i64())
+                    .ok_or(RuntimeError::IntegerOverflow)?;
+                let res = VMData::new_i64(res);
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::DivF64 => {
-                let a = self.stack.pop().unwrap();
+                let (b, a) = self.operands(VMData::TAG_FLOAT)?;
                 if a == VMData::new_f64(0.0) {
                     return Err(RuntimeError::DivisionByZero);
                 }
-                let b = self.stack.pop().unwrap();
                 let res = VMData::new_f64(b.as_f64() / a.as_f64());
-                self.stack.push(res).unwrap();
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::DivU64 => {
-                let a = self.stack.pop().unwrap();
+                let (b, a) = self.operands(VMData::TAG_U64)?;
                 if a == VMData::new_u64(0) {
                     return Err(RuntimeError::DivisionByZero);
                 }
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_u64(b.as_u64() / a.as_u64());
-                self.stack.push(res).unwrap();
+                let res = b
+                    .as_u64()
+                    .checked_div(a.as_u64())
+                    .ok_or(RuntimeError::IntegerOverflow)?;
+                let res = VMData::new_u64(res);
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::AddI64 => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_i64(b.as_i64() + a.as_i64());
-                self.stack.push(res).unwrap();
+                let (b, a) = self.operands(VMData::TAG_I64)?;
+                let res = b
+                    .as_i64()
+                    .checked_add(a.as_i64())
+                    .ok_or(RuntimeError::IntegerOverflow)?;
+                let res = VMData::new_i64(res);
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::AddF64 => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
+                let (b, a) = self.operands(VMData::TAG_FLOAT)?;
                 let res = VMData::new_f64(b.as_f64() + a.as_f64());
-                self.stack.push(res).unwrap();
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::AddU64 => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_u64(b.as_u64() + a.as_u64());
-                self.stack.push(res).unwrap();
+                let (b, a) = self.operands(VMData::TAG_U64)?;
+                let res = b
+                    .as_u64()
+                    .checked_add(a.as_u64())
+                    .ok_or(RuntimeError::IntegerOverflow)?;
+                let res = VMData::new_u64(res);
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::SubI64 => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_i64(b.as_i64() - a.as_i64());
-                self.stack.push(res).unwrap();
+                let (b, a) = self.operands(VMData::TAG_I64)?;
+                let res = b
+                    .as_i64()
+                    .checked_sub(a.as_i64())
+                    .ok_or(RuntimeError::IntegerOverflow)?;
+                let res = VMData::new_i64(res);
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::SubF64 => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
+                let (b, a) = self.operands(VMData::TAG_FLOAT)?;
                 let res = VMData::new_f64(b.as_f64() - a.as_f64());
-                self.stack.push(res).unwrap();
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::SubU64 => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_u64(b.as_u64() - a.as_u64());
-                self.stack.push(res).unwrap();
+                let (b, a) = self.operands(VMData::TAG_U64)?;
+                let res = b
+                    .as_u64()
+                    .checked_sub(a.as_u64())
+                    .ok_or(RuntimeError::IntegerOverflow)?;
+                let res = VMData::new_u64(res);
+                self.stack.push(res)?;
                 self.pc += 1;
             }
             Instruction::ModI64 => {
-                let a = self.stack.pop().unwrap();
-                let b = self.stack.pop().unwrap();
-                let res = VMData::new_i64(b.as_i64() % a.as_i64());
-                self.stack.push(res).unwrap();
+                let (b, a) = self.operands(VMData::TAG_I64)?;
+                if a == VMData::new_i64(0) {
+                    return Err(RuntimeError::DivisionByZero);
+                }
+                let res = b
+                    .as_i64()
+                    .checked_rem(a.as_i64())
+                    .ok_or(RuntimeError::IntegerOverflow)?;
+                let res = VMData::new_i64(res);
+                self.stack.push(res)?;
+                self.pc += 1;
+            }
+            Instruction::ModF64 => {
+                let (b, a) = self.operands(VMData::TAG_FLOAT)?;
+                let res = VMData::new_f64(b.as_f64() % a.as_f64());
+                self.stack.push(res)?;
                 self.pc += 1;
             }
-            Instruction::ExternCall { name, .. } => {
+            Instruction::ModU64 => {
+                let (b, a) = self.operands(VMData::TAG_U64)?;
+                if a == VMData::new_u64(0) {
+                    return Err(RuntimeError::DivisionByZero);
+                }
+                let res = b
+                    .as_u64()
+                    .checked_rem(a.as_u64())
+                    .ok_or(RuntimeError::IntegerOverflow)?;
+                let res = VMData::new_u64(res);
+                self.stack.push(res)?;
+                self.pc += 1;
+            }
+            Instruction::ExternCall { library, name, .. } => {
+                self.gc_safepoint();
                 let consts = HashMap::new();
-                let vm_state = vm_state::VMState::new(
-                    &mut self.stack,
-                    &mut self._object_map,
-                    &consts,
-                    self.varmap.last().unwrap(),
-                );
-                let extern_fn: &CallBack = self.extern_fn.get::<&str>(&name.as_str()).unwrap();
+                let vm_state =
+                    vm_state::VMState::new(&mut self.stack, &mut self.object_map, &consts);
+                let key = (library, name);
+                let extern_fn = self
+                    .extern_fn
+                    .get(&key)
+                    .ok_or_else(|| RuntimeError::UnknownExtern(format!("{}::{}", key.0, key.1)))?;
                 let res = extern_fn(vm_state)?;
-                self.stack.push(res).unwrap();
+                self.stack.push(res)?;
+                self.pc += 1;
+            }
+            Instruction::Call { target, args } => {
+                self.enter(target, self.args_start(args)?, Some(self.pc))?;
+            }
+            //Linking replaces the instructions referring to labels by name
+            Instruction::CallFunction { .. } | Instruction::MakeClosure { .. } => {
+                return Err(RuntimeError::InvalidOperation);
+            }
+            Instruction::NewClosure { target, captures } => {
+                self.gc_safepoint();
+                let mut values = Vec::with_capacity(captures.min(self.stack.top));
+                for _ in 0..captures {
+                    values.push(self.stack.pop()?);
+                }
+                values.reverse();
+                let idx = self.object_map.put(Object::Closure(Closure {
+                    function: target,
+                    captures: values,
+                }))?;
+                self.stack
+                    .push(VMData::new_object(VMData::TAG_CLOSURE, idx))?;
                 self.pc += 1;
             }
-            Instruction::CallFunction { name, args } => {
-                let label = self
-                    .program
-                    .labels
-                    .iter()
-                    .find(|label| label.name == name)
-                    .unwrap();
-                let (pc, sp) = (self.pc, self.stack.top - args as usize);
-                self.stack_frame.push((pc, sp));
-                self.varmap.push(HashMap::new());
-                self.pc = label.position;
+            Instruction::CallIndirect { args } => {
+                let closure = self.stack.pop()?;
+                let closure = self.closure(closure)?.clone();
+                let bp = self.args_start(args)?;
+                //The captures are the locals following the arguments
+                for capture in closure.captures {
+                    self.stack.push(capture)?;
+                }
+                self.enter(closure.function, bp, Some(self.pc))?;
+            }
+            Instruction::CallVirtual { method, args } => {
+                let receiver = self.args_start(args)?;
+                let trait_obj = self.stack.get(receiver)?;
+                let (vtable, instance) = match self.fields(trait_obj)?[..] {
+                    [vtable, instance] if vtable.tag == VMData::TAG_U64 => {
+                        (vtable.as_u64() as usize, instance)
+                    }
+                    _ => return Err(RuntimeError::TypeMismatchError),
+                };
+                //The method expects the instance itself as its receiver
+                self.stack.set(receiver, instance)?;
+                let target = self
+                    .linked
+                    .vtables
+                    .get(vtable)
+                    .and_then(|vtable| vtable.get(method))
+                    .copied()
+                    .ok_or(RuntimeError::InvalidOperation)?;
+                self.enter(target, receiver, Some(self.pc))?;
             }
             Instruction::Return => {
-                let (pc, sp) = self.stack_frame.pop().unwrap_or_else(|| {
-                    eprintln!(
-                        "No stack frame to return to {:?} @ {}",
-                        self.stack.pop(),
-                        self.pc
-                    );
-                    std::process::exit(1);
-                });
-                self.varmap.pop();
+                //Returning from the entry point ends the program, the value stays on the stack
+                let Some(CallFrame {
+                    bp,
+                    return_pc: Some(pc),
+                    ..
+                }) = self.frames.pop()
+                else {
+                    self.pc = self.linked.code.len();
+                    return Ok(());
+                };
                 self.pc = pc + 1;
-                let ret = self.stack.pop().unwrap();
-                self.stack.truncate(sp);
-                self.stack.push(ret).unwrap();
+                let ret = self.stack.pop()?;
+                //The arguments & locals of the frame are dropped along with it
+                self.stack.truncate(bp);
+                self.stack.push(ret)?;
+                if let Some(caller) = self.frames.last() {
+                    self.bp = caller.bp;
+                }
             }
             Instruction::Halt => {
-                self.pc = self.program.len();
+                self.pc = self.linked.code.len();
             }
-            _ => unimplemented!("{:?}", instr),
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_only_numbers() {
+        let mut vm = Atlas77VM::new(Program::new());
+        vm.stack.push(VMData::new_f64(1.5)).unwrap();
+        vm.stack.push(VMData::new_f64(2.0)).unwrap();
+        vm.execute_instruction(Instruction::Lt).unwrap();
+        assert!(vm.stack.pop().unwrap().as_bool());
+
+        let list = vm.object_map.put(Object::List(Vec::new())).unwrap();
+        vm.stack
+            .push(VMData::new_list(VMData::TAG_LIST, list))
+            .unwrap();
+        vm.stack
+            .push(VMData::new_list(VMData::TAG_LIST, list))
+            .unwrap();
+        assert!(matches!(
+            vm.execute_instruction(Instruction::Gte),
+            Err(RuntimeError::TypeMismatchError)
+        ));
+    }
+
+    #[test]
+    fn checks_fields_of_decoded_programs() {
+        let mut vm = Atlas77VM::new(Program::new());
+        vm.stack.push(VMData::new_i64(1)).unwrap();
+        vm.stack.push(VMData::new_i64(2)).unwrap();
+        vm.execute_instruction(Instruction::NewObj { fields: 2 })
+            .unwrap();
+        let obj = vm.stack.pop().unwrap();
+
+        vm.stack.push(obj).unwrap();
+        vm.execute_instruction(Instruction::GetField { field: 1 })
+            .unwrap();
+        assert_eq!(vm.stack.pop().unwrap().as_i64(), 2);
+        vm.stack.push(obj).unwrap();
+        assert!(matches!(
+            vm.execute_instruction(Instruction::GetField { field: 2 }),
+            Err(RuntimeError::IndexOutOfBounds)
+        ));
+        vm.stack.push(obj).unwrap();
+        vm.stack.push(VMData::new_i64(3)).unwrap();
+        assert!(matches!(
+            vm.execute_instruction(Instruction::SetField { field: 5 }),
+            Err(RuntimeError::IndexOutOfBounds)
+        ));
+        vm.stack.push(VMData::new_i64(3)).unwrap();
+        assert!(matches!(
+            vm.execute_instruction(Instruction::GetField { field: 0 }),
+            Err(RuntimeError::TypeMismatchError)
+        ));
+        //A trait object is a vtable id & an instance, anything else isn't one
+        vm.stack.push(obj).unwrap();
+        assert!(matches!(
+            vm.execute_instruction(Instruction::CallVirtual { method: 0, args: 1 }),
+            Err(RuntimeError::TypeMismatchError)
+        ));
+    }
+
+    #[test]
+    fn checks_operands_of_decoded_programs() {
+        let mut vm = Atlas77VM::new(Program::new());
+        let mismatches = [
+            (
+                vec![VMData::new_i64(1), VMData::new_i64(0)],
+                Instruction::ListLoad,
+            ),
+            (
+                vec![VMData::new_i64(1), VMData::new_i64(0), VMData::new_i64(2)],
+                Instruction::ListStore,
+            ),
+            (
+                vec![VMData::new_i64(1), VMData::new_i64(2)],
+                Instruction::StrConcat,
+            ),
+            (
+                vec![VMData::new_i64(1), VMData::new_i64(2)],
+                Instruction::StrCmp,
+            ),
+            (
+                vec![VMData::new_i64(1)],
+                Instruction::CallIndirect { args: 0 },
+            ),
+            (vec![VMData::new_i64(0)], Instruction::JmpZ { pos: 1 }),
+            (
+                vec![VMData::new_i64(0)],
+                Instruction::JmpTable { targets: vec![1] },
+            ),
+            (
+                vec![VMData::new_f64(1.0), VMData::new_i64(2)],
+                Instruction::AddI64,
+            ),
+            (
+                vec![VMData::new_u64(1), VMData::new_unit()],
+                Instruction::DivU64,
+            ),
+        ];
+        for (operands, instr) in mismatches {
+            for operand in operands {
+                vm.stack.push(operand).unwrap();
+            }
+            assert!(
+                matches!(
+                    vm.execute_instruction(instr.clone()),
+                    Err(RuntimeError::TypeMismatchError)
+                ),
+                "{:?}",
+                instr
+            );
+        }
+        //Jumps can't leave the program, whatever their offset
+        for (operands, instr) in [
+            (vec![], Instruction::Jmp { pos: -1 }),
+            (
+                vec![VMData::new_bool(false)],
+                Instruction::JmpZ { pos: isize::MAX },
+            ),
+            (
+                vec![],
+                Instruction::Call {
+                    target: 0,
+                    args: u8::MAX,
+                },
+            ),
+        ] {
+            for operand in operands {
+                vm.stack.push(operand).unwrap();
+            }
+            assert!(vm.execute_instruction(instr).is_err());
+        }
+    }
+
+    #[test]
+    fn reports_integer_overflow() {
+        let mut vm = Atlas77VM::new(Program::new());
+        for (a, b, instr) in [
+            (i64::MAX, 1, Instruction::AddI64),
+            (i64::MIN, 1, Instruction::SubI64),
+            (i64::MAX, 2, Instruction::MulI64),
+            (i64::MIN, -1, Instruction::DivI64),
+            (i64::MIN, -1, Instruction::ModI64),
+        ] {
+            vm.stack.push(VMData::new_i64(a)).unwrap();
+            vm.stack.push(VMData::new_i64(b)).unwrap();
+            assert!(matches!(
+                vm.execute_instruction(instr),
+                Err(RuntimeError::IntegerOverflow)
+            ));
+        }
+        for (a, b, instr) in [
+            (u64::MAX, 1, Instruction::AddU64),
+            (0, 1, Instruction::SubU64),
+            (u64::MAX, 2, Instruction::MulU64),
+        ] {
+            vm.stack.push(VMData::new_u64(a)).unwrap();
+            vm.stack.push(VMData::new_u64(b)).unwrap();
+            assert!(matches!(
+                vm.execute_instruction(instr),
+                Err(RuntimeError::IntegerOverflow)
+            ));
+        }
+    }
+}
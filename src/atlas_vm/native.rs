@@ -0,0 +1,170 @@
+use std::{fmt, sync::Arc};
+
+use crate::{
+    atlas_memory::vm_data::VMData,
+    atlas_vm::{
+        convert::{FromAtlas, IntoAtlas},
+        vm_state::VMState,
+        CallBack, NativeCallBack,
+    },
+};
+
+/// Type of a parameter or of the return value of a native function, as written in Atlas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NativeType {
+    Int64,
+    Float64,
+    UInt64,
+    Bool,
+    Str,
+    Unit,
+    List(Box<NativeType>),
+    /// A struct, class or enum declared in Atlas, with the names of its fields in their order
+    Named(String, Vec<String>),
+    /// A type parameter of the `extern` declaration, e.g. the items of `[T]`
+    Generic,
+}
+
+impl fmt::Display for NativeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NativeType::Int64 => write!(f, "i64"),
+            NativeType::Float64 => write!(f, "f64"),
+            NativeType::UInt64 => write!(f, "u64"),
+            NativeType::Bool => write!(f, "bool"),
+            NativeType::Str => write!(f, "str"),
+            NativeType::Unit => write!(f, "unit"),
+            NativeType::List(inner) if f.alternate() => write!(f, "[{:#}]", inner),
+            NativeType::List(inner) => write!(f, "[{}]", inner),
+            //`{:#}` spells out the fields, e.g. `Point { x, y }`
+            NativeType::Named(name, fields) if f.alternate() && !fields.is_empty() => {
+                write!(f, "{} {{ {} }}", name, fields.join(", "))
+            }
+            NativeType::Named(name, _) => write!(f, "{}", name),
+            NativeType::Generic => write!(f, "T"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub params: Vec<NativeType>,
+    pub ret: NativeType,
+    pub callback: NativeCallBack,
+}
+
+impl NativeFunction {
+    /// The signature as an Atlas `extern` declaration would spell it, e.g. `(i64, str) -> bool`
+    pub fn signature(&self) -> String {
+        let params = self
+            .params
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("({}) -> {}", params, self.ret)
+    }
+}
+
+/// Native functions a host provides under a module name.
+///
+/// Atlas code imports the module with `import "<name>"` & declares the functions it uses with
+/// `extern`, each declaration is checked against the signature given here.
+#[derive(Clone)]
+pub struct NativeModule {
+    pub name: String,
+    pub functions: Vec<NativeFunction>,
+}
+
+impl NativeModule {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            functions: Vec::new(),
+        }
+    }
+    /// Adds a plain Rust function, its signature is the one of its arguments & result:
+    /// ```ignore
+    /// NativeModule::new("host").function("double", |x: i64| x * 2)
+    /// ```
+    pub fn function<Args>(
+        mut self,
+        name: impl Into<String>,
+        function: impl NativeFn<Args>,
+    ) -> Self {
+        self.functions.push(NativeFunction {
+            name: name.into(),
+            params: function.params(),
+            ret: function.ret(),
+            callback: function.into_callback(),
+        });
+        self
+    }
+    /// Adds a function working on the state of the VM directly, it pops its arguments itself
+    pub fn raw_function(
+        mut self,
+        name: impl Into<String>,
+        params: &[NativeType],
+        ret: NativeType,
+        callback: CallBack,
+    ) -> Self {
+        self.functions.push(NativeFunction {
+            name: name.into(),
+            params: params.to_vec(),
+            ret,
+            callback: Arc::new(callback),
+        });
+        self
+    }
+    pub fn get(&self, name: &str) -> Option<&NativeFunction> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+}
+
+/// Rust functions whose arguments & result convert from & to Atlas values
+pub trait NativeFn<Args> {
+    fn params(&self) -> Vec<NativeType>;
+    fn ret(&self) -> NativeType;
+    fn into_callback(self) -> NativeCallBack;
+}
+
+macro_rules! native_fn {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg,)*> NativeFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: IntoAtlas,
+            $($arg: FromAtlas,)*
+        {
+            fn params(&self) -> Vec<NativeType> {
+                vec![$($arg::atlas_type()),*]
+            }
+            fn ret(&self) -> NativeType {
+                R::atlas_type()
+            }
+            #[allow(unused_mut, unused_variables)]
+            fn into_callback(self) -> NativeCallBack {
+                Arc::new(move |mut state: VMState| {
+                    //The last argument is on top of the stack
+                    let arity: &[&str] = &[$(stringify!($arg)),*];
+                    let mut args: Vec<VMData> = Vec::with_capacity(arity.len());
+                    for _ in arity {
+                        args.push(state.stack.pop()?);
+                    }
+                    let mut args = args.into_iter().rev();
+                    let res = self($($arg::from_atlas(args.next().unwrap(), state.object_map)?),*);
+                    state.alloc(res)
+                })
+            }
+        }
+    };
+}
+
+native_fn!();
+native_fn!(A);
+native_fn!(A, B);
+native_fn!(A, B, C);
+native_fn!(A, B, C, D);
+native_fn!(A, B, C, D, E);
+native_fn!(A, B, C, D, E, G);
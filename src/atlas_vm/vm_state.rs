@@ -1,12 +1,27 @@
 use std::collections::HashMap;
 
-use crate::atlas_memory::{object_map::Memory, stack::Stack, vm_data::VMData};
+use crate::{
+    atlas_memory::{
+        object_map::{Memory, ObjectIndex},
+        stack::Stack,
+        vm_data::VMData,
+    },
+    atlas_vm::{
+        convert::{FromAtlas, IntoAtlas},
+        RuntimeResult,
+    },
+};
 
+/// What a native function sees of the VM while it runs.
+///
+/// The objects it allocates with [`VMState::alloc`] or holds with [`VMState::pin`] are roots of
+/// the collector until the native function returns, so allocating again can't free them.
 pub struct VMState<'state, 'run> {
     pub stack: &'state mut Stack,
     pub object_map: &'state mut Memory,
     pub consts: &'state HashMap<&'run str, VMData>,
-    pub varmap: &'state HashMap<String, VMData>,
+    /// Objects pinned on behalf of the native function, unpinned when it returns
+    pinned: Vec<ObjectIndex>,
 }
 
 impl<'state, 'run> VMState<'state, 'run> {
@@ -14,13 +29,44 @@ impl<'state, 'run> VMState<'state, 'run> {
         stack: &'state mut Stack,
         object_map: &'state mut Memory,
         consts: &'state HashMap<&'run str, VMData>,
-        varmap: &'state HashMap<String, VMData>,
     ) -> Self {
         Self {
             stack,
             object_map,
             consts,
-            varmap,
+            pinned: Vec::new(),
+        }
+    }
+    /// Pops a value of type `T`, fails with `TypeMismatchError` if the value has another type
+    pub fn pop<T: FromAtlas>(&mut self) -> RuntimeResult<T> {
+        let value = self.stack.pop()?;
+        T::from_atlas(value, self.object_map)
+    }
+    /// Turns `value` into a value of the VM, allocating it if needed.
+    ///
+    /// The heap may be collected first, the values on the stack & the pinned objects are kept.
+    pub fn alloc<T: IntoAtlas>(&mut self, value: T) -> RuntimeResult<VMData> {
+        if self.object_map.should_collect() {
+            self.object_map.collect(self.stack.values().iter().copied());
+        }
+        let value = value.into_atlas(self.object_map)?;
+        self.pin(value);
+        Ok(value)
+    }
+    /// Keeps the object `value` refers to alive until the native function returns, e.g. a
+    /// handle popped from the stack that is still used after allocating
+    pub fn pin(&mut self, value: VMData) {
+        if value.is_object() {
+            self.object_map.pin(value.as_object());
+            self.pinned.push(value.as_object());
+        }
+    }
+}
+
+impl Drop for VMState<'_, '_> {
+    fn drop(&mut self) {
+        for index in self.pinned.drain(..) {
+            self.object_map.unpin(index);
         }
     }
 }
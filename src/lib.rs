@@ -3,76 +3,125 @@ pub mod atlas_frontend;
 pub mod atlas_hir;
 pub mod atlas_macro;
 pub mod atlas_memory;
+pub mod atlas_repl;
 pub mod atlas_runtime;
 pub mod atlas_stdlib;
 pub mod atlas_vm;
 use crate::{
     atlas_codegen::{arena::CodeGenArena, CodeGenUnit},
-    atlas_frontend::parse,
+    atlas_frontend::{parse, tokenize},
     atlas_hir::{arena::HirArena, syntax_lowering_pass::AstSyntaxLoweringPass},
 };
+use atlas_core::prelude::Spanned;
 use atlas_frontend::parser::arena::AstArena;
 use atlas_hir::type_check_pass::TypeChecker;
 use bumpalo::Bump;
+use miette::{Context, IntoDiagnostic};
 
 use std::{io::Write, path::PathBuf, time::Instant};
 
-pub fn build(path: String) -> miette::Result<()> {
-    let mut path_buf = PathBuf::from(path.clone());
-    if let Ok(current_dir) = std::env::current_dir() {
-        if !path_buf.is_absolute() {
-            path_buf = current_dir.join(path_buf);
-        }
-    } else {
-        eprintln!("Failed to get current directory");
-    }
+/// What `build` produces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Emit {
+    /// The tokens of the source file
+    Tokens,
+    /// The AST, as RON
+    Ast,
+    /// The HIR right after lowering, as RON
+    Hir,
+    /// The HIR once type-checked, as RON
+    TypedHir,
+    /// The binary bytecode `exec` runs
+    #[default]
+    Bytecode,
+    /// A listing of the bytecode
+    Disasm,
+}
 
-    let source = std::fs::read_to_string(path).unwrap();
-    //parse
-    let bump = Bump::new();
-    let ast_arena = AstArena::new(&bump);
-    let program = parse(path_buf.to_str().unwrap(), &ast_arena, source.clone())?;
+/// Compiles the file at `path` up to `emit` & writes the result to `output`.
+///
+/// Without `output`, the bytecode goes to `output.atlasc` & everything else to stdout. The
+/// bytecode only holds the paths & the source of the files when `debug` is set, runtime errors
+/// are then located in the code.
+pub fn build(path: String, output: Option<String>, emit: Emit, debug: bool) -> miette::Result<()> {
+    let artifact = compile(path, emit, debug)?;
+    match output {
+        Some(output) => std::fs::write(&output, artifact)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to write {}", output))?,
+        None if emit == Emit::Bytecode => std::fs::write("output.atlasc", artifact)
+            .into_diagnostic()
+            .wrap_err("failed to write output.atlasc")?,
+        None => std::io::stdout().write_all(&artifact).into_diagnostic()?,
+    }
+    Ok(())
+}
 
-    //hir
-    let hir_arena = HirArena::new();
-    let lower = AstSyntaxLoweringPass::new(&hir_arena, &program, &ast_arena, source.clone());
-    let hir = lower.lower()?;
+/// Compiles the file at `path` then runs it, the bytecode isn't written anywhere
+pub fn run(path: String) -> miette::Result<()> {
+    let bytecode = compile(path, Emit::Bytecode, true)?;
+    execute(&bytecode)
+}
 
-    //type-check
-    let mut type_checker = TypeChecker::new(&hir_arena, source.clone());
-    type_checker.check(&hir)?;
+/// Prints the bytecode of a source file, or of a bytecode file produced by `build`
+pub fn disasm(path: String) -> miette::Result<()> {
+    let listing = if path.ends_with(".atlasc") {
+        let bytecode = std::fs::read(&path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to read {}", path))?;
+        let bump = Bump::new();
+        let program = atlas_vm::bytecode::decode(&bytecode, &bump)?;
+        atlas_vm::disasm::disassemble(&program).into_bytes()
+    } else {
+        compile(path, Emit::Disasm, false)?
+    };
+    std::io::stdout().write_all(&listing).into_diagnostic()
+}
 
-    //codegen
+/// Starts an interactive prompt, entries are read from stdin until `:quit` or the end of input
+pub fn repl() -> miette::Result<()> {
     let bump = Bump::new();
-    let arena = CodeGenArena::new(&bump);
-    let mut codegen = CodeGenUnit::new(hir, arena, source);
-    let program = codegen.compile()?;
-    let output = ron::ser::to_string_pretty(&program, Default::default()).unwrap();
-    let mut file = std::fs::File::create("output.atlasc").unwrap();
-    file.write_all(output.as_bytes()).unwrap();
-
-    //run
-    let start = Instant::now();
-    let mut vm = atlas_vm::Atlas77VM::new(program);
-    let res = vm.run();
-    let end = Instant::now();
-    match res {
-        Ok(_) => {
-            println!(
-                "Program ran successfully: {} (time: {}ms)",
-                vm.stack.pop().unwrap(),
-                (end - start).as_millis()
-            );
+    let mut repl = atlas_repl::Repl::new(&bump);
+    let stdin = std::io::stdin();
+    loop {
+        //An entry goes on until its braces are balanced
+        let mut input = String::new();
+        let mut depth = 0;
+        loop {
+            print!("{}", if input.is_empty() { ">> " } else { ".. " });
+            std::io::stdout().flush().into_diagnostic()?;
+            let mut line = String::new();
+            if stdin.read_line(&mut line).into_diagnostic()? == 0 {
+                return Ok(());
+            }
+            depth += line.matches('{').count() as isize - line.matches('}').count() as isize;
+            input.push_str(&line);
+            if depth <= 0 {
+                break;
+            }
+        }
+        match input.trim() {
+            "" => continue,
+            ":quit" | ":q" => return Ok(()),
+            _ => {}
         }
-        Err(e) => {
-            eprintln!("{}", e);
+        match repl.eval(&input) {
+            Ok(Some(value)) => println!("{}", value),
+            Ok(None) => {}
+            Err(e) => eprintln!("{:?}", e),
         }
     }
-    Ok(())
 }
 
-//The "run" function needs a bit of refactoring
-pub fn run(path: String) -> miette::Result<()> {
+/// Runs the bytecode file at `path` produced by `build`
+pub fn exec(path: String) -> miette::Result<()> {
+    let bytecode = std::fs::read(&path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to read {}", path))?;
+    execute(&bytecode)
+}
+
+fn compile(path: String, emit: Emit, debug: bool) -> miette::Result<Vec<u8>> {
     let mut path_buf = PathBuf::from(path.clone());
     if let Ok(current_dir) = std::env::current_dir() {
         if !path_buf.is_absolute() {
@@ -82,47 +131,204 @@ pub fn run(path: String) -> miette::Result<()> {
         eprintln!("Failed to get current directory");
     }
 
-    let source = std::fs::read_to_string(path).unwrap();
+    let source = std::fs::read_to_string(&path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed to read {}", path))?;
+    if emit == Emit::Tokens {
+        let mut tokens = String::new();
+        for token in tokenize(source)? {
+            let span = token.span();
+            tokens.push_str(&format!(
+                "{}..{} {:?}\n",
+                span.start(),
+                span.end(),
+                token.kind()
+            ));
+        }
+        return Ok(tokens.into_bytes());
+    }
     //parse
     let bump = Bump::new();
     let ast_arena = AstArena::new(&bump);
-    let program = parse(path_buf.to_str().unwrap(), &ast_arena, source.clone())?;
+    let file_name = path_buf.to_string_lossy().to_string();
+    let program = parse(&file_name, &ast_arena, source.clone())?;
+    if emit == Emit::Ast {
+        return to_ron(&program);
+    }
 
     //hir
     let hir_arena = HirArena::new();
-    let lower = AstSyntaxLoweringPass::new(&hir_arena, &program, &ast_arena, source.clone());
-    let hir = lower.lower()?;
+    let lower = AstSyntaxLoweringPass::new(
+        &hir_arena,
+        &program,
+        &ast_arena,
+        path_buf.clone(),
+        source.clone(),
+    );
+    let mut hir = lower.lower()?;
+    if emit == Emit::Hir {
+        return to_ron(&hir);
+    }
 
     //type-check
     let mut type_checker = TypeChecker::new(&hir_arena, source.clone());
-    type_checker.check(&hir)?;
+    type_checker.check(&mut hir)?;
+    if emit == Emit::TypedHir {
+        return to_ron(&hir);
+    }
 
     //codegen
     let bump = Bump::new();
     let arena = CodeGenArena::new(&bump);
     let mut codegen = CodeGenUnit::new(hir, arena, source);
-    let program = codegen.compile()?;
-    let output = ron::ser::to_string_pretty(&program, Default::default()).unwrap();
-    let mut file = std::fs::File::create("output.atlasc").unwrap();
-    file.write_all(output.as_bytes()).unwrap();
+    let mut program = codegen.compile()?;
+    if emit == Emit::Disasm {
+        return Ok(atlas_vm::disasm::disassemble(&program).into_bytes());
+    }
+    if !debug {
+        program.debug = None;
+    }
+    Ok(atlas_vm::bytecode::encode(&program))
+}
+
+fn to_ron<T: serde::Serialize>(value: &T) -> miette::Result<Vec<u8>> {
+    let ron = ron::ser::to_string_pretty(value, Default::default()).into_diagnostic()?;
+    Ok(ron.into_bytes())
+}
+
+fn execute(bytecode: &[u8]) -> miette::Result<()> {
+    let bump = Bump::new();
+    let program = atlas_vm::bytecode::decode(bytecode, &bump)?;
+    atlas_vm::bytecode::verify(&program)?;
+    atlas_vm::bytecode::check_libraries(&program, &[])?;
 
-    //run
     let start = Instant::now();
     let mut vm = atlas_vm::Atlas77VM::new(program);
-    let res = vm.run();
+    let value = vm.run().map_err(|e| vm.report(e))?;
     let end = Instant::now();
-    match res {
-        Ok(_) => {
-            println!(
-                "Program ran successfully: {} (time: {}ms)",
-                vm.stack.pop().unwrap(),
-                (end - start).as_millis()
-            );
-        }
-        Err(e) => {
-            eprintln!("{}", e);
+    println!(
+        "Program ran successfully: {} (time: {}ms)",
+        value,
+        (end - start).as_millis()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `source` to a directory of its own, the directory is returned along the file
+    fn write_source(name: &str, source: &str) -> (PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("atlas_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.atlas");
+        std::fs::write(&path, source).unwrap();
+        (dir, path.display().to_string())
+    }
+
+    #[test]
+    fn builds_then_executes_bytecode() {
+        let (dir, path) = write_source(
+            "exec",
+            r#"
+import "std/io"
+func main() -> i64 {
+    print_int(6 * 7);
+    return 42;
+}
+"#,
+        );
+        let output = dir.join("main.atlasc").display().to_string();
+        build(path.clone(), Some(output.clone()), Emit::Bytecode, false).unwrap();
+        let bytecode = std::fs::read(&output).unwrap();
+        assert!(bytecode.starts_with(&atlas_vm::bytecode::MAGIC));
+        exec(output.clone()).unwrap();
+
+        //Neither the path nor the source of the file end up in the bytecode, unless asked for
+        let contains =
+            |bytes: &[u8], text: &str| bytes.windows(text.len()).any(|w| w == text.as_bytes());
+        let dir_name = dir.file_name().unwrap().to_string_lossy().to_string();
+        assert!(!contains(&bytecode, &dir_name));
+        assert!(!contains(&bytecode, "6 * 7"));
+        let debug_output = dir.join("debug.atlasc").display().to_string();
+        build(path, Some(debug_output.clone()), Emit::Bytecode, true).unwrap();
+        let debug_bytecode = std::fs::read(&debug_output).unwrap();
+        assert!(contains(&debug_bytecode, &dir_name));
+        assert!(contains(&debug_bytecode, "6 * 7"));
+        exec(debug_output).unwrap();
+
+        //A file from another version of the format is rejected instead of run
+        let mut outdated = bytecode.clone();
+        let version = atlas_vm::bytecode::MAGIC.len();
+        outdated[version] = outdated[version].wrapping_add(1);
+        std::fs::write(&output, outdated).unwrap();
+        assert!(exec(output.clone()).is_err());
+        std::fs::write(&output, &bytecode[..bytecode.len() / 2]).unwrap();
+        assert!(exec(output).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn runs_functions_returning_from_every_branch() {
+        //The jumps over the `else` branches land right after the last `return` of `sign`
+        let (dir, path) = write_source(
+            "branches",
+            r#"
+func sign(n: i64) -> i64 {
+    if n < 0 {
+        return 0 - 1;
+    } else {
+        if n == 0 {
+            return 0;
+        } else {
+            return 1;
         }
     }
+}
+func main() -> i64 {
+    return sign(0 - 2) + sign(4);
+}
+"#,
+        );
+        run(path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 
-    Ok(())
+    #[test]
+    fn emits_every_stage() {
+        let (dir, path) = write_source(
+            "emit",
+            r#"
+func square(x: i64) -> i64 {
+    return x * x;
+}
+func main() -> i64 {
+    return square(7);
+}
+"#,
+        );
+        let emit = |emit: Emit| {
+            let output = dir.join(format!("{:?}", emit)).display().to_string();
+            build(path.clone(), Some(output.clone()), emit, false).unwrap();
+            (output.clone(), std::fs::read(output).unwrap())
+        };
+        let text = |kind: Emit| String::from_utf8(emit(kind).1).unwrap();
+
+        assert!(text(Emit::Tokens).lines().any(|l| l.ends_with("KwFunc")));
+        let (ast, hir, typed_hir) = (text(Emit::Ast), text(Emit::Hir), text(Emit::TypedHir));
+        for ron in [&ast, &hir, &typed_hir] {
+            ron::from_str::<ron::Value>(ron).unwrap();
+            assert!(ron.contains("square"));
+        }
+        assert_ne!(hir, typed_hir);
+
+        //The bytecode decodes to the program the listing shows, & runs
+        let (output, bytecode) = emit(Emit::Bytecode);
+        let bump = Bump::new();
+        let program = atlas_vm::bytecode::decode(&bytecode, &bump).unwrap();
+        assert_eq!(atlas_vm::disasm::disassemble(&program), text(Emit::Disasm));
+        exec(output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
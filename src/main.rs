@@ -1,5 +1,5 @@
-use atlas_77::{build, run};
-use clap::{command, Parser};
+use atlas_77::{build, disasm, exec, repl, run, Emit};
+use clap::Parser;
 
 #[derive(Parser)] // requires `derive` feature
 #[command(name = "Atlas77")]
@@ -14,21 +14,57 @@ enum AtlasRuntimeCLI {
     #[command(
         arg_required_else_help = true,
         about = "Compile then run a local package",
-        long_about = "Compile then run a local package. The output will be written to the current directory."
+        long_about = "Compile then run a local package. Nothing is written to disk, use `build` to keep the bytecode."
     )]
     Run { file_path: String },
     #[command(
         arg_required_else_help = true,
         about = "Compile a local package and all of its dependencies",
-        long_about = "Compile a local package and all of its dependencies. The output will be written to the current directory as `output.atlasc`."
+        long_about = "Compile a local package and all of its dependencies. By default the bytecode is written to the current directory as `output.atlasc`, `--emit` selects another stage of the compilation to output."
     )]
-    Build { file_path: String },
+    Build {
+        file_path: String,
+        /// Where to write the output, defaults to `output.atlasc` for bytecode & stdout otherwise
+        #[arg(short, long)]
+        output: Option<String>,
+        /// What to output
+        #[arg(long, value_enum, default_value_t)]
+        emit: Emit,
+        /// Keep the paths & the source of the files in the bytecode, so runtime errors point at the code
+        #[arg(long)]
+        debug: bool,
+    },
+    #[command(
+        arg_required_else_help = true,
+        about = "Run a compiled program",
+        long_about = "Run a bytecode file produced by `build`, without needing its source."
+    )]
+    Exec { file_path: String },
+    #[command(
+        arg_required_else_help = true,
+        about = "Print the bytecode of a program",
+        long_about = "Print a listing of the bytecode of a source file, or of a `.atlasc` file produced by `build`."
+    )]
+    Disasm { file_path: String },
+    #[command(
+        about = "Start an interactive prompt",
+        long_about = "Start an interactive prompt. Functions, imports & `let` bindings stay available from one entry to the next, `:quit` exits."
+    )]
+    Repl,
 }
 
 fn main() -> miette::Result<()> {
     //std::env::set_var("RUST_BACKTRACE", "1");
     match AtlasRuntimeCLI::parse() {
         AtlasRuntimeCLI::Run { file_path } => run(file_path),
-        AtlasRuntimeCLI::Build { file_path } => build(file_path),
+        AtlasRuntimeCLI::Build {
+            file_path,
+            output,
+            emit,
+            debug,
+        } => build(file_path, output, emit, debug),
+        AtlasRuntimeCLI::Exec { file_path } => exec(file_path),
+        AtlasRuntimeCLI::Disasm { file_path } => disasm(file_path),
+        AtlasRuntimeCLI::Repl => repl(),
     }
 }